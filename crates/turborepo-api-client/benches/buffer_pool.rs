@@ -0,0 +1,81 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use serde_json::{json, Value};
+use turborepo_api_client::BufferPool;
+
+/// Counts allocations, including reallocations, so the benchmark can report
+/// what pooling saves besides time
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// A task summary with 64 KiB of logs
+fn task_summary() -> Value {
+    json!({
+        "key": "web#build",
+        "name": "build",
+        "workspace": "web",
+        "hash": "0123456789abcdef",
+        "startTime": 1_700_000_000_000_i64,
+        "endTime": 1_700_000_060_000_i64,
+        "cache": { "status": "MISS", "source": "LOCAL", "timeSaved": 0 },
+        "exitCode": 0,
+        "dependencies": ["ui#build", "utils#build"],
+        "dependents": [],
+        "logs": "compiled successfully\n".repeat(64 * 1024 / 22),
+    })
+}
+
+fn allocations(encode: impl Fn()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    encode();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn bench_encode(c: &mut Criterion) {
+    let summary = task_summary();
+    let pool = BufferPool::default();
+    // Grows the pooled buffer, like the first upload of a run does
+    pool.encode_json(&summary).unwrap();
+
+    println!(
+        "allocations per summary: to_vec {}, pooled {}",
+        allocations(|| drop(black_box(serde_json::to_vec(&summary).unwrap()))),
+        allocations(|| drop(black_box(pool.encode_json(&summary).unwrap()))),
+    );
+
+    let mut group = c.benchmark_group("encode task summary");
+    group.bench_function("to_vec", |b| {
+        b.iter(|| serde_json::to_vec(black_box(&summary)).unwrap())
+    });
+    group.bench_function("pooled", |b| {
+        b.iter(|| pool.encode_json(black_box(&summary)).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_encode);
+criterion_main!(benches);
@@ -0,0 +1,122 @@
+use reqwest::{
+    header::{HeaderMap, CONTENT_LENGTH, CONTENT_TYPE},
+    Method, StatusCode,
+};
+
+use crate::{APIAuth, APIClient, Error, ARTIFACT_DIGEST_HEADER};
+
+/// What the server knows about an artifact, as returned by
+/// `APIClient::artifact_head`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ArtifactMeta {
+    /// The size of the artifact in bytes
+    pub size: Option<u64>,
+    pub content_type: Option<String>,
+    /// The signature tag, if the artifact was uploaded signed
+    pub tag: Option<String>,
+    /// The digest the download will be verified against
+    pub digest: Option<String>,
+    /// How long the task that produced the artifact took, in milliseconds
+    pub duration: Option<u64>,
+}
+
+impl ArtifactMeta {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+
+        Self {
+            size: header(CONTENT_LENGTH.as_str()).and_then(|size| size.parse().ok()),
+            content_type: header("x-artifact-content-type")
+                .or_else(|| header(CONTENT_TYPE.as_str())),
+            tag: header("x-artifact-tag"),
+            digest: header(ARTIFACT_DIGEST_HEADER),
+            duration: header("x-artifact-duration").and_then(|duration| duration.parse().ok()),
+        }
+    }
+}
+
+impl APIClient {
+    /// Reads an artifact's metadata without downloading it, e.g. to skip
+    /// downloading huge artifacts on constrained runners. Returns `None` if
+    /// the artifact doesn't exist. Other failures, including a 403 for a
+    /// disabled cache, are returned as errors.
+    pub async fn artifact_head(
+        &self,
+        hash: &str,
+        api_auth: &APIAuth,
+    ) -> Result<Option<ArtifactMeta>, Error> {
+        let result = self
+            .send_artifact_request(hash, api_auth, Method::HEAD, None)
+            .await;
+
+        match result {
+            Ok(response) => Ok(Some(ArtifactMeta::from_headers(response.headers()))),
+            Err(Error::ReqwestError(err)) if err.status() == Some(StatusCode::NOT_FOUND) => {
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use turborepo_vercel_api_mock::{start_test_server, EXPECTED_TOKEN};
+
+    use super::ArtifactMeta;
+    use crate::{testing::test_auth, APIClient, Client};
+
+    #[test]
+    fn test_artifact_meta_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-length", HeaderValue::from_static("1024"));
+        headers.insert(
+            "content-type",
+            HeaderValue::from_static("application/octet-stream"),
+        );
+        headers.insert("x-artifact-tag", HeaderValue::from_static("tag"));
+        headers.insert("digest", HeaderValue::from_static("sha-256=abc"));
+        headers.insert("x-artifact-duration", HeaderValue::from_static("42"));
+
+        assert_eq!(
+            ArtifactMeta::from_headers(&headers),
+            ArtifactMeta {
+                size: Some(1024),
+                content_type: Some("application/octet-stream".to_string()),
+                tag: Some("tag".to_string()),
+                digest: Some("sha-256=abc".to_string()),
+                duration: Some(42),
+            }
+        );
+        assert_eq!(
+            ArtifactMeta::from_headers(&HeaderMap::new()),
+            ArtifactMeta::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_artifact_head() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+        let client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0", false)?;
+        let api_auth = test_auth();
+
+        assert_eq!(client.artifact_head("missing", &api_auth).await?, None);
+
+        client
+            .put_artifact("hash", b"artifact", 42, None, None, EXPECTED_TOKEN)
+            .await?;
+        let meta = client.artifact_head("hash", &api_auth).await?.unwrap();
+        assert_eq!(meta.duration, Some(42));
+
+        handle.abort();
+        Ok(())
+    }
+}
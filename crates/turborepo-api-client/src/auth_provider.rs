@@ -0,0 +1,139 @@
+use async_trait::async_trait;
+use reqwest::{header::HeaderName, RequestBuilder};
+
+use crate::{signature, APIAuth, APIClient, AuthMode, Result};
+
+/// Attaches credentials to spaces and artifact requests, for authentication
+/// schemes the crate doesn't know about, e.g. short-lived OIDC tokens minted
+/// per request by a local agent, custom headers, or mTLS where nothing needs
+/// to be added. Used through `AuthMode::Custom`.
+///
+/// The provider is called once per request, after the rest of the request,
+/// including its body, is set. The exception are artifact uploads that report
+/// progress, whose streamed body is set afterwards. Retries reuse the
+/// authorized request. It isn't called if a preflight response disallows the
+/// `Authorization` header.
+#[async_trait]
+pub trait AuthProvider: Send + Sync {
+    async fn authorize(&self, request: RequestBuilder) -> Result<RequestBuilder>;
+}
+
+/// The default provider, which sends the token as an `Authorization: Bearer`
+/// header. Used for `AuthMode::Bearer`.
+pub struct BearerAuth {
+    token: String,
+}
+
+impl BearerAuth {
+    pub fn new(token: impl Into<String>) -> Self {
+        Self {
+            token: token.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for BearerAuth {
+    async fn authorize(&self, request: RequestBuilder) -> Result<RequestBuilder> {
+        Ok(request.header("Authorization", format!("Bearer {}", self.token)))
+    }
+}
+
+impl APIClient {
+    /// Adds the credentials of `api_auth` to `request_builder`, once its URL
+    /// is final. `body` is the body the request is sent with, which is signed
+    /// in `AuthMode::Hmac`. If `allow_auth` is false, i.e. a preflight
+    /// response disallowed the `Authorization` header, only signatures are
+    /// added.
+    pub(crate) async fn authorize(
+        &self,
+        request_builder: RequestBuilder,
+        api_auth: &APIAuth,
+        body: &[u8],
+        allow_auth: bool,
+    ) -> Result<RequestBuilder> {
+        match &api_auth.mode {
+            AuthMode::Bearer if allow_auth => {
+                BearerAuth::new(api_auth.token.as_str())
+                    .authorize(request_builder)
+                    .await
+            }
+            AuthMode::Hmac { key, algorithm } => Ok(signature::sign_request_builder(
+                request_builder,
+                key,
+                *algorithm,
+                body,
+            )?),
+            AuthMode::Custom(provider) if allow_auth => {
+                let unauthorized = header_names(&request_builder);
+                let request_builder = provider.authorize(request_builder).await?;
+                let mut auth_headers = self
+                    .auth_headers
+                    .lock()
+                    .expect("auth headers lock poisoned");
+                for header in header_names(&request_builder) {
+                    if !unauthorized.contains(&header) && !auth_headers.contains(&header) {
+                        auth_headers.push(header);
+                    }
+                }
+                Ok(request_builder)
+            }
+            AuthMode::Bearer | AuthMode::Custom(_) => Ok(request_builder),
+        }
+    }
+
+    /// The headers that `AuthProvider`s have added to requests so far
+    pub(crate) fn auth_headers(&self) -> Vec<HeaderName> {
+        self.auth_headers
+            .lock()
+            .expect("auth headers lock poisoned")
+            .clone()
+    }
+}
+
+/// The names of the headers `request_builder` would send
+fn header_names(request_builder: &RequestBuilder) -> Vec<HeaderName> {
+    request_builder
+        .try_clone()
+        .and_then(|request_builder| request_builder.build().ok())
+        .map(|request| request.headers().keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use reqwest::{Method, RequestBuilder};
+
+    use super::AuthProvider;
+    use crate::{testing::test_auth, APIAuth, APIClient, AuthMode, Result};
+
+    struct HeaderAuth;
+
+    #[async_trait]
+    impl AuthProvider for HeaderAuth {
+        async fn authorize(&self, request: RequestBuilder) -> Result<RequestBuilder> {
+            Ok(request.header("x-oidc-token", "minted"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_auth_provider() -> anyhow::Result<()> {
+        let client = APIClient::new("http://localhost", 0, "2.0.0", false)?;
+        let api_auth = APIAuth {
+            mode: AuthMode::Custom(Arc::new(HeaderAuth)),
+            ..test_auth()
+        };
+
+        let request = client
+            .create_request_builder("/v0/spaces", &api_auth, Method::GET, None)
+            .await?
+            .build()?;
+        assert_eq!(request.headers()["x-oidc-token"], "minted");
+        assert!(request.headers().get("authorization").is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+use reqwest::{Method, StatusCode};
+use serde::Deserialize;
+use turborepo_vercel_api::CachingStatus;
+
+use crate::{retry, APIAuth, APIClient, Client, Error};
+
+/// Whether remote caching can be used with the current team and token
+#[derive(Debug, Clone)]
+pub struct RemoteCacheAccess {
+    pub status: CachingStatus,
+    /// Whether the token is allowed to upload artifacts
+    pub can_write: bool,
+}
+
+impl RemoteCacheAccess {
+    /// Whether artifacts can be uploaded, i.e. caching is enabled and the
+    /// token has write access
+    pub fn can_upload(&self) -> bool {
+        matches!(self.status, CachingStatus::Enabled) && self.can_write
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheAccessResponse {
+    status: CachingStatus,
+    // Older servers don't report write access, in which case tokens can
+    // always write
+    can_write: Option<bool>,
+}
+
+impl APIClient {
+    /// Checks whether remote caching is enabled and writable before any
+    /// artifacts are uploaded, so that a run can fall back to local caching
+    /// instead of failing every upload. A 403 is reported as caching being
+    /// unavailable rather than as an error.
+    pub async fn get_remote_cache_access(
+        &self,
+        api_auth: &APIAuth,
+    ) -> Result<RemoteCacheAccess, Error> {
+        let request_builder = self
+            .create_request_builder("/v8/artifacts/status", api_auth, Method::GET, None)
+            .await?;
+
+        let response = retry::make_retryable_request(request_builder, self).await?;
+
+        if response.status() == StatusCode::FORBIDDEN {
+            let status = match Self::handle_403(response).await {
+                Error::CacheDisabled { status, .. } => status,
+                _ => CachingStatus::Disabled,
+            };
+            return Ok(RemoteCacheAccess {
+                status,
+                can_write: false,
+            });
+        }
+
+        let response: CacheAccessResponse = response.error_for_status()?.json().await?;
+        Ok(RemoteCacheAccess {
+            status: response.status,
+            can_write: response.can_write.unwrap_or(true),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+    use turborepo_vercel_api_mock::start_test_server;
+
+    use crate::{testing::test_auth, APIClient};
+
+    #[tokio::test]
+    async fn test_get_remote_cache_access() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+        let base_url = format!("http://localhost:{}", port);
+
+        let client = APIClient::new(&base_url, 200, "2.0.0", false)?;
+        let api_auth = test_auth();
+
+        let access = client.get_remote_cache_access(&api_auth).await?;
+        assert!(access.can_upload());
+
+        handle.abort();
+        Ok(())
+    }
+}
@@ -0,0 +1,113 @@
+use base64::{prelude::BASE64_STANDARD, Engine};
+use bytes::Bytes;
+use reqwest::{header::HeaderValue, Response};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::Error;
+
+/// The header the server sends an artifact's checksum in, formatted as
+/// `<algorithm>=<base64 digest>`, e.g. `sha-256=X48E9q...`. Several checksums
+/// can be sent separated by commas.
+pub const ARTIFACT_DIGEST_HEADER: &str = "digest";
+
+/// Reads an artifact download's body, verifying it against the checksum in
+/// the response's digest header so a truncated or corrupted transfer isn't
+/// restored into the cache. Artifacts from servers that don't send a
+/// checksum, or only send ones in algorithms other than SHA-256 and SHA-512,
+/// aren't verified.
+pub async fn read_verified_artifact(response: Response) -> Result<Bytes, Error> {
+    let mut verifier = ChecksumVerifier::new(response.headers().get(ARTIFACT_DIGEST_HEADER))?;
+    let body = response.bytes().await?;
+    verifier.update(&body);
+    verifier.verify()?;
+
+    Ok(body)
+}
+
+/// Verifies an artifact against its checksums as it's read, for artifacts
+/// that are streamed rather than read into memory
+pub(crate) struct ChecksumVerifier {
+    sha256: Option<(Sha256, String)>,
+    sha512: Option<(Sha512, String)>,
+}
+
+impl ChecksumVerifier {
+    pub(crate) fn new(digest: Option<&HeaderValue>) -> Result<Self, Error> {
+        let mut verifier = Self {
+            sha256: None,
+            sha512: None,
+        };
+        let Some(digest) = digest else {
+            return Ok(verifier);
+        };
+
+        for checksum in digest.to_str()?.split(',') {
+            let Some((algorithm, expected)) = checksum.trim().split_once('=') else {
+                continue;
+            };
+            let expected = expected.to_string();
+            match algorithm.to_ascii_lowercase().as_str() {
+                "sha-256" => verifier.sha256 = Some((Sha256::new(), expected)),
+                "sha-512" => verifier.sha512 = Some((Sha512::new(), expected)),
+                _ => {}
+            }
+        }
+
+        Ok(verifier)
+    }
+
+    pub(crate) fn update(&mut self, chunk: &[u8]) {
+        if let Some((hasher, _)) = &mut self.sha256 {
+            hasher.update(chunk);
+        }
+        if let Some((hasher, _)) = &mut self.sha512 {
+            hasher.update(chunk);
+        }
+    }
+
+    pub(crate) fn verify(self) -> Result<(), Error> {
+        let checksums = [
+            self.sha256
+                .map(|(hasher, expected)| (BASE64_STANDARD.encode(hasher.finalize()), expected)),
+            self.sha512
+                .map(|(hasher, expected)| (BASE64_STANDARD.encode(hasher.finalize()), expected)),
+        ];
+        for (actual, expected) in checksums.into_iter().flatten() {
+            if actual != expected {
+                return Err(Error::ChecksumMismatch { expected, actual });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn verify_checksum(digest: &HeaderValue, body: &[u8]) -> Result<(), Error> {
+    let mut verifier = ChecksumVerifier::new(Some(digest))?;
+    verifier.update(body);
+    verifier.verify()
+}
+
+#[cfg(test)]
+mod test {
+    use reqwest::header::HeaderValue;
+
+    use super::verify_checksum;
+    use crate::Error;
+
+    #[test]
+    fn test_verify_checksum() {
+        // sha-256 of "hello"
+        let digest =
+            HeaderValue::from_static("sha-256=LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=");
+        assert!(verify_checksum(&digest, b"hello").is_ok());
+        assert!(matches!(
+            verify_checksum(&digest, b"hell"),
+            Err(Error::ChecksumMismatch { .. })
+        ));
+
+        let unknown = HeaderValue::from_static("md5=XUFAKrxLKna5cZ2REBfFkg==");
+        assert!(verify_checksum(&unknown, b"anything").is_ok());
+    }
+}
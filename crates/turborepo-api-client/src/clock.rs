@@ -0,0 +1,163 @@
+use std::{sync::atomic::Ordering, time::Instant};
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde_json::Value;
+use turborepo_vercel_api::ServerTimeResponse;
+
+use crate::{retry, spaces::OPAQUE_FIELDS, APIClient, Client, Error};
+
+/// The payload timestamps corrected by `APIClient::correct_clock_skew`, in
+/// both the run and task summary casing
+const TIMESTAMP_KEYS: [&str; 6] = [
+    "startTime",
+    "endTime",
+    "expiresAt",
+    "start_time",
+    "end_time",
+    "expires_at",
+];
+
+impl APIClient {
+    /// Fetches the server's current time
+    pub async fn get_server_time(&self) -> Result<DateTime<Utc>, Error> {
+        let request_builder = self
+            .client
+            .get(self.make_url("/v0/time"))
+            .header("User-Agent", self.user_agent.clone());
+
+        let response: ServerTimeResponse = retry::make_retryable_request(request_builder, self)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Utc.timestamp_millis_opt(response.time)
+            .single()
+            .ok_or(Error::InvalidServerTime(response.time))
+    }
+
+    /// Measures how far the local clock is off from the server's, and from
+    /// then on shifts the start, end and expiry times of every run and task
+    /// payload sent by this client and its clones by that amount. This keeps
+    /// run timelines accurate on runners with a skewed clock. Payload
+    /// timestamps aren't corrected unless this is called.
+    ///
+    /// Returns the offset, positive if the local clock is behind.
+    pub async fn correct_clock_skew(&self) -> Result<Duration, Error> {
+        let sent_at = Utc::now();
+        let started = Instant::now();
+        let server_time = self.get_server_time().await?;
+        // Assume the server read its clock halfway through the request
+        let round_trip = Duration::from_std(started.elapsed()).unwrap_or_else(|_| Duration::zero());
+        let offset = server_time - (sent_at + round_trip / 2);
+
+        self.clock_offset
+            .store(offset.num_milliseconds(), Ordering::Relaxed);
+        Ok(offset)
+    }
+
+    /// Applies the offset measured by `correct_clock_skew` to a payload's
+    /// timestamps, including those of the tasks of batches and bundles
+    pub(crate) fn correct_timestamps(&self, payload: &mut Value) {
+        let offset = self.clock_offset.load(Ordering::Relaxed);
+        if offset != 0 {
+            shift_timestamps(payload, offset);
+        }
+    }
+}
+
+fn shift_timestamps(value: &mut Value, offset: i64) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                if TIMESTAMP_KEYS.contains(&key.as_str()) {
+                    if let Some(corrected) = value.as_i64().map(|t| t.saturating_add(offset)) {
+                        *value = corrected.into();
+                    }
+                } else if !OPAQUE_FIELDS.contains(&key.as_str()) {
+                    shift_timestamps(value, offset);
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                shift_timestamps(value, offset);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::Ordering;
+
+    use serde_json::json;
+
+    use crate::{
+        spaces::{SpaceTaskSummary, TASK_BATCHES_CAPABILITY},
+        testing::{set_capabilities, test_auth, Canned, CannedServer},
+        APIClient,
+    };
+
+    #[test]
+    fn test_correct_timestamps() -> anyhow::Result<()> {
+        let client = APIClient::new("http://localhost", 0, "2.0.0", false)?;
+        let mut payload = json!({ "startTime": 1_000, "end_time": 2_000, "exitCode": 0 });
+
+        client.correct_timestamps(&mut payload);
+        assert_eq!(payload["startTime"], 1_000);
+
+        client.clock_offset.store(500, Ordering::Relaxed);
+        client.correct_timestamps(&mut payload);
+        assert_eq!(
+            payload,
+            json!({ "startTime": 1_500, "end_time": 2_500, "exitCode": 0 })
+        );
+
+        // Nested timestamps are corrected too, but not caller provided ones
+        let mut payload = json!({
+            "expiresAt": 3_000,
+            "tasks": [{ "start_time": 1_000, "metadata": { "start_time": 1_000 } }],
+        });
+        client.correct_timestamps(&mut payload);
+        assert_eq!(
+            payload,
+            json!({
+                "expiresAt": 3_500,
+                "tasks": [{ "start_time": 1_500, "metadata": { "start_time": 1_000 } }],
+            })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_corrects_sent_tasks() -> anyhow::Result<()> {
+        let server = CannedServer::always(Canned::ok()).await;
+        let client = server.client();
+        set_capabilities(&client, &[TASK_BATCHES_CAPABILITY]);
+        client.clock_offset.store(500, Ordering::Relaxed);
+
+        let task = SpaceTaskSummary {
+            key: "web#build".to_string(),
+            start_time: 1_000,
+            end_time: 2_000,
+            ..SpaceTaskSummary::default()
+        };
+        client
+            .upload_task_batch(&"space".into(), &"run".into(), &test_auth(), vec![task])
+            .await?;
+
+        let requests = server.requests();
+        let batch = requests
+            .iter()
+            .find(|request| request.method == "POST")
+            .expect("batch was sent");
+        let batch: serde_json::Value = serde_json::from_slice(&batch.body)?;
+        assert_eq!(batch.pointer("/0/start_time"), Some(&json!(1_500)));
+        assert_eq!(batch.pointer("/0/end_time"), Some(&json!(2_500)));
+
+        Ok(())
+    }
+}
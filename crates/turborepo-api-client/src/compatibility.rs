@@ -0,0 +1,121 @@
+use reqwest::{Method, StatusCode};
+use turborepo_vercel_api::CapabilitiesResponse;
+
+use crate::{retry, APIAuth, APIClient, Error};
+
+/// The API version this client was built for
+pub const CLIENT_API_VERSION: u32 = 1;
+
+/// Whether this client and the server speak compatible API versions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compatibility {
+    Compatible,
+    /// The server no longer supports this client's API version, `turbo`
+    /// needs to be upgraded
+    ClientOutdated {
+        server_version: u32,
+    },
+    /// The server is older than this client's API version, so newer
+    /// features may fail
+    ServerOutdated {
+        server_version: u32,
+    },
+}
+
+impl Compatibility {
+    fn from_capabilities(capabilities: &CapabilitiesResponse) -> Self {
+        let server_version = capabilities.api_version;
+        if capabilities.min_client_api_version > CLIENT_API_VERSION {
+            Compatibility::ClientOutdated { server_version }
+        } else if server_version < CLIENT_API_VERSION {
+            Compatibility::ServerOutdated { server_version }
+        } else {
+            Compatibility::Compatible
+        }
+    }
+}
+
+impl APIClient {
+    /// Compares the server's advertised API version with the one this
+    /// client was built for. Servers that don't advertise a version are
+    /// assumed to be compatible.
+    pub async fn check_compatibility(&self, api_auth: &APIAuth) -> Result<Compatibility, Error> {
+        Ok(match self.get_capabilities(api_auth).await? {
+            Some(capabilities) => Compatibility::from_capabilities(&capabilities),
+            None => Compatibility::Compatible,
+        })
+    }
+
+    /// Returns whether the server advertises an optional feature. Servers
+    /// whose capabilities can't be read are assumed not to have it.
+    pub(crate) async fn has_capability(&self, api_auth: &APIAuth, capability: &str) -> bool {
+        matches!(
+            self.get_capabilities(api_auth).await,
+            Ok(Some(capabilities)) if capabilities.capabilities.iter().any(|c| c == capability)
+        )
+    }
+
+    /// Fetches the server's API version and optional features, or `None`
+    /// if the server doesn't have the endpoint. The response is cached
+    /// across all clones of this client.
+    async fn get_capabilities(
+        &self,
+        api_auth: &APIAuth,
+    ) -> Result<Option<CapabilitiesResponse>, Error> {
+        let capabilities = self
+            .capabilities
+            .get_or_try_init(|| async {
+                let request_builder = self
+                    .create_request_builder("/v0/capabilities", api_auth, Method::GET, None)
+                    .await?;
+
+                let response = retry::make_retryable_request(request_builder, self).await?;
+                if response.status() == StatusCode::NOT_FOUND {
+                    return Ok::<_, Error>(None);
+                }
+
+                Ok(Some(response.error_for_status()?.json().await?))
+            })
+            .await?;
+
+        Ok(capabilities.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use turborepo_vercel_api::CapabilitiesResponse;
+
+    use super::{Compatibility, CLIENT_API_VERSION};
+
+    fn capabilities(api_version: u32, min_client_api_version: u32) -> CapabilitiesResponse {
+        CapabilitiesResponse {
+            api_version,
+            min_client_api_version,
+            capabilities: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_compatibility() {
+        assert_eq!(
+            Compatibility::from_capabilities(&capabilities(CLIENT_API_VERSION, 0)),
+            Compatibility::Compatible
+        );
+        assert_eq!(
+            Compatibility::from_capabilities(&capabilities(
+                CLIENT_API_VERSION + 1,
+                CLIENT_API_VERSION + 1
+            )),
+            Compatibility::ClientOutdated {
+                server_version: CLIENT_API_VERSION + 1
+            }
+        );
+        assert_eq!(
+            Compatibility::from_capabilities(&capabilities(CLIENT_API_VERSION - 1, 0)),
+            Compatibility::ServerOutdated {
+                server_version: CLIENT_API_VERSION - 1
+            }
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+
+/// How many requests were sent over a newly opened connection versus one
+/// reused from the connection pool.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionStats {
+    pub new_connections: u64,
+    pub reused_connections: u64,
+}
+
+/// Counts requests and new connections. New connections are counted by
+/// wrapping DNS resolution, since the connection pool only resolves a host
+/// when it has to open a connection. Requests to IP address literals skip
+/// resolution, so they always count as reused.
+#[derive(Default)]
+pub(crate) struct ConnectionCounter {
+    requests: AtomicU64,
+    connections: AtomicU64,
+}
+
+impl ConnectionCounter {
+    pub(crate) fn record_request(&self) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stats(&self) -> ConnectionStats {
+        let requests = self.requests.load(Ordering::Relaxed);
+        let new_connections = self.connections.load(Ordering::Relaxed);
+
+        ConnectionStats {
+            new_connections,
+            reused_connections: requests.saturating_sub(new_connections),
+        }
+    }
+}
+
+/// Resolves hosts with the system resolver, unless the host has a static
+/// override. Overrides are handled here rather than with reqwest's `resolve`
+/// so that connections to overridden hosts are still counted.
+pub(crate) struct CountingResolver {
+    counter: Arc<ConnectionCounter>,
+    host_overrides: HashMap<String, IpAddr>,
+}
+
+impl CountingResolver {
+    pub(crate) fn new(
+        counter: Arc<ConnectionCounter>,
+        host_overrides: HashMap<String, IpAddr>,
+    ) -> Self {
+        Self {
+            counter,
+            host_overrides,
+        }
+    }
+}
+
+impl Resolve for CountingResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        self.counter.connections.fetch_add(1, Ordering::Relaxed);
+        // The TLS server name still comes from the URL, so certificates are
+        // validated against the original host
+        if let Some(ip) = self.host_overrides.get(name.as_str()) {
+            let addrs = vec![SocketAddr::new(*ip, 0)];
+            return Box::pin(async move { Ok(Box::new(addrs.into_iter()) as Addrs) });
+        }
+        Box::pin(async move {
+            // The port is filled in by the connector
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
@@ -0,0 +1,123 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use reqwest::{header::RETRY_AFTER, Response, StatusCode};
+use tokio::time::sleep;
+
+// Upper bound on how long a single Retry-After can pause the client
+const MAX_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// A client-wide pause after the server throttles a request. Once one request
+/// gets a 429, every request holds off until the server's Retry-After has
+/// passed, instead of only the throttled one backing off.
+#[derive(Default)]
+pub(crate) struct Cooldown {
+    until: Mutex<Option<Instant>>,
+}
+
+impl Cooldown {
+    /// Waits until the cooldown, if any, has passed.
+    pub(crate) async fn wait(&self) {
+        let until = *self.until.lock().expect("cooldown lock poisoned");
+        if let Some(until) = until {
+            let now = Instant::now();
+            if until > now {
+                sleep(until - now).await;
+            }
+        }
+    }
+
+    /// Starts a cooldown if the response is a 429 with a Retry-After header.
+    /// An existing cooldown is only ever extended.
+    pub(crate) fn observe(&self, response: &Response) {
+        if response.status() != StatusCode::TOO_MANY_REQUESTS {
+            return;
+        }
+
+        let Some(delay) = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| parse_retry_after(value, Utc::now()))
+        else {
+            return;
+        };
+
+        let until = Instant::now() + delay.min(MAX_COOLDOWN);
+        let mut current = self.until.lock().expect("cooldown lock poisoned");
+        if current.map_or(true, |current| current < until) {
+            *current = Some(until);
+        }
+    }
+}
+
+/// Parses a Retry-After value, which is either a number of seconds or an HTTP
+/// date.
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    (date.with_timezone(&Utc) - now).to_std().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::{Duration, Instant},
+    };
+
+    use chrono::{TimeZone, Utc};
+
+    use super::parse_retry_after;
+    use crate::testing::{test_auth, Canned, CannedServer};
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = Utc.with_ymd_and_hms(2023, 10, 21, 7, 28, 0).unwrap();
+
+        assert_eq!(parse_retry_after("5", now), Some(Duration::from_secs(5)));
+        assert_eq!(
+            parse_retry_after("Sat, 21 Oct 2023 07:28:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        // Dates in the past don't need a cooldown
+        assert_eq!(
+            parse_retry_after("Sat, 21 Oct 2023 07:27:00 GMT", now),
+            None
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[tokio::test]
+    async fn test_cooldown_delays_other_requests() -> anyhow::Result<()> {
+        // Only the first request is throttled
+        let requests = AtomicUsize::new(0);
+        let server = CannedServer::start(move |_| {
+            if requests.fetch_add(1, Ordering::SeqCst) == 0 {
+                Canned::status(429).header("retry-after", "1")
+            } else {
+                Canned::json(r#"{"artifactBytesUsed":0,"runsThisPeriod":0}"#)
+            }
+        })
+        .await;
+        let client = server.client();
+        let api_auth = test_auth();
+
+        assert!(client.get_team_usage(&api_auth).await.is_err());
+        // A different request, which wasn't throttled itself, waits out the
+        // Retry-After too
+        let started = Instant::now();
+        let other = client.clone();
+        tokio::spawn(async move { other.get_team_usage(&api_auth).await }).await??;
+        assert!(started.elapsed() >= Duration::from_millis(900));
+        assert_eq!(server.requests().len(), 2);
+
+        Ok(())
+    }
+}
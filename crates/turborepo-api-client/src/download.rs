@@ -0,0 +1,113 @@
+use futures::{stream, StreamExt};
+use reqwest::Method;
+use tokio::{fs::File, io::AsyncWriteExt};
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf};
+
+use crate::{
+    checksum::{ChecksumVerifier, ARTIFACT_DIGEST_HEADER},
+    APIAuth, APIClient, Error,
+};
+
+impl APIClient {
+    /// Downloads several artifacts concurrently into `dir`, at most
+    /// `concurrency` at a time, e.g. to restore the cache of a large
+    /// monorepo. Each artifact is streamed to `<hash>.tar.zst` and verified
+    /// against its checksum, so it's never held in memory in full. A failed
+    /// download doesn't stop the others, and leaves no file behind.
+    ///
+    /// Results are returned in the same order as `hashes`.
+    pub async fn fetch_artifacts(
+        &self,
+        hashes: &[String],
+        api_auth: &APIAuth,
+        concurrency: usize,
+        dir: &AbsoluteSystemPath,
+    ) -> Vec<Result<AbsoluteSystemPathBuf, Error>> {
+        stream::iter(hashes)
+            .map(|hash| self.fetch_artifact_to(hash, api_auth, dir))
+            .buffered(concurrency.max(1))
+            .collect()
+            .await
+    }
+
+    async fn fetch_artifact_to(
+        &self,
+        hash: &str,
+        api_auth: &APIAuth,
+        dir: &AbsoluteSystemPath,
+    ) -> Result<AbsoluteSystemPathBuf, Error> {
+        let path = dir.join_component(&format!("{}.tar.zst", hash));
+        // Written next to the artifact and moved into place once verified, so
+        // a partial download is never mistaken for the artifact
+        let partial_path = dir.join_component(&format!("{}.tar.zst.partial", hash));
+
+        let result = self
+            .stream_artifact(hash, api_auth, &partial_path)
+            .await
+            .and_then(|()| partial_path.rename(&path).map_err(Error::FileError));
+        if result.is_err() {
+            let _ = partial_path.remove_file();
+        }
+
+        result.map(|()| path)
+    }
+
+    async fn stream_artifact(
+        &self,
+        hash: &str,
+        api_auth: &APIAuth,
+        path: &AbsoluteSystemPath,
+    ) -> Result<(), Error> {
+        let response = self
+            .send_artifact_request(hash, api_auth, Method::GET, None)
+            .await?;
+
+        let mut verifier = ChecksumVerifier::new(response.headers().get(ARTIFACT_DIGEST_HEADER))?;
+        let mut file = File::create(path.as_std_path())
+            .await
+            .map_err(Error::FileError)?;
+        let mut body = response.bytes_stream();
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk?;
+            verifier.update(&chunk);
+            file.write_all(&chunk).await.map_err(Error::FileError)?;
+        }
+        file.flush().await.map_err(Error::FileError)?;
+
+        verifier.verify()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+    use tempfile::tempdir;
+    use turbopath::AbsoluteSystemPathBuf;
+    use turborepo_vercel_api_mock::{start_test_server, EXPECTED_TOKEN};
+
+    use crate::{testing::test_auth, APIClient, Client};
+
+    #[tokio::test]
+    async fn test_fetch_artifacts() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+        let client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0", false)?;
+        let api_auth = test_auth();
+
+        client
+            .put_artifact("downloaded", b"artifact", 10, None, None, EXPECTED_TOKEN)
+            .await?;
+
+        let dir = tempdir()?;
+        let dir = AbsoluteSystemPathBuf::try_from(dir.path())?;
+        let results = client
+            .fetch_artifacts(&["downloaded".to_string()], &api_auth, 4, &dir)
+            .await;
+
+        let path = results.into_iter().next().unwrap()?;
+        assert_eq!(std::fs::read(path.as_std_path())?, b"artifact");
+
+        handle.abort();
+        Ok(())
+    }
+}
@@ -3,7 +3,7 @@ use std::backtrace::Backtrace;
 use reqwest::header::ToStrError;
 use thiserror::Error;
 
-use crate::CachingStatus;
+use crate::{CachingStatus, Region};
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -15,8 +15,28 @@ pub enum Error {
     TlsError(reqwest::Error),
     #[error("Error parsing header: {0}")]
     InvalidHeader(#[from] ToStrError),
+    #[error("Error serializing request body: {0}")]
+    SerializationError(#[from] serde_json::Error),
+    #[cfg(feature = "msgpack")]
+    #[error("Error serializing request body: {0}")]
+    MessagePackError(#[from] rmp_serde::encode::Error),
+    #[error("Error connecting to the API host: {0}")]
+    ConnectionError(std::io::Error),
+    #[error("Error writing to disk: {0}")]
+    FileError(std::io::Error),
     #[error("Error parsing URL: {0}")]
     InvalidUrl(#[from] url::ParseError),
+    #[error(
+        "downloaded artifact doesn't match its checksum, expected {expected} but got {actual}. \
+         The download may have been truncated or corrupted"
+    )]
+    ChecksumMismatch { expected: String, actual: String },
+    #[error("the server returned an invalid time: {0}")]
+    InvalidServerTime(i64),
+    #[error("unknown region {0}, expected one of: us, eu")]
+    UnknownRegion(String),
+    #[error("no API endpoint is configured for region {0}")]
+    UnconfiguredRegion(Region),
     #[error("unknown caching status: {0}")]
     UnknownCachingStatus(String, #[backtrace] Backtrace),
     #[error("unknown status {code}: {message}")]
@@ -26,6 +46,45 @@ pub enum Error {
         #[backtrace]
         backtrace: Backtrace,
     },
+    #[error(
+        "space {space_id} was not found. It may have been deleted, run `turbo link` to link this \
+         repository to a space"
+    )]
+    SpaceNotFound { space_id: String },
+    #[error("team {slug} was not found. Check that the team slug is correct and you are a member")]
+    TeamNotFound { slug: String },
+    #[error("task {task_key} was not found in the run")]
+    TaskNotFound { task_key: String },
+    #[error(
+        "task {key} was already uploaded to the run. Only one summary per task key is kept, so \
+         this is likely a bug in how task keys are generated"
+    )]
+    DuplicateTaskKey { key: String },
+    #[error("task {key} started at {task_start}, before its run started at {run_start}")]
+    TaskStartedBeforeRun {
+        key: String,
+        task_start: i64,
+        run_start: i64,
+    },
+    #[error(
+        "the request to {url} timed out after it was sent, so the server may have processed it. \
+         It wasn't retried to avoid a duplicate write"
+    )]
+    AmbiguousWrite {
+        url: String,
+        #[source]
+        source: Box<reqwest::Error>,
+    },
+    #[error("the network appears to be down, a recent request couldn't connect")]
+    Offline,
+    #[error("turbo config hash {hash} isn't a hex encoded 64-bit hash")]
+    InvalidConfigHash { hash: String },
+    #[error("the upload was cancelled because the uploads of run {run_id} were cancelled")]
+    UploadCancelled { run_id: String },
+    #[error("the task summary stream stopped unexpectedly")]
+    SummaryStreamClosed,
+    #[error("task summary metadata is {size} bytes, which is over the limit of {limit} bytes")]
+    MetadataTooLarge { size: usize, limit: usize },
     #[error("{message}")]
     CacheDisabled {
         status: CachingStatus,
@@ -0,0 +1,150 @@
+use reqwest::{Request, Response};
+use url::Url;
+
+use crate::{redirect, timing::BodySent, APIClient};
+
+/// Executes `request`, failing over to the client's fallback hosts in order
+/// if the connection to a host can't be established. Only connect errors
+/// fail over: once a host has received the request, even if it responded with
+/// a 5xx, it may have acted on it, and sending it to another host could
+/// duplicate a write.
+///
+/// Requests that aren't for the client's base URL, e.g. ones redirected by a
+/// preflight, or that can't be cloned, don't fail over.
+pub(crate) async fn execute(
+    client: &APIClient,
+    http_client: &reqwest::Client,
+    request: Request,
+    body_sent: &BodySent,
+) -> reqwest::Result<Response> {
+    let original = (!client.fallback_urls.is_empty())
+        .then(|| request.try_clone())
+        .flatten();
+    let mut result = send(client, http_client, request, body_sent).await;
+
+    let Some(original) = original else {
+        return result;
+    };
+    for fallback_url in &client.fallback_urls {
+        if !matches!(&result, Err(err) if err.is_connect()) {
+            break;
+        }
+        let Some(url) = rebase(original.url(), &client.base_url, fallback_url) else {
+            break;
+        };
+        let Some(mut request) = original.try_clone() else {
+            break;
+        };
+        *request.url_mut() = url;
+        result = send(client, http_client, request, body_sent).await;
+    }
+
+    result
+}
+
+// Every request of the client is sent from here, so this is where requests
+// are recorded.
+async fn send(
+    client: &APIClient,
+    http_client: &reqwest::Client,
+    request: Request,
+    body_sent: &BodySent,
+) -> reqwest::Result<Response> {
+    #[cfg(feature = "test-util")]
+    if let Some(recorder) = &client.request_recorder {
+        recorder.record(&request);
+    }
+    let auth_headers = client.auth_headers();
+    redirect::execute(
+        http_client,
+        request,
+        &client.redirect_policy,
+        &auth_headers,
+        body_sent,
+    )
+    .await
+}
+
+/// Moves `url` from `base_url` to `fallback_url`, keeping the endpoint and
+/// the query. URLs are compared once parsed, so e.g. a base URL with an
+/// uppercase host or an explicit default port still matches.
+fn rebase(url: &Url, base_url: &str, fallback_url: &str) -> Option<Url> {
+    let base_url = Url::parse(base_url).ok()?;
+    if url.origin() != base_url.origin() {
+        return None;
+    }
+
+    let mut segments = url.path_segments()?;
+    for base_segment in base_url.path_segments()?.filter(|s| !s.is_empty()) {
+        if segments.next()? != base_segment {
+            return None;
+        }
+    }
+    // The segments are still percent encoded, so they're joined rather than
+    // pushed with `path_segments_mut`, which would encode them again
+    let endpoint = segments.collect::<Vec<_>>().join("/");
+
+    let mut rebased = Url::parse(fallback_url).ok()?;
+    let path = format!("{}/{}", rebased.path().trim_end_matches('/'), endpoint);
+    rebased.set_path(&path);
+    rebased.set_query(url.query());
+    Some(rebased)
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::TcpListener;
+
+    use turborepo_vercel_api_mock::{start_test_server, EXPECTED_TOKEN, EXPECTED_USER_ID};
+    use url::Url;
+
+    use super::rebase;
+    use crate::{APIClient, Client};
+
+    #[test]
+    fn test_rebase() {
+        let url = Url::parse("https://api.example.com/api/v2/user?teamId=team_1").unwrap();
+        let rebased = |base_url| rebase(&url, base_url, "https://fallback.example.com/proxy/");
+
+        // Base URLs that are written differently but parse to the same one
+        for base_url in [
+            "https://api.example.com/api",
+            "https://api.example.com/api/",
+            "https://API.example.com/api",
+            "https://api.example.com:443/api",
+        ] {
+            assert_eq!(
+                rebased(base_url).map(String::from).as_deref(),
+                Some("https://fallback.example.com/proxy/v2/user?teamId=team_1"),
+                "{base_url}"
+            );
+        }
+
+        // The URL isn't under the base URL
+        assert_eq!(rebased("http://api.example.com/api"), None);
+        assert_eq!(rebased("https://api.example.com:8443/api"), None);
+        assert_eq!(rebased("https://api.example.com/ap"), None);
+        assert_eq!(rebased("https://api.example.com/api/v3"), None);
+    }
+
+    #[tokio::test]
+    async fn test_fails_over_on_connect_error() -> anyhow::Result<()> {
+        // Nothing listens on the primary's port once the listener is dropped
+        let primary_port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+
+        let client = APIClient::new(
+            format!("http://127.0.0.1:{}", primary_port),
+            200,
+            "2.0.0",
+            false,
+        )?
+        .with_fallback_hosts(vec![format!("http://localhost:{}", port)]);
+        let response = client.get_user(EXPECTED_TOKEN).await?;
+        assert_eq!(response.user.id, EXPECTED_USER_ID);
+
+        handle.abort();
+        Ok(())
+    }
+}
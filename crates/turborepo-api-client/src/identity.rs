@@ -0,0 +1,172 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use reqwest::StatusCode;
+use turborepo_vercel_api::User;
+
+use crate::{APIAuth, APIClient, Client, Error};
+
+/// How long a resolved identity is reused by default
+pub const DEFAULT_IDENTITY_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Who a token belongs to, as resolved by `APIClient::resolve_identity`
+#[derive(Debug, Clone)]
+pub struct Identity {
+    pub user: User,
+    /// The team the auth is for, if it has a team id or slug
+    pub team_id: Option<String>,
+    /// The slugs of the teams the token can access
+    pub scopes: Vec<String>,
+}
+
+// A token and the team slug it was resolved for
+type IdentityKey = (String, Option<String>);
+
+/// Identities resolved for each token and team slug, shared by an
+/// `APIClient` and its clones
+#[derive(Default)]
+pub(crate) struct IdentityCache {
+    entries: Mutex<HashMap<IdentityKey, (Instant, Identity)>>,
+}
+
+impl IdentityCache {
+    fn get(&self, key: &IdentityKey, ttl: Duration) -> Option<Identity> {
+        let entries = self.entries.lock().expect("identity cache lock poisoned");
+        let (resolved_at, identity) = entries.get(key)?;
+        (resolved_at.elapsed() < ttl).then(|| identity.clone())
+    }
+
+    fn insert(&self, key: IdentityKey, identity: Identity) {
+        self.entries
+            .lock()
+            .expect("identity cache lock poisoned")
+            .insert(key, (Instant::now(), identity));
+    }
+
+    fn invalidate(&self, token: &str) {
+        self.entries
+            .lock()
+            .expect("identity cache lock poisoned")
+            .retain(|(cached_token, _), _| cached_token != token);
+    }
+}
+
+impl APIClient {
+    /// Resolves the user, team id and accessible teams of `api_auth`. The
+    /// result is cached for the client's identity TTL, so this is cheap to
+    /// call for every run in a long-lived process. A 401 while resolving
+    /// drops the token's cached identities.
+    pub async fn resolve_identity(&self, api_auth: &APIAuth) -> Result<Identity, Error> {
+        let key = (api_auth.token.clone(), api_auth.team_slug.clone());
+        if let Some(identity) = self.identity_cache.get(&key, self.identity_ttl) {
+            return Ok(identity);
+        }
+
+        let result = self.fetch_identity(api_auth).await;
+        match &result {
+            Ok(identity) if !self.identity_ttl.is_zero() => {
+                self.identity_cache.insert(key, identity.clone());
+            }
+            Err(Error::ReqwestError(err)) if err.status() == Some(StatusCode::UNAUTHORIZED) => {
+                self.invalidate_identity(&api_auth.token);
+            }
+            _ => {}
+        }
+
+        result
+    }
+
+    /// Drops the cached identities of `token`, e.g. after a request made
+    /// with it was rejected with a 401
+    pub fn invalidate_identity(&self, token: &str) {
+        self.identity_cache.invalidate(token);
+    }
+
+    async fn fetch_identity(&self, api_auth: &APIAuth) -> Result<Identity, Error> {
+        let user = self.get_user(&api_auth.token).await?.user;
+        let team_id = match self.resolve_team(api_auth).await?.team_id {
+            team_id if team_id.is_empty() => None,
+            team_id => Some(team_id),
+        };
+        let scopes = self
+            .get_teams(&api_auth.token)
+            .await?
+            .teams
+            .into_iter()
+            .map(|team| team.slug)
+            .collect();
+
+        Ok(Identity {
+            user,
+            team_id,
+            scopes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use anyhow::Result;
+    use turborepo_vercel_api_mock::{
+        start_test_server, EXPECTED_TEAM_ID, EXPECTED_TEAM_SLUG, EXPECTED_TOKEN, EXPECTED_USER_ID,
+    };
+
+    use crate::{testing::test_auth, APIAuth, APIClient};
+
+    #[tokio::test]
+    async fn test_identity_cache() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+        let requests = Arc::new(AtomicUsize::new(0));
+        let client = |ttl| {
+            let requests = requests.clone();
+            APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0", false)
+                .unwrap()
+                .with_identity_ttl(ttl)
+                .with_request_observer(move |_| {
+                    requests.fetch_add(1, Ordering::Relaxed);
+                })
+        };
+        let api_auth = APIAuth {
+            team_id: String::new(),
+            team_slug: Some(EXPECTED_TEAM_SLUG.to_string()),
+            ..test_auth()
+        };
+
+        let caching = client(Duration::from_secs(60));
+        let identity = caching.resolve_identity(&api_auth).await?;
+        assert_eq!(identity.user.id, EXPECTED_USER_ID);
+        assert_eq!(identity.team_id.as_deref(), Some(EXPECTED_TEAM_ID));
+        assert_eq!(identity.scopes, [EXPECTED_TEAM_SLUG]);
+        let resolved = requests.swap(0, Ordering::Relaxed);
+
+        let cached = caching.resolve_identity(&api_auth).await?;
+        assert_eq!(cached.user.id, identity.user.id);
+        assert_eq!(requests.swap(0, Ordering::Relaxed), 0);
+
+        // The team id is cached separately, so only the user and teams are
+        // fetched again
+        caching.invalidate_identity(EXPECTED_TOKEN);
+        caching.resolve_identity(&api_auth).await?;
+        assert_eq!(requests.swap(0, Ordering::Relaxed), resolved - 1);
+
+        let uncached = client(Duration::ZERO);
+        uncached.resolve_identity(&api_auth).await?;
+        uncached.resolve_identity(&api_auth).await?;
+        assert_eq!(requests.swap(0, Ordering::Relaxed), 2 * resolved - 1);
+
+        handle.abort();
+        Ok(())
+    }
+}
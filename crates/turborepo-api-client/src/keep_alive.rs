@@ -0,0 +1,112 @@
+use std::sync::atomic::Ordering;
+
+use tokio::time::{interval_at, Instant, MissedTickBehavior};
+
+use crate::{retry, APIClient};
+
+impl APIClient {
+    /// Starts pinging the base URL every keep-alive interval in the
+    /// background, so that a pooled connection stays warm between the runs of
+    /// a long-lived process, e.g. `turbo watch`. Does nothing if no interval
+    /// is set, if it's already started, or once `shutdown` was called.
+    ///
+    /// The pings stop when `shutdown` is called. They keep a clone of the
+    /// client alive until then.
+    pub fn start_keep_alive(&self) {
+        let Some(period) = self.keep_alive_interval else {
+            return;
+        };
+        let mut closing = self.open_work.closing();
+        if *closing.borrow() || self.keep_alive_started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let client = self.clone();
+        tokio::spawn(async move {
+            let mut ticks = interval_at(Instant::now() + period, period);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = closing.changed() => break,
+                    _ = ticks.tick() => client.ping().await,
+                }
+            }
+        });
+    }
+
+    // Any response keeps the connection in the pool, so failures are ignored.
+    // Pings are sent like other requests, e.g. within the in-flight cap and
+    // following redirects, so the connection to a moved API is kept warm.
+    async fn ping(&self) {
+        retry::wait_to_send(self).await;
+        let Ok(request) = self
+            .client
+            .head(&self.base_url)
+            .header("User-Agent", self.user_agent.clone())
+            .build()
+        else {
+            return;
+        };
+        let _ =
+            retry::send_attempt(self, &self.client, request, 0, std::time::Instant::now()).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::testing::{Canned, CannedServer};
+
+    #[tokio::test]
+    async fn test_keep_alive() -> anyhow::Result<()> {
+        let server = CannedServer::always(Canned::ok()).await;
+        let pings = || server.requests().len();
+
+        let client = server.client().with_keep_alive(Duration::from_millis(20));
+        client.start_keep_alive();
+        // Starting it twice doesn't double the pings
+        client.start_keep_alive();
+        tokio::time::sleep(Duration::from_millis(110)).await;
+        let pinged = pings();
+        assert!((2..=6).contains(&pinged), "pinged {} times", pinged);
+        // The pings reuse one connection
+        assert_eq!(server.connections(), 1);
+
+        client.shutdown(Duration::from_secs(1)).await;
+        // A ping that was already sent may still arrive
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let pinged = pings();
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(pings(), pinged);
+
+        // Off by default
+        let client = server.client();
+        client.start_keep_alive();
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(pings(), pinged);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive_follows_redirects() -> anyhow::Result<()> {
+        let server = CannedServer::start(|request| match request.path.as_str() {
+            "/moved" => Canned::ok(),
+            _ => Canned::status(308).header("location", "/moved"),
+        })
+        .await;
+
+        let client = server.client().with_keep_alive(Duration::from_millis(20));
+        client.start_keep_alive();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        client.shutdown(Duration::from_secs(1)).await;
+
+        let requests = server.requests();
+        assert!(requests
+            .iter()
+            .any(|request| request.method == "HEAD" && request.path == "/moved"));
+
+        Ok(())
+    }
+}
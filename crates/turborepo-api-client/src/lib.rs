@@ -2,29 +2,124 @@
 #![feature(error_generic_member_access)]
 #![deny(clippy::all)]
 
-use std::{backtrace::Backtrace, env};
+use std::{
+    backtrace::Backtrace,
+    collections::{HashMap, HashSet},
+    env,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicBool, AtomicI64},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 
 use async_trait::async_trait;
+use bytes::Bytes;
 use lazy_static::lazy_static;
 use regex::Regex;
 pub use reqwest::Response;
-use reqwest::{Method, RequestBuilder, StatusCode};
+use reqwest::{
+    header::{HeaderName, ETAG, IF_NONE_MATCH},
+    Method, RequestBuilder, StatusCode,
+};
+use tokio::sync::{OnceCell, Semaphore};
+use turbopath::AbsoluteSystemPathBuf;
 use turborepo_ci::{is_ci, Vendor};
 use turborepo_vercel_api::{
-    APIError, CachingStatus, CachingStatusResponse, PreflightResponse, SpacesResponse, Team,
-    TeamsResponse, UserResponse, VerificationResponse, VerifiedSsoUser,
+    APIError, CachingStatus, CachingStatusResponse, CapabilitiesResponse, PreflightResponse,
+    SpacesResponse, Team, TeamsResponse, UserResponse, VerificationResponse, VerifiedSsoUser,
 };
-use url::Url;
 
-pub use crate::error::{Error, Result};
+#[cfg(feature = "test-util")]
+pub use crate::recording::{RecordedRequest, RequestRecorder};
+#[cfg(feature = "rustls-tls")]
+pub use crate::tls_diagnostic::TlsDiagnosis;
+pub use crate::{
+    artifact_meta::ArtifactMeta,
+    auth_provider::{AuthProvider, BearerAuth},
+    buffer_pool::{BufferPool, DEFAULT_BUFFER_POOL_SIZE},
+    cache_access::RemoteCacheAccess,
+    checksum::{read_verified_artifact, ARTIFACT_DIGEST_HEADER},
+    compatibility::{Compatibility, CLIENT_API_VERSION},
+    connection_stats::ConnectionStats,
+    error::{Error, Result},
+    identity::{Identity, DEFAULT_IDENTITY_TTL},
+    progress::UploadProgress,
+    redirect::RedirectPolicy,
+    region::{Region, DEFAULT_API_URL},
+    resumable::RESUMABLE_UPLOADS_CAPABILITY,
+    retry::{RetryDecider, IDEMPOTENCY_KEY_HEADER},
+    signature::HmacAlgorithm,
+    timing::{RequestObserver, RequestTiming},
+    urls::TrailingSlashPolicy,
+    usage::TeamUsage,
+    warnings::{Warning, WarningSink},
+};
+use crate::{
+    connection_stats::{ConnectionCounter, CountingResolver},
+    cooldown::Cooldown,
+    identity::IdentityCache,
+    network::NetworkStatus,
+    preflight::PreflightCache,
+    progress::progress_body,
+    rate_limit::RateLimiter,
+    spaces::{
+        BodyFormat, CacheSourceCasing, CompressionThresholds, DuplicateTaskKeyPolicy,
+        EmptyUserPolicy, FinishRetryPolicy, LogCompression, LogUploadPolicy, OpenWork,
+        PayloadDialect, RequestPriority, RequestQueue, RunStartTimes, RunUploads,
+        SpacesFailurePolicy, SpacesMethod, SpacesPriorities, StreamReconnectPolicy, TaskKeys,
+        TaskTimePolicy, UserIdentity, DEFAULT_RUN_VISIBILITY_TIMEOUT,
+    },
+    timing::BodySent,
+};
 
+mod artifact_meta;
+mod auth_provider;
+mod buffer_pool;
+mod cache_access;
+mod checksum;
+mod clock;
+mod compatibility;
+mod connection_stats;
+mod cooldown;
+mod download;
 mod error;
+mod failover;
+mod identity;
+mod keep_alive;
+mod network;
+mod preflight;
+mod progress;
+mod rate_limit;
+#[cfg(feature = "test-util")]
+mod recording;
+mod redirect;
+mod region;
+mod resumable;
 mod retry;
+mod signature;
 pub mod spaces;
+mod team;
+#[cfg(test)]
+mod testing;
+mod timing;
+#[cfg(feature = "rustls-tls")]
+mod tls_diagnostic;
+mod urls;
+mod usage;
+mod warnings;
+
+/// The spaces API version implied by the `/v0/spaces` endpoint paths
+pub const DEFAULT_SPACES_API_VERSION: u32 = 0;
+
+/// How long finishing a run waits for the server by default
+pub const DEFAULT_FINISH_TIMEOUT: Duration = Duration::from_secs(5);
 
 lazy_static! {
-    static ref AUTHORIZATION_REGEX: Regex =
-        Regex::new(r"(?i)(?:^|,) *authorization *(?:,|$)").unwrap();
+    // Deployment URLs are routed through Vercel's deployment proxy
+    static ref PREFLIGHT_PROXY_URL_REGEX: Regex =
+        Regex::new(r"(?i)^https?://[^/]+\.vercel\.app(?:[:/]|$)").unwrap();
 }
 
 #[async_trait]
@@ -52,6 +147,7 @@ pub trait Client {
         artifact_body: &[u8],
         duration: u64,
         tag: Option<&str>,
+        content_type: Option<&str>,
         token: &str,
     ) -> Result<()>;
     async fn handle_403(response: Response) -> Error;
@@ -87,12 +183,140 @@ pub trait Client {
     fn make_url(&self, endpoint: &str) -> String;
 }
 
+/// The result of `fetch_artifact_if_changed`
+#[derive(Debug)]
+pub enum ArtifactFetch {
+    /// The artifact changed, or there was no local copy to compare against.
+    /// The ETag should be stored with the downloaded artifact.
+    Modified {
+        response: Response,
+        etag: Option<String>,
+    },
+    /// The local copy of the artifact is up to date
+    NotModified,
+}
+
+/// Customizes a request built by the `APIClient` before it's sent, see
+/// `APIClient::with_request_hook`
+pub type RequestHook = Arc<dyn Fn(RequestBuilder) -> RequestBuilder + Send + Sync>;
+
 #[derive(Clone)]
 pub struct APIClient {
     client: reqwest::Client,
     base_url: String,
+    fallback_urls: Vec<String>,
+    region_urls: HashMap<Region, String>,
+    trailing_slash_policy: TrailingSlashPolicy,
+    version: String,
     user_agent: String,
     use_preflight: bool,
+    preflight_cache: Arc<PreflightCache>,
+    timeout: u64,
+    host_overrides: HashMap<String, IpAddr>,
+    request_observer: Option<RequestObserver>,
+    request_hook: Option<RequestHook>,
+    #[cfg(feature = "test-util")]
+    request_recorder: Option<RequestRecorder>,
+    warnings: Option<WarningSink>,
+    upload_progress: Option<UploadProgress>,
+    connection_counter: Arc<ConnectionCounter>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    in_flight: Option<Arc<Semaphore>>,
+    capabilities: Arc<OnceCell<Option<CapabilitiesResponse>>>,
+    team_ids: Arc<Mutex<HashMap<String, String>>>,
+    identity_cache: Arc<IdentityCache>,
+    identity_ttl: Duration,
+    task_keys: Arc<Mutex<TaskKeys>>,
+    run_start_times: Arc<Mutex<RunStartTimes>>,
+    clock_offset: Arc<AtomicI64>,
+    open_work: Arc<OpenWork>,
+    run_uploads: Arc<RunUploads>,
+    buffer_pool: Arc<BufferPool>,
+    keep_alive_interval: Option<Duration>,
+    keep_alive_started: Arc<AtomicBool>,
+    redirect_policy: RedirectPolicy,
+    artifact_auth_mode: AuthMode,
+    cooldown: Arc<Cooldown>,
+    network: Arc<NetworkStatus>,
+    offline_window: Duration,
+    retry_budget: Option<Duration>,
+    attempt_timeout: Option<Duration>,
+    retry_decider: Option<RetryDecider>,
+    spaces_api_version: u32,
+    spaces_queue: Option<Arc<RequestQueue>>,
+    spaces_priorities: SpacesPriorities,
+    compression_thresholds: CompressionThresholds,
+    spaces_disabled: Option<Arc<dyn Fn() -> bool + Send + Sync>>,
+    cache_source_casing: CacheSourceCasing,
+    payload_dialect: PayloadDialect,
+    user_identity: UserIdentity,
+    empty_user_policy: EmptyUserPolicy,
+    body_format: BodyFormat,
+    log_upload_policy: LogUploadPolicy,
+    streamed_logs: bool,
+    sanitize_commands: bool,
+    canonicalize_commands: bool,
+    finish_precheck: bool,
+    deterministic_uploads: bool,
+    run_recording: bool,
+    finish_timeout: Duration,
+    run_visibility_timeout: Duration,
+    finish_retry_policy: FinishRetryPolicy,
+    stream_reconnect_policy: StreamReconnectPolicy,
+    deferred_finish_dir: Option<AbsoluteSystemPathBuf>,
+    log_compression: Vec<LogCompression>,
+    spaces_failure_policy: SpacesFailurePolicy,
+    duplicate_task_key_policy: DuplicateTaskKeyPolicy,
+    task_time_policy: TaskTimePolicy,
+    deadline_header: bool,
+    // Spaces that the server reported as missing. Shared between clones so
+    // that every upload for a bad space short-circuits.
+    invalid_spaces: Arc<Mutex<HashSet<String>>>,
+    // Headers that `AuthProvider`s added to requests, which are stripped on
+    // cross-origin redirects like the token
+    auth_headers: Arc<Mutex<Vec<HeaderName>>>,
+}
+
+/// Whether to send a CORS preflight request before artifact and spaces
+/// requests. Preflight is only needed when requests go through Vercel's
+/// deployment proxy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Preflight {
+    On,
+    #[default]
+    Off,
+    /// Enables preflight only when the environment indicates that requests
+    /// go through a proxy that requires it
+    Auto,
+}
+
+// Environment variables that are set when turbo runs behind Vercel's
+// deployment proxy
+const PREFLIGHT_PROXY_ENV_VARS: &[&str] = &["VERCEL_ARTIFACTS_TOKEN", "VERCEL_ARTIFACTS_OWNER"];
+
+impl Preflight {
+    fn is_enabled(&self, base_url: &str) -> bool {
+        match self {
+            Preflight::On => true,
+            Preflight::Off => false,
+            Preflight::Auto => {
+                PREFLIGHT_PROXY_ENV_VARS
+                    .iter()
+                    .any(|var| env::var_os(var).is_some())
+                    || PREFLIGHT_PROXY_URL_REGEX.is_match(base_url)
+            }
+        }
+    }
+}
+
+impl From<bool> for Preflight {
+    fn from(use_preflight: bool) -> Self {
+        if use_preflight {
+            Preflight::On
+        } else {
+            Preflight::Off
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -100,6 +324,46 @@ pub struct APIAuth {
     pub team_id: String,
     pub token: String,
     pub team_slug: Option<String>,
+    pub mode: AuthMode,
+}
+
+impl APIAuth {
+    /// The token to send with a preflight request, and the auth headers it
+    /// asks for. Only bearer auth sends the token, a signed request carries
+    /// its signature instead.
+    fn preflight_credentials(&self) -> (Option<&str>, String) {
+        match &self.mode {
+            AuthMode::Bearer => (Some(self.token.as_str()), "Authorization".to_string()),
+            AuthMode::Hmac { .. } => (
+                None,
+                format!(
+                    "{}, {}",
+                    signature::SIGNATURE_HEADER,
+                    signature::TIMESTAMP_HEADER
+                ),
+            ),
+            AuthMode::Custom(_) => (None, "Authorization".to_string()),
+        }
+    }
+}
+
+/// How requests made with an `APIAuth` are authenticated.
+#[derive(Clone, Default)]
+pub enum AuthMode {
+    /// Sends the token as an `Authorization: Bearer` header
+    #[default]
+    Bearer,
+    /// Signs the method, path, timestamp and body with a shared secret and
+    /// sends the signature in the `x-turbo-signature` header instead of the
+    /// token. Used by self-hosted caches that authenticate with shared-secret
+    /// signing.
+    Hmac {
+        key: Vec<u8>,
+        algorithm: HmacAlgorithm,
+    },
+    /// Leaves attaching credentials to an `AuthProvider`, the token is
+    /// ignored
+    Custom(Arc<dyn AuthProvider>),
 }
 
 #[async_trait]
@@ -112,7 +376,7 @@ impl Client for APIClient {
             .header("User-Agent", self.user_agent.clone())
             .header("Authorization", format!("Bearer {}", token))
             .header("Content-Type", "application/json");
-        let response = retry::make_retryable_request(request_builder)
+        let response = retry::make_retryable_request(request_builder, self)
             .await?
             .error_for_status()?;
 
@@ -127,7 +391,7 @@ impl Client for APIClient {
             .header("Content-Type", "application/json")
             .header("Authorization", format!("Bearer {}", token));
 
-        let response = retry::make_retryable_request(request_builder)
+        let response = retry::make_retryable_request(request_builder, self)
             .await?
             .error_for_status()?;
 
@@ -135,14 +399,15 @@ impl Client for APIClient {
     }
 
     async fn get_team(&self, token: &str, team_id: &str) -> Result<Option<Team>> {
-        let response = self
+        let request = self
             .client
             .get(self.make_url("/v2/team"))
             .query(&[("teamId", team_id)])
             .header("User-Agent", self.user_agent.clone())
             .header("Content-Type", "application/json")
             .header("Authorization", format!("Bearer {}", token))
-            .send()
+            .build()?;
+        let response = failover::execute(self, &self.client, request, &BodySent::default())
             .await?
             .error_for_status()?;
 
@@ -188,7 +453,7 @@ impl Client for APIClient {
 
         let request_builder = Self::add_team_params(request_builder, team_id, team_slug);
 
-        let response = retry::make_retryable_request(request_builder)
+        let response = retry::make_retryable_request(request_builder, self)
             .await?
             .error_for_status()?;
 
@@ -209,7 +474,7 @@ impl Client for APIClient {
             .header("Content-Type", "application/json")
             .header("Authorization", format!("Bearer {}", token));
 
-        let response = retry::make_retryable_request(request_builder)
+        let response = retry::make_retryable_request(request_builder, self)
             .await?
             .error_for_status()?;
 
@@ -223,7 +488,7 @@ impl Client for APIClient {
             .query(&[("token", token), ("tokenName", token_name)])
             .header("User-Agent", self.user_agent.clone());
 
-        let response = retry::make_retryable_request(request_builder)
+        let response = retry::make_retryable_request(request_builder, self)
             .await?
             .error_for_status()?;
 
@@ -241,51 +506,12 @@ impl Client for APIClient {
         artifact_body: &[u8],
         duration: u64,
         tag: Option<&str>,
+        content_type: Option<&str>,
         token: &str,
     ) -> Result<()> {
-        let mut request_url = self.make_url(&format!("/v8/artifacts/{}", hash));
-        let mut allow_auth = true;
-
-        if self.use_preflight {
-            let preflight_response = self
-                .do_preflight(
-                    token,
-                    &request_url,
-                    "PUT",
-                    "Authorization, Content-Type, User-Agent, x-artifact-duration, x-artifact-tag",
-                )
-                .await?;
-
-            allow_auth = preflight_response.allow_authorization_header;
-            request_url = preflight_response.location.to_string();
-        }
-
-        let mut request_builder = self
-            .client
-            .put(&request_url)
-            .header("Content-Type", "application/octet-stream")
-            .header("x-artifact-duration", duration.to_string())
-            .header("User-Agent", self.user_agent.clone())
-            .body(artifact_body.to_vec());
-
-        if allow_auth {
-            request_builder = request_builder.header("Authorization", format!("Bearer {}", token));
-        }
-
-        request_builder = Self::add_ci_header(request_builder);
-
-        if let Some(tag) = tag {
-            request_builder = request_builder.header("x-artifact-tag", tag);
-        }
-
-        let response = retry::make_retryable_request(request_builder).await?;
-
-        if response.status() == StatusCode::FORBIDDEN {
-            return Err(Self::handle_403(response).await);
-        }
-
-        response.error_for_status()?;
-        Ok(())
+        let api_auth = self.artifact_auth(token, "", None);
+        self.send_put_artifact(hash, artifact_body, duration, tag, content_type, &api_auth)
+            .await
     }
 
     async fn handle_403(response: Response) -> Error {
@@ -350,16 +576,297 @@ impl Client for APIClient {
         team_id: &str,
         team_slug: Option<&str>,
         method: Method,
+    ) -> Result<Response> {
+        let api_auth = self.artifact_auth(token, team_id, team_slug);
+        self.send_artifact_request(hash, &api_auth, method, None)
+            .await
+    }
+
+    async fn do_preflight(
+        &self,
+        token: &str,
+        request_url: &str,
+        request_method: &str,
+        request_headers: &str,
+    ) -> Result<PreflightResponse> {
+        self.send_preflight(Some(token), request_url, request_method, request_headers)
+            .await
+    }
+
+    fn make_url(&self, endpoint: &str) -> String {
+        urls::join_url(&self.base_url, endpoint, self.trailing_slash_policy)
+    }
+}
+
+impl APIClient {
+    pub fn new(
+        base_url: impl AsRef<str>,
+        timeout: u64,
+        version: &str,
+        preflight: impl Into<Preflight>,
+    ) -> Result<Self> {
+        let connection_counter = Arc::new(ConnectionCounter::default());
+        let host_overrides = HashMap::new();
+        let client = Self::build_http_client(timeout, &connection_counter, &host_overrides)?;
+
+        let use_preflight = preflight.into().is_enabled(base_url.as_ref());
+
+        Ok(APIClient {
+            client,
+            base_url: base_url.as_ref().to_string(),
+            fallback_urls: Vec::new(),
+            region_urls: region::default_region_urls(),
+            trailing_slash_policy: TrailingSlashPolicy::default(),
+            version: version.to_string(),
+            user_agent: user_agent(version, false),
+            use_preflight,
+            preflight_cache: Arc::default(),
+            timeout,
+            host_overrides,
+            request_observer: None,
+            request_hook: None,
+            #[cfg(feature = "test-util")]
+            request_recorder: None,
+            warnings: None,
+            upload_progress: None,
+            connection_counter,
+            rate_limiter: None,
+            in_flight: None,
+            capabilities: Arc::default(),
+            team_ids: Arc::default(),
+            identity_cache: Arc::default(),
+            identity_ttl: DEFAULT_IDENTITY_TTL,
+            task_keys: Arc::default(),
+            run_start_times: Arc::default(),
+            clock_offset: Arc::default(),
+            open_work: Arc::default(),
+            run_uploads: Arc::default(),
+            buffer_pool: Arc::default(),
+            keep_alive_interval: None,
+            keep_alive_started: Arc::default(),
+            redirect_policy: RedirectPolicy::default(),
+            artifact_auth_mode: AuthMode::default(),
+            cooldown: Arc::default(),
+            network: Arc::default(),
+            offline_window: Duration::ZERO,
+            retry_budget: None,
+            attempt_timeout: None,
+            retry_decider: None,
+            spaces_api_version: DEFAULT_SPACES_API_VERSION,
+            spaces_queue: None,
+            spaces_priorities: SpacesPriorities::new(),
+            compression_thresholds: CompressionThresholds::new(),
+            spaces_disabled: None,
+            cache_source_casing: CacheSourceCasing::default(),
+            payload_dialect: PayloadDialect::default(),
+            user_identity: UserIdentity::default(),
+            empty_user_policy: EmptyUserPolicy::default(),
+            body_format: BodyFormat::default(),
+            log_upload_policy: LogUploadPolicy::default(),
+            streamed_logs: false,
+            sanitize_commands: false,
+            canonicalize_commands: false,
+            finish_precheck: false,
+            deterministic_uploads: false,
+            run_recording: false,
+            finish_timeout: DEFAULT_FINISH_TIMEOUT,
+            run_visibility_timeout: DEFAULT_RUN_VISIBILITY_TIMEOUT,
+            finish_retry_policy: FinishRetryPolicy::default(),
+            stream_reconnect_policy: StreamReconnectPolicy::default(),
+            deferred_finish_dir: None,
+            log_compression: Vec::new(),
+            spaces_failure_policy: SpacesFailurePolicy::default(),
+            duplicate_task_key_policy: DuplicateTaskKeyPolicy::default(),
+            task_time_policy: TaskTimePolicy::default(),
+            deadline_header: false,
+            invalid_spaces: Arc::default(),
+            auth_headers: Arc::default(),
+        })
+    }
+
+    fn build_http_client(
+        timeout: u64,
+        connection_counter: &Arc<ConnectionCounter>,
+        host_overrides: &HashMap<String, IpAddr>,
+    ) -> Result<reqwest::Client> {
+        let resolver = CountingResolver::new(connection_counter.clone(), host_overrides.clone());
+        // Redirects are followed by `redirect::execute` according to the
+        // client's `RedirectPolicy`
+        let mut client_builder = reqwest::Client::builder()
+            .dns_resolver(Arc::new(resolver))
+            .redirect(reqwest::redirect::Policy::none());
+        if timeout != 0 {
+            client_builder = client_builder.timeout(Duration::from_secs(timeout));
+        }
+
+        client_builder.build().map_err(Error::TlsError)
+    }
+
+    /// Resolves `host` to `ip` instead of using DNS, e.g. to test against a
+    /// specific server behind a load balancer. The URL is unchanged, so TLS
+    /// certificates are still validated against `host`.
+    pub fn with_host_override(mut self, host: impl Into<String>, ip: IpAddr) -> Result<Self> {
+        self.host_overrides.insert(host.into(), ip);
+        self.client =
+            Self::build_http_client(self.timeout, &self.connection_counter, &self.host_overrides)?;
+        Ok(self)
+    }
+
+    /// Sets the hosts to fail over to, in order, when the base URL can't be
+    /// connected to, e.g. a regional mirror. Each is used like the base URL,
+    /// so it should include the same path prefix, e.g.
+    /// `https://eu.vercel.com/api`. Requests only fail over on connect
+    /// errors, never after a host has responded, even with a 5xx, so that a
+    /// write isn't applied twice. Requests sent to a fallback host carry the
+    /// same credentials.
+    pub fn with_fallback_hosts(mut self, fallback_urls: Vec<String>) -> Self {
+        self.fallback_urls = fallback_urls;
+        self
+    }
+
+    /// Sets whether the URLs of API requests end in a slash, for self-hosted
+    /// servers that only accept one form. By default endpoints are used as
+    /// is. Duplicate slashes, e.g. from a base URL with a trailing slash, are
+    /// always removed.
+    pub fn with_trailing_slash_policy(mut self, policy: TrailingSlashPolicy) -> Self {
+        self.trailing_slash_policy = policy;
+        self
+    }
+
+    /// Sets how redirects from the API are followed. By default up to 10
+    /// redirects are followed and credentials are only forwarded to the same
+    /// origin, see `RedirectPolicy` for the security considerations.
+    /// Applies to every request, including keep-alive pings, except for those
+    /// with a streaming body, e.g. the summary stream.
+    pub fn with_redirect_policy(mut self, policy: RedirectPolicy) -> Self {
+        self.redirect_policy = policy;
+        self
+    }
+
+    /// Sets how the artifact methods of the `Client` trait, which only take a
+    /// token, authenticate, e.g. `AuthMode::Hmac` for self-hosted caches that
+    /// sign requests. The token is used as the bearer token or ignored,
+    /// depending on the mode. Methods that take an `APIAuth`, e.g.
+    /// `artifact_head`, use its mode instead. Defaults to `AuthMode::Bearer`.
+    pub fn with_artifact_auth_mode(mut self, mode: AuthMode) -> Self {
+        self.artifact_auth_mode = mode;
+        self
+    }
+
+    fn artifact_auth(&self, token: &str, team_id: &str, team_slug: Option<&str>) -> APIAuth {
+        APIAuth {
+            team_id: team_id.to_string(),
+            token: token.to_string(),
+            team_slug: team_slug.map(|team_slug| team_slug.to_string()),
+            mode: self.artifact_auth_mode.clone(),
+        }
+    }
+
+    /// Uploads an artifact, authenticated according to `api_auth`'s mode. The
+    /// team isn't sent, the token identifies it.
+    pub(crate) async fn send_put_artifact(
+        &self,
+        hash: &str,
+        artifact_body: &[u8],
+        duration: u64,
+        tag: Option<&str>,
+        content_type: Option<&str>,
+        api_auth: &APIAuth,
+    ) -> Result<()> {
+        let mut request_url = self.make_url(&format!("/v8/artifacts/{}", hash));
+        let mut allow_auth = true;
+
+        if self.use_preflight {
+            let (preflight_token, auth_headers) = api_auth.preflight_credentials();
+            let preflight_response = self
+                .send_preflight(
+                    preflight_token,
+                    &request_url,
+                    "PUT",
+                    &format!(
+                        "{}, Content-Type, User-Agent, x-artifact-duration, x-artifact-tag, \
+                         x-artifact-content-type",
+                        auth_headers
+                    ),
+                )
+                .await?;
+
+            allow_auth = preflight_response.allow_authorization_header();
+            request_url = preflight_response.location.to_string();
+        }
+
+        let mut request_builder = self
+            .client
+            .put(&request_url)
+            .header("Content-Type", "application/octet-stream")
+            .header("x-artifact-duration", duration.to_string())
+            .header("User-Agent", self.user_agent.clone());
+
+        request_builder = Self::add_ci_header(request_builder);
+
+        if let Some(tag) = tag {
+            request_builder = request_builder.header("x-artifact-tag", tag);
+        }
+
+        // The body is always sent as an octet stream, so the original content
+        // type of the artifact is sent separately as a hint
+        if let Some(content_type) = content_type {
+            request_builder = request_builder.header("x-artifact-content-type", content_type);
+        }
+
+        request_builder = self
+            .authorize(request_builder, api_auth, artifact_body, allow_auth)
+            .await?;
+
+        let response = match &self.upload_progress {
+            Some(progress) => {
+                // A streaming body can't be cloned, so a new one is created for
+                // every attempt
+                let body = Bytes::copy_from_slice(artifact_body);
+                let build = || {
+                    request_builder
+                        .try_clone()
+                        .expect("cannot clone request")
+                        .header("Content-Length", body.len())
+                        .body(progress_body(body.clone(), progress.clone()))
+                };
+                retry::make_rebuildable_request(build, self).await?
+            }
+            None => {
+                let request_builder = request_builder.body(artifact_body.to_vec());
+                retry::make_retryable_request(request_builder, self).await?
+            }
+        };
+
+        if response.status() == StatusCode::FORBIDDEN {
+            return Err(Self::handle_403(response).await);
+        }
+
+        response.error_for_status()?;
+        Ok(())
+    }
+
+    pub(crate) async fn send_artifact_request(
+        &self,
+        hash: &str,
+        api_auth: &APIAuth,
+        method: Method,
+        if_none_match: Option<&str>,
     ) -> Result<Response> {
         let mut request_url = self.make_url(&format!("/v8/artifacts/{}", hash));
         let mut allow_auth = true;
 
         if self.use_preflight {
+            let (preflight_token, auth_headers) = api_auth.preflight_credentials();
+            let mut request_headers = format!("{}, User-Agent", auth_headers);
+            if if_none_match.is_some() {
+                request_headers.push_str(", If-None-Match");
+            }
             let preflight_response = self
-                .do_preflight(token, &request_url, "GET", "Authorization, User-Agent")
+                .send_preflight(preflight_token, &request_url, "GET", &request_headers)
                 .await?;
 
-            allow_auth = preflight_response.allow_authorization_header;
+            allow_auth = preflight_response.allow_authorization_header();
             request_url = preflight_response.location.to_string();
         };
 
@@ -368,13 +875,21 @@ impl Client for APIClient {
             .request(method, request_url)
             .header("User-Agent", self.user_agent.clone());
 
-        if allow_auth {
-            request_builder = request_builder.header("Authorization", format!("Bearer {}", token));
+        if let Some(etag) = if_none_match {
+            request_builder = request_builder.header(IF_NONE_MATCH, etag);
         }
 
-        request_builder = Self::add_team_params(request_builder, team_id, team_slug);
+        request_builder = Self::add_team_params(
+            request_builder,
+            &api_auth.team_id,
+            api_auth.team_slug.as_deref(),
+        );
+        // Signed once the team params are added, so they're covered
+        request_builder = self
+            .authorize(request_builder, api_auth, &[], allow_auth)
+            .await?;
 
-        let response = retry::make_retryable_request(request_builder).await?;
+        let response = retry::make_retryable_request(request_builder, self).await?;
 
         if response.status() == StatusCode::FORBIDDEN {
             Err(Self::handle_403(response).await)
@@ -383,94 +898,501 @@ impl Client for APIClient {
         }
     }
 
-    async fn do_preflight(
+    /// Fetches an artifact unless it matches `etag`, the ETag returned when
+    /// a local copy of the artifact was downloaded. Without an ETag the
+    /// artifact is always downloaded.
+    ///
+    /// Storing the ETag is up to the caller. `turborepo-cache` doesn't, since
+    /// it checks its local cache, which is keyed by the same hash, before
+    /// fetching, so it never fetches an artifact it has a copy of.
+    pub async fn fetch_artifact_if_changed(
         &self,
+        hash: &str,
+        etag: Option<&str>,
         token: &str,
+        team_id: &str,
+        team_slug: Option<&str>,
+    ) -> Result<ArtifactFetch> {
+        let api_auth = self.artifact_auth(token, team_id, team_slug);
+        let response = self
+            .send_artifact_request(hash, &api_auth, Method::GET, etag)
+            .await?;
+
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(ArtifactFetch::NotModified);
+        }
+
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .map(|etag| etag.to_string());
+
+        Ok(ArtifactFetch::Modified { response, etag })
+    }
+
+    /// Registers a hook that can modify requests before they're sent, e.g.
+    /// to add a header or change the timeout. It applies to the spaces
+    /// endpoints, usage, capabilities and resumable uploads, but not to the
+    /// methods of the `Client` trait, e.g. `get_user` or artifact uploads and
+    /// downloads. The hook
+    /// runs after all built-in headers have been set, including the request
+    /// signature, so changing the method, URL or body of a signed request
+    /// invalidates its signature.
+    pub fn with_request_hook(
+        mut self,
+        hook: impl Fn(RequestBuilder) -> RequestBuilder + Send + Sync + 'static,
+    ) -> Self {
+        self.request_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Registers a callback that receives the timing breakdown of every
+    /// request attempt made by this client.
+    pub fn with_request_observer(
+        mut self,
+        observer: impl Fn(&RequestTiming) + Send + Sync + 'static,
+    ) -> Self {
+        self.request_observer = Some(Arc::new(observer));
+        self
+    }
+
+    /// Collects warnings about soft failures into `sink` instead of dropping
+    /// them.
+    pub fn with_warnings(mut self, sink: WarningSink) -> Self {
+        self.warnings = Some(sink);
+        self
+    }
+
+    pub(crate) fn warn(&self, warning: Warning) {
+        if let Some(warnings) = &self.warnings {
+            warnings.push(warning);
+        }
+    }
+
+    /// Registers a callback that receives the progress of artifact uploads as
+    /// `(bytes_sent, total)`.
+    pub fn with_upload_progress(
+        mut self,
+        progress: impl Fn(u64, u64) + Send + Sync + 'static,
+    ) -> Self {
+        self.upload_progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Returns how many requests opened a new connection versus reused one
+    /// from the pool, across all clones of this client.
+    pub fn connection_stats(&self) -> ConnectionStats {
+        self.connection_counter.stats()
+    }
+
+    /// Caps the rate of outgoing requests, including retries, to
+    /// `requests_per_second` across all clones of this client. A value of 0
+    /// disables the limit.
+    pub fn with_rate_limit(mut self, requests_per_second: u32) -> Self {
+        self.rate_limiter =
+            (requests_per_second > 0).then(|| Arc::new(RateLimiter::new(requests_per_second)));
+        self
+    }
+
+    /// Caps the number of requests in flight at once to `max_requests`
+    /// across all clones of this client, wherever they're sent from, so a
+    /// fragile self-hosted cache isn't overwhelmed with connections.
+    /// Requests over the cap wait until another one has received its
+    /// response headers, so an open `SummaryStream` takes up a slot until
+    /// it's closed. A value of 0 disables the limit, which is the default.
+    pub fn with_max_in_flight_requests(mut self, max_requests: usize) -> Self {
+        self.in_flight = (max_requests > 0).then(|| Arc::new(Semaphore::new(max_requests)));
+        self
+    }
+
+    /// Replaces the default status based decision of whether to retry a
+    /// request attempt, for servers with failure modes the status doesn't
+    /// capture. The decider only sees the status and headers of a response,
+    /// not its body. Attempts are still limited by the maximum number of
+    /// retries and the retry budget.
+    pub fn with_retry_decider(
+        mut self,
+        decider: impl Fn(std::result::Result<&Response, &reqwest::Error>) -> bool
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.retry_decider = Some(Arc::new(decider));
+        self
+    }
+
+    /// Limits how long each attempt of a request may take. An attempt that
+    /// times out is retried, within the retry budget if there is one.
+    pub fn with_attempt_timeout(mut self, timeout: Duration) -> Self {
+        self.attempt_timeout = Some(timeout);
+        self
+    }
+
+    /// Limits how long a request keeps being retried, across all of its
+    /// attempts. Retrying stops at whichever comes first, the budget running
+    /// out or the maximum number of attempts, and no attempt runs past the
+    /// end of the budget.
+    pub fn with_retry_budget(mut self, budget: Duration) -> Self {
+        self.retry_budget = Some(budget);
+        self
+    }
+
+    /// Pins the spaces API version requested from the server via the
+    /// `Accept` header. Defaults to `DEFAULT_SPACES_API_VERSION`.
+    pub fn with_spaces_api_version(mut self, version: u32) -> Self {
+        self.spaces_api_version = version;
+        self
+    }
+
+    /// Sets a kill switch for spaces. The predicate is checked on every
+    /// spaces call, and while it returns true no spaces requests are made:
+    /// `create_space_run` returns a disabled run (see
+    /// `spaces::is_disabled_run`) and uploads succeed immediately.
+    pub fn with_spaces_disabled(
+        mut self,
+        predicate: impl Fn() -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.spaces_disabled = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Limits the number of concurrent spaces requests across all clones of
+    /// this client. Waiting requests are sent in order of priority, so that
+    /// e.g. finishing a run isn't stuck behind a backlog of task uploads. A
+    /// value of 0 disables the limit.
+    pub fn with_spaces_concurrency(mut self, max_concurrent: usize) -> Self {
+        self.spaces_queue =
+            (max_concurrent > 0).then(|| Arc::new(RequestQueue::new(max_concurrent)));
+        self
+    }
+
+    /// Like `with_spaces_concurrency`, but the limit adapts to the server's
+    /// health. It's halved whenever a task summary upload is rate limited or
+    /// times out, and grows back by one after a full limit's worth of
+    /// successful uploads, up to `max_concurrent`.
+    pub fn with_adaptive_spaces_concurrency(mut self, max_concurrent: usize) -> Self {
+        self.spaces_queue =
+            (max_concurrent > 0).then(|| Arc::new(RequestQueue::adaptive(max_concurrent)));
+        self
+    }
+
+    /// The current limit on concurrent spaces requests, if there is one
+    pub fn spaces_concurrency(&self) -> Option<usize> {
+        self.spaces_queue.as_ref().map(|queue| queue.limit())
+    }
+
+    /// Overrides the priority of a spaces request, see
+    /// `SpacesMethod::default_priority` for the defaults.
+    pub fn with_spaces_priority(mut self, method: SpacesMethod, priority: RequestPriority) -> Self {
+        self.spaces_priorities.insert(method, priority);
+        self
+    }
+
+    /// Overrides the smallest request body that's gzipped for `method`, see
+    /// `SpacesMethod::default_compression_threshold`. `None` turns off
+    /// compression for the method. Bodies are only compressed for servers
+    /// that advertise `GZIP_REQUESTS_CAPABILITY`.
+    pub fn with_compression_threshold(
+        mut self,
+        method: SpacesMethod,
+        threshold: Option<usize>,
+    ) -> Self {
+        self.compression_thresholds.insert(method, threshold);
+        self
+    }
+
+    /// Sets the casing used when sending a task's cache source, for servers
+    /// that don't accept the canonical uppercase values.
+    pub fn with_cache_source_casing(mut self, casing: CacheSourceCasing) -> Self {
+        self.cache_source_casing = casing;
+        self
+    }
+
+    /// Sets the field naming used for spaces payloads, for dashboard servers
+    /// that don't use the official field names.
+    pub fn with_payload_dialect(mut self, dialect: PayloadDialect) -> Self {
+        self.payload_dialect = dialect;
+        self
+    }
+
+    /// Sets how the user that started a run is reported. Defaults to sending
+    /// the user as is.
+    pub fn with_user_identity(mut self, user_identity: UserIdentity) -> Self {
+        self.user_identity = user_identity;
+        self
+    }
+
+    /// Sets what's reported as a run's user when the user couldn't be
+    /// detected. Defaults to leaving the user out.
+    pub fn with_empty_user_policy(mut self, policy: EmptyUserPolicy) -> Self {
+        self.empty_user_policy = policy;
+        self
+    }
+
+    /// Sets the format run creations and task summaries are sent in, see
+    /// `BodyFormat`. Defaults to JSON.
+    pub fn with_body_format(mut self, format: BodyFormat) -> Self {
+        self.body_format = format;
+        self
+    }
+
+    /// Sets which task summaries include their logs, see `LogUploadPolicy`.
+    /// Defaults to uploading every task's logs.
+    pub fn with_log_upload_policy(mut self, policy: LogUploadPolicy) -> Self {
+        self.log_upload_policy = policy;
+        self
+    }
+
+    /// When enabled, task summaries with empty `logs` are sent without the
+    /// field, so that logs streamed with `append_task_logs` aren't
+    /// overwritten. Off by default, since servers that don't support log
+    /// streaming may require the field.
+    pub fn with_streamed_logs(mut self, enabled: bool) -> Self {
+        self.streamed_logs = enabled;
+        self
+    }
+
+    /// When enabled, a run's command is canonicalized before it's sent. The
+    /// path to the binary is shortened to its name and the values of
+    /// arguments that look like secrets are redacted.
+    pub fn with_command_sanitization(mut self, enabled: bool) -> Self {
+        self.sanitize_commands = enabled;
+        self
+    }
+
+    /// When enabled, the command of a run is canonicalized before it's sent,
+    /// so that runs of the same command group together on the dashboard even
+    /// if their flags are in a different order or their paths differ. The
+    /// command as it was run is sent as `rawCommand`, after sanitization.
+    pub fn with_command_canonicalization(mut self, enabled: bool) -> Self {
+        self.canonicalize_commands = enabled;
+        self
+    }
+
+    /// When enabled, spaces requests send the client's timeout, or the attempt
+    /// timeout if it's shorter, as a `grpc-timeout` deadline header. Only some
+    /// backends honor it, so it's off by default. Has no effect if the client
+    /// has neither timeout.
+    pub fn with_deadline_header(mut self, enabled: bool) -> Self {
+        self.deadline_header = enabled;
+        self
+    }
+
+    /// When enabled, the user agent is only `turbo/{version}`, without the
+    /// rustc version, OS and architecture, for deployments that audit what's
+    /// sent to the API. Off by default.
+    pub fn with_anonymized_user_agent(mut self, enabled: bool) -> Self {
+        self.user_agent = user_agent(&self.version, enabled);
+        self
+    }
+
+    /// Sets what runs should do when they can't be recorded in their space
+    pub fn with_spaces_failure_policy(mut self, policy: SpacesFailurePolicy) -> Self {
+        self.spaces_failure_policy = policy;
+        self
+    }
+
+    /// Sets what happens when a task summary is uploaded to a run that
+    /// already has one with the same key. Defaults to uploading it anyway
+    /// with a warning.
+    pub fn with_duplicate_task_key_policy(mut self, policy: DuplicateTaskKeyPolicy) -> Self {
+        self.duplicate_task_key_policy = policy;
+        self
+    }
+
+    /// Sets what happens when a task summary starts before its run. Defaults
+    /// to uploading it as is.
+    pub fn with_task_time_policy(mut self, policy: TaskTimePolicy) -> Self {
+        self.task_time_policy = policy;
+        self
+    }
+
+    pub fn spaces_failure_policy(&self) -> SpacesFailurePolicy {
+        self.spaces_failure_policy
+    }
+
+    /// When enabled, finishing a run first checks whether the server already
+    /// finished it and skips the update if so. This avoids errors from
+    /// servers that reject finishing a run twice, at the cost of an extra
+    /// request.
+    pub fn with_finish_precheck(mut self, finish_precheck: bool) -> Self {
+        self.finish_precheck = finish_precheck;
+        self
+    }
+
+    /// Makes concurrent uploads, e.g. `create_task_summaries`, send their
+    /// requests one at a time in input order, so tests comparing the requests
+    /// are reproducible. Off by default, since it's slower.
+    pub fn with_deterministic_uploads(mut self, deterministic: bool) -> Self {
+        self.deterministic_uploads = deterministic;
+        self
+    }
+
+    /// When enabled, each `SpaceSession` keeps a `RunRecording` of what it
+    /// uploaded, which can be exported as a `RunDocument`. Task summaries are
+    /// kept in memory until the recording is dropped, so this is off by
+    /// default.
+    pub fn with_run_recording(mut self, enabled: bool) -> Self {
+        self.run_recording = enabled;
+        self
+    }
+
+    /// Sets how many buffers are kept for encoding spaces request bodies, see
+    /// `BufferPool`. With 0, every body is encoded into a new buffer.
+    /// Defaults to `DEFAULT_BUFFER_POOL_SIZE`.
+    pub fn with_buffer_pool_size(mut self, max_buffers: usize) -> Self {
+        self.buffer_pool = Arc::new(BufferPool::new(max_buffers));
+        self
+    }
+
+    /// Sets how often `start_keep_alive` pings the server to keep a pooled
+    /// connection warm. This trades a trickle of background traffic for fast
+    /// run starts in long-lived processes, so there's no keep-alive by
+    /// default.
+    pub fn with_keep_alive(mut self, interval: Duration) -> Self {
+        self.keep_alive_interval = Some(interval);
+        self
+    }
+
+    /// Sets how long identities resolved by `resolve_identity` are reused.
+    /// A zero TTL disables the cache. Defaults to `DEFAULT_IDENTITY_TTL`.
+    pub fn with_identity_ttl(mut self, ttl: Duration) -> Self {
+        self.identity_ttl = ttl;
+        self
+    }
+
+    /// Sets how long requests fail fast with `Error::Offline` after a request
+    /// couldn't connect to the API, e.g. while the machine is offline. The
+    /// request that couldn't connect still makes its remaining attempts. Off
+    /// by default.
+    pub fn with_offline_window(mut self, window: Duration) -> Self {
+        self.offline_window = window;
+        self
+    }
+
+    /// Sets how long finishing a run may take in total, including its
+    /// pre-check and every retry. Runs are finished during teardown, so this
+    /// is usually shorter than the timeout of other requests to avoid hanging
+    /// on a dead connection. Defaults to `DEFAULT_FINISH_TIMEOUT`.
+    pub fn with_finish_timeout(mut self, timeout: Duration) -> Self {
+        self.finish_timeout = timeout;
+        self
+    }
+
+    /// Sets how long `verify_run_visible` waits for a run to become visible.
+    /// Defaults to `DEFAULT_RUN_VISIBILITY_TIMEOUT`.
+    pub fn with_run_visibility_timeout(mut self, timeout: Duration) -> Self {
+        self.run_visibility_timeout = timeout;
+        self
+    }
+
+    /// Sets how finishing a run retries server errors, see
+    /// `FinishRetryPolicy`
+    pub fn with_finish_retry_policy(mut self, policy: FinishRetryPolicy) -> Self {
+        self.finish_retry_policy = policy;
+        self
+    }
+
+    /// Sets how a `SummaryStream` is reopened after its connection drops,
+    /// see `StreamReconnectPolicy`
+    pub fn with_stream_reconnect_policy(mut self, policy: StreamReconnectPolicy) -> Self {
+        self.stream_reconnect_policy = policy;
+        self
+    }
+
+    /// Stores finishes that still fail after their retries in `dir`, instead
+    /// of returning the error, so that the run isn't left running on the
+    /// server. They're sent again by `finish_deferred_runs`, e.g. on the next
+    /// invocation. Off by default.
+    pub fn with_deferred_finishes(mut self, dir: AbsoluteSystemPathBuf) -> Self {
+        self.deferred_finish_dir = Some(dir);
+        self
+    }
+
+    /// Sets the algorithms task logs may be compressed with, in order of
+    /// preference. Each task's logs are compressed with the accepted
+    /// algorithm that makes them smallest, or sent as is if the server
+    /// accepts none of them. Logs aren't compressed by default.
+    pub fn with_log_compression(mut self, preferences: Vec<LogCompression>) -> Self {
+        self.log_compression = preferences;
+        self
+    }
+
+    /// Sends a preflight request, with the token as a bearer token if there
+    /// is one. Responses are reused for the same URL and method for as long
+    /// as their max age allows.
+    async fn send_preflight(
+        &self,
+        token: Option<&str>,
         request_url: &str,
         request_method: &str,
         request_headers: &str,
     ) -> Result<PreflightResponse> {
-        let request_builder = self
+        if let Some(response) = self.preflight_cache.get(request_url, request_method) {
+            return Ok(response);
+        }
+
+        let mut request_builder = self
             .client
             .request(Method::OPTIONS, request_url)
             .header("User-Agent", self.user_agent.clone())
             .header("Access-Control-Request-Method", request_method)
-            .header("Access-Control-Request-Headers", request_headers)
-            .header("Authorization", format!("Bearer {}", token));
-
-        let response = retry::make_retryable_request(request_builder).await?;
-
-        let headers = response.headers();
-        let location = if let Some(location) = headers.get("Location") {
-            let location = location.to_str()?;
-
-            match Url::parse(location) {
-                Ok(location_url) => location_url,
-                Err(url::ParseError::RelativeUrlWithoutBase) => {
-                    Url::parse(&self.base_url)?.join(location)?
-                }
-                Err(e) => return Err(e.into()),
-            }
-        } else {
-            response.url().clone()
-        };
-
-        let allowed_headers = headers
-            .get("Access-Control-Allow-Headers")
-            .map_or("", |h| h.to_str().unwrap_or(""));
+            .header("Access-Control-Request-Headers", request_headers);
+        if let Some(token) = token {
+            request_builder = request_builder.header("Authorization", format!("Bearer {}", token));
+        }
 
-        let allow_auth = AUTHORIZATION_REGEX.is_match(allowed_headers);
+        let response = retry::make_retryable_request(request_builder, self).await?;
 
-        Ok(PreflightResponse {
-            location,
-            allow_authorization_header: allow_auth,
-        })
+        let response = preflight::parse_preflight_response(
+            response.headers(),
+            response.url(),
+            &self.base_url,
+        )?;
+        self.preflight_cache
+            .insert(request_url, request_method, &response);
+        Ok(response)
     }
 
-    fn make_url(&self, endpoint: &str) -> String {
-        format!("{}{}", self.base_url, endpoint)
+    fn spaces_disabled(&self) -> bool {
+        self.spaces_disabled
+            .as_ref()
+            .map_or(false, |predicate| predicate())
     }
 }
 
-impl APIClient {
-    pub fn new(
-        base_url: impl AsRef<str>,
-        timeout: u64,
-        version: &str,
-        use_preflight: bool,
-    ) -> Result<Self> {
-        let client_build = if timeout != 0 {
-            reqwest::Client::builder()
-                .timeout(std::time::Duration::from_secs(timeout))
-                .build()
-        } else {
-            reqwest::Client::builder().build()
-        };
-
-        let client = client_build.map_err(Error::TlsError)?;
-
-        let user_agent = format!(
-            "turbo {} {} {} {}",
-            version,
-            rustc_version_runtime::version(),
-            env::consts::OS,
-            env::consts::ARCH
-        );
-        Ok(APIClient {
-            client,
-            base_url: base_url.as_ref().to_string(),
-            user_agent,
-            use_preflight,
-        })
+fn user_agent(version: &str, anonymized: bool) -> String {
+    if anonymized {
+        return format!("turbo/{}", version);
     }
+    format!(
+        "turbo {} {} {} {}",
+        version,
+        rustc_version_runtime::version(),
+        env::consts::OS,
+        env::consts::ARCH
+    )
 }
 
 #[cfg(test)]
 mod test {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
     use anyhow::Result;
+    use reqwest::StatusCode;
     use turborepo_vercel_api_mock::start_test_server;
 
-    use crate::{APIClient, Client};
+    use crate::{
+        signature::sign_request,
+        spaces::RunPatch,
+        testing::{test_auth, Canned, CannedServer},
+        APIAuth, APIClient, ArtifactFetch, AuthMode, Client, HmacAlgorithm, Preflight,
+        PREFLIGHT_PROXY_URL_REGEX,
+    };
 
     #[tokio::test]
     async fn test_do_preflight() -> Result<()> {
@@ -513,7 +1435,7 @@ mod test {
             )
             .await?;
 
-        assert!(response.allow_authorization_header);
+        assert!(response.allow_authorization_header());
 
         let response = client
             .do_preflight(
@@ -524,9 +1446,304 @@ mod test {
             )
             .await?;
 
-        assert!(!response.allow_authorization_header);
+        assert!(!response.allow_authorization_header());
 
         handle.abort();
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_request_observer() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+        let base_url = format!("http://localhost:{}", port);
+
+        let timings = Arc::new(Mutex::new(Vec::new()));
+        let recorded = timings.clone();
+        let client = APIClient::new(&base_url, 200, "2.0.0", true)?
+            .with_request_observer(move |timing| recorded.lock().unwrap().push(timing.clone()));
+
+        client.get_user("").await?;
+
+        {
+            let timings = timings.lock().unwrap();
+            assert_eq!(timings.len(), 1);
+            assert_eq!(timings[0].attempt, 0);
+            assert_eq!(timings[0].status, Some(StatusCode::OK));
+            assert_eq!(timings[0].url.path(), "/v2/user");
+        }
+
+        // The bucket holds 5 tokens, so the 6th request waits for the rate
+        // limiter, and that wait is reported as queued
+        let timings = Arc::new(Mutex::new(Vec::new()));
+        let recorded = timings.clone();
+        let client = APIClient::new(&base_url, 200, "2.0.0", true)?
+            .with_rate_limit(5)
+            .with_request_observer(move |timing| recorded.lock().unwrap().push(timing.clone()));
+        for _ in 0..6 {
+            client.get_user("").await?;
+        }
+        let timings = timings.lock().unwrap();
+        assert!(timings[0].queued < Duration::from_millis(50));
+        assert!(timings[5].queued >= Duration::from_millis(100));
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_preflight_cache() -> Result<()> {
+        let server = CannedServer::start(|request| match request.path.as_str() {
+            "/cached" => Canned::ok().header("access-control-max-age", "600"),
+            _ => Canned::ok(),
+        })
+        .await;
+        let client = server.client();
+        let preflight = |path: &str, method: &str| {
+            let url = format!("{}{}", server.url(), path);
+            let client = &client;
+            let method = method.to_string();
+            async move { client.do_preflight("token", &url, &method, "").await }
+        };
+
+        preflight("/cached", "GET").await?;
+        preflight("/cached", "GET").await?;
+        assert_eq!(server.requests().len(), 1);
+
+        // Cached per method, and not at all without a max age
+        preflight("/cached", "PUT").await?;
+        preflight("/uncached", "GET").await?;
+        preflight("/uncached", "GET").await?;
+        assert_eq!(server.requests().len(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_preflight_modes() {
+        assert!(Preflight::from(true).is_enabled("http://localhost:3000"));
+        assert!(!Preflight::from(false).is_enabled("https://my-app.vercel.app"));
+        assert!(Preflight::Auto.is_enabled("https://my-app.vercel.app"));
+
+        assert!(PREFLIGHT_PROXY_URL_REGEX.is_match("https://my-app.vercel.app/api"));
+        assert!(!PREFLIGHT_PROXY_URL_REGEX.is_match("https://cache.example.com"));
+        assert!(!PREFLIGHT_PROXY_URL_REGEX.is_match("https://vercel.app.example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_connection_stats() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+        let base_url = format!("http://localhost:{}", port);
+
+        let client = APIClient::new(&base_url, 200, "2.0.0", true)?;
+        client.get_user("").await?;
+        client.get_user("").await?;
+
+        // The second request goes over the pooled connection from the first
+        let stats = client.connection_stats();
+        assert_eq!(stats.new_connections, 1);
+        assert_eq!(stats.reused_connections, 1);
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upload_progress() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+        let base_url = format!("http://localhost:{}", port);
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let client = APIClient::new(&base_url, 200, "2.0.0", false)?.with_upload_progress({
+            let reports = reports.clone();
+            move |sent, total| reports.lock().unwrap().push((sent, total))
+        });
+
+        let body = vec![0; 100 * 1024];
+        client
+            .put_artifact("progress", &body, 10, None, None, "")
+            .await?;
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.first(), Some(&(0, body.len() as u64)));
+        assert_eq!(
+            reports.last(),
+            Some(&(body.len() as u64, body.len() as u64))
+        );
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_host_override() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+        let base_url = format!("http://api.turbo.invalid:{}", port);
+        // Unlike `localhost`, the override has no other address to fall back
+        // on while the server starts, so wait for it to accept connections
+        while tokio::net::TcpStream::connect(("127.0.0.1", port))
+            .await
+            .is_err()
+        {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        let client = APIClient::new(&base_url, 200, "2.0.0", false)?
+            .with_host_override("api.turbo.invalid", [127, 0, 0, 1].into())?;
+        client.get_user("").await?;
+        assert_eq!(client.connection_stats().new_connections, 1);
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[test]
+    fn test_anonymized_user_agent() -> Result<()> {
+        let client = APIClient::new("http://localhost", 200, "2.0.0", false)?;
+        assert!(client.user_agent.contains(std::env::consts::OS));
+
+        let client = client.with_anonymized_user_agent(true);
+        assert_eq!(client.user_agent, "turbo/2.0.0");
+
+        let client = client.with_anonymized_user_agent(false);
+        assert!(client.user_agent.starts_with("turbo 2.0.0 "));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fetch_artifact_if_changed() -> Result<()> {
+        let server = CannedServer::start(|request| match request.header("if-none-match") {
+            Some(r#""v1""#) => Canned::status(304),
+            _ => Canned::ok().header("etag", r#""v1""#).body("artifact"),
+        })
+        .await;
+        let client = server.client();
+        let api_auth = test_auth();
+        let fetch = |etag: Option<&'static str>| {
+            client.fetch_artifact_if_changed("hash", etag, &api_auth.token, &api_auth.team_id, None)
+        };
+
+        // Without a local copy, the artifact is downloaded along with its ETag
+        let ArtifactFetch::Modified { response, etag } = fetch(None).await? else {
+            panic!("expected the artifact to be downloaded");
+        };
+        assert_eq!(etag.as_deref(), Some(r#""v1""#));
+        assert_eq!(response.text().await?, "artifact");
+        assert_eq!(server.requests()[0].header("if-none-match"), None);
+
+        assert!(matches!(
+            fetch(Some(r#""v1""#)).await?,
+            ArtifactFetch::NotModified
+        ));
+        assert!(matches!(
+            fetch(Some(r#""v0""#)).await?,
+            ArtifactFetch::Modified { .. }
+        ));
+        assert_eq!(
+            server.requests()[2].header("if-none-match"),
+            Some(r#""v0""#)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_signed_artifact_requests() -> Result<()> {
+        let server = CannedServer::always(Canned::ok()).await;
+        let mode = AuthMode::Hmac {
+            key: b"secret".to_vec(),
+            algorithm: HmacAlgorithm::Sha256,
+        };
+        let client = server.client().with_artifact_auth_mode(mode.clone());
+        let api_auth = test_auth();
+
+        client
+            .put_artifact("hash", b"artifact", 10, None, None, &api_auth.token)
+            .await?;
+        client
+            .fetch_artifact("hash", &api_auth.token, &api_auth.team_id, Some("slug"))
+            .await?;
+        // Methods that take an `APIAuth` use its mode
+        let signed_auth = APIAuth {
+            mode,
+            ..test_auth()
+        };
+        server.client().artifact_head("hash", &signed_auth).await?;
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        for request in &requests {
+            assert_eq!(request.header("authorization"), None);
+            let timestamp = request.header("x-turbo-timestamp").unwrap().parse()?;
+            // The query string with the team is part of the signed path
+            let expected = sign_request(
+                b"secret",
+                HmacAlgorithm::Sha256,
+                &request.method,
+                &request.path,
+                timestamp,
+                &request.body,
+            );
+            assert_eq!(request.header("x-turbo-signature"), Some(&*expected));
+        }
+        assert_eq!(requests[1].path, "/v8/artifacts/hash?teamSlug=slug");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_max_in_flight_requests() -> Result<()> {
+        let server = CannedServer::always(
+            Canned::json(r#"{"artifactBytesUsed":0,"runsThisPeriod":0}"#)
+                .delay(Duration::from_millis(100)),
+        )
+        .await;
+        let client = server.client().with_max_in_flight_requests(2);
+        let api_auth = test_auth();
+
+        let started = std::time::Instant::now();
+        let requests = (0..6).map(|_| client.get_team_usage(&api_auth));
+        for result in futures::future::join_all(requests).await {
+            result?;
+        }
+        // Three rounds of two requests
+        assert!(started.elapsed() >= Duration::from_millis(300));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_request_hook() -> Result<()> {
+        let server = CannedServer::always(Canned::json("{}")).await;
+        let client = server
+            .client()
+            .with_request_hook(|request_builder| request_builder.header("x-hooked", "1"));
+        let api_auth = test_auth();
+
+        let patch = RunPatch {
+            name: Some("build web".to_string()),
+            ..Default::default()
+        };
+        client
+            .update_space_run(&"space".into(), &"run".into(), &api_auth, &patch)
+            .await?;
+        let _ = client.get_user(&api_auth.token).await;
+
+        let requests = server.requests();
+        let update = requests
+            .iter()
+            .find(|request| request.method == "PATCH")
+            .expect("request was sent");
+        assert_eq!(update.header("x-hooked"), Some("1"));
+        let get_user = requests
+            .iter()
+            .find(|request| request.path == "/v2/user")
+            .expect("request was sent");
+        assert_eq!(get_user.header("x-hooked"), None);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,40 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// A client-wide "the network appears to be down" flag. Once a request
+/// fails to connect, every request fails right away with `Error::Offline`
+/// for the client's offline window, instead of each one spending its retry
+/// budget on a network that isn't there. A successful request clears it.
+/// Off unless the client has an offline window.
+#[derive(Default)]
+pub(crate) struct NetworkStatus {
+    offline_until: Mutex<Option<Instant>>,
+}
+
+impl NetworkStatus {
+    pub(crate) fn is_offline(&self) -> bool {
+        self.offline_until
+            .lock()
+            .expect("network status lock poisoned")
+            .is_some_and(|until| until > Instant::now())
+    }
+
+    pub(crate) fn mark_offline(&self, window: Duration) {
+        if window.is_zero() {
+            return;
+        }
+        *self
+            .offline_until
+            .lock()
+            .expect("network status lock poisoned") = Some(Instant::now() + window);
+    }
+
+    pub(crate) fn mark_online(&self) {
+        *self
+            .offline_until
+            .lock()
+            .expect("network status lock poisoned") = None;
+    }
+}
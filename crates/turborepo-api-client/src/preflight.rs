@@ -0,0 +1,155 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use reqwest::header::{
+    HeaderMap, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_MAX_AGE,
+    LOCATION,
+};
+use turborepo_vercel_api::PreflightResponse;
+use url::Url;
+
+use crate::Error;
+
+/// Parses the headers of a response to a preflight request for
+/// `request_url`. A relative `Location` is resolved against `base_url`.
+pub(crate) fn parse_preflight_response(
+    headers: &HeaderMap,
+    request_url: &Url,
+    base_url: &str,
+) -> Result<PreflightResponse, Error> {
+    let location = match headers.get(LOCATION) {
+        Some(location) => {
+            let location = location.to_str()?;
+            match Url::parse(location) {
+                Ok(location_url) => location_url,
+                Err(url::ParseError::RelativeUrlWithoutBase) => {
+                    Url::parse(base_url)?.join(location)?
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        None => request_url.clone(),
+    };
+
+    let list = |name, normalize: fn(&str) -> String| -> Vec<String> {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(normalize)
+            .collect()
+    };
+
+    // An invalid max age is treated as missing, as browsers do
+    let max_age = headers
+        .get(ACCESS_CONTROL_MAX_AGE)
+        .and_then(|max_age| max_age.to_str().ok())
+        .and_then(|max_age| max_age.trim().parse().ok())
+        .map(Duration::from_secs);
+
+    Ok(PreflightResponse {
+        location,
+        allowed_headers: list(ACCESS_CONTROL_ALLOW_HEADERS, str::to_ascii_lowercase),
+        allowed_methods: list(ACCESS_CONTROL_ALLOW_METHODS, str::to_ascii_uppercase),
+        max_age,
+    })
+}
+
+/// Preflight responses by request URL and method, kept for their max age and
+/// shared by an `APIClient` and its clones
+#[derive(Default)]
+pub(crate) struct PreflightCache {
+    entries: Mutex<HashMap<(String, String), (Instant, PreflightResponse)>>,
+}
+
+impl PreflightCache {
+    pub(crate) fn get(&self, url: &str, method: &str) -> Option<PreflightResponse> {
+        let entries = self.entries.lock().expect("preflight cache lock poisoned");
+        let (expires_at, response) = entries.get(&(url.to_string(), method.to_string()))?;
+        (Instant::now() < *expires_at).then(|| response.clone())
+    }
+
+    /// Caches `response` if it has a max age
+    pub(crate) fn insert(&self, url: &str, method: &str, response: &PreflightResponse) {
+        let Some(max_age) = response.max_age.filter(|max_age| !max_age.is_zero()) else {
+            return;
+        };
+        self.entries
+            .lock()
+            .expect("preflight cache lock poisoned")
+            .insert(
+                (url.to_string(), method.to_string()),
+                (Instant::now() + max_age, response.clone()),
+            );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use url::Url;
+
+    use super::parse_preflight_response;
+
+    fn headers(fixture: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in fixture {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn test_parse_preflight_response() {
+        let request_url = Url::parse("https://api.vercel.com/v8/artifacts/hash").unwrap();
+
+        let response = parse_preflight_response(
+            &headers(&[
+                ("access-control-allow-headers", "Authorization, User-Agent"),
+                ("access-control-allow-headers", "x-artifact-tag"),
+                ("access-control-allow-methods", "get,put"),
+                ("access-control-max-age", "600"),
+                ("location", "/v8/artifacts/hash?rewritten"),
+            ]),
+            &request_url,
+            "https://proxy.example.com",
+        )
+        .unwrap();
+        assert_eq!(
+            response.location.as_str(),
+            "https://proxy.example.com/v8/artifacts/hash?rewritten"
+        );
+        assert_eq!(
+            response.allowed_headers,
+            ["authorization", "user-agent", "x-artifact-tag"]
+        );
+        assert!(response.allow_authorization_header());
+        assert!(response.allows_header("X-Artifact-Tag"));
+        assert!(response.allows_method("PUT"));
+        assert!(!response.allows_method("DELETE"));
+        assert_eq!(response.max_age, Some(Duration::from_secs(600)));
+
+        // The wildcard doesn't allow the token
+        let response = parse_preflight_response(
+            &headers(&[
+                ("access-control-allow-headers", "*"),
+                ("access-control-max-age", "soon"),
+            ]),
+            &request_url,
+            "https://proxy.example.com",
+        )
+        .unwrap();
+        assert_eq!(response.location, request_url);
+        assert!(response.allows_header("user-agent"));
+        assert!(!response.allow_authorization_header());
+        assert_eq!(response.max_age, None);
+    }
+}
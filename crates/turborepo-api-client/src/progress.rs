@@ -0,0 +1,31 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::stream;
+
+// Size of the chunks the body is streamed in, and so how often progress is
+// reported
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A callback that receives the number of bytes sent so far and the total
+/// size of an artifact upload.
+pub type UploadProgress = Arc<dyn Fn(u64, u64) + Send + Sync>;
+
+/// Wraps `body` in a stream that reports progress as it's read. Progress
+/// starts from zero every time a body is created, so a retried upload
+/// restarts its progress.
+pub(crate) fn progress_body(body: Bytes, progress: UploadProgress) -> reqwest::Body {
+    let total = body.len() as u64;
+    progress(0, total);
+
+    let chunks = (0..body.len()).step_by(CHUNK_SIZE).map(move |start| {
+        let end = (start + CHUNK_SIZE).min(body.len());
+        (body.slice(start..end), end as u64)
+    });
+    let stream = stream::iter(chunks.map(move |(chunk, sent)| {
+        progress(sent, total);
+        Ok::<_, std::io::Error>(chunk)
+    }));
+
+    reqwest::Body::wrap_stream(stream)
+}
@@ -0,0 +1,100 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tokio::time::sleep;
+
+/// A token bucket that caps the rate of outgoing requests. The bucket holds
+/// up to one second's worth of tokens, so short bursts are allowed as long as
+/// the average rate stays under the limit.
+pub(crate) struct RateLimiter {
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    requests_per_second: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    /// Takes a token if one is available, otherwise returns how long to wait
+    /// until the next token is available.
+    fn try_acquire(&mut self, now: Instant) -> Option<Duration> {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.requests_per_second)
+            .min(self.requests_per_second);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64(
+                (1.0 - self.tokens) / self.requests_per_second,
+            ))
+        }
+    }
+}
+
+impl RateLimiter {
+    pub(crate) fn new(requests_per_second: u32) -> Self {
+        let requests_per_second = f64::from(requests_per_second);
+        Self {
+            bucket: Mutex::new(Bucket {
+                requests_per_second,
+                tokens: requests_per_second,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Waits until a request is allowed to be sent.
+    pub(crate) async fn acquire(&self) {
+        loop {
+            let wait = self
+                .bucket
+                .lock()
+                .expect("rate limiter lock poisoned")
+                .try_acquire(Instant::now());
+
+            match wait {
+                Some(wait) => sleep(wait).await,
+                None => return,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::Bucket;
+
+    #[test]
+    fn test_bucket_refills_over_time() {
+        let start = Instant::now();
+        let mut bucket = Bucket {
+            requests_per_second: 2.0,
+            tokens: 2.0,
+            last_refill: start,
+        };
+
+        assert_eq!(bucket.try_acquire(start), None);
+        assert_eq!(bucket.try_acquire(start), None);
+        assert_eq!(bucket.try_acquire(start), Some(Duration::from_millis(500)));
+
+        // Half a second later a single token has been refilled
+        let later = start + Duration::from_millis(500);
+        assert_eq!(bucket.try_acquire(later), None);
+        assert!(bucket.try_acquire(later).is_some());
+
+        // The bucket never holds more than one second of tokens
+        let much_later = later + Duration::from_secs(60);
+        assert_eq!(bucket.try_acquire(much_later), None);
+        assert_eq!(bucket.try_acquire(much_later), None);
+        assert!(bucket.try_acquire(much_later).is_some());
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use reqwest::Request;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::APIClient;
+
+/// An outgoing request as captured by a `RequestRecorder`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub url: String,
+    /// The `Authorization` header is redacted
+    pub headers: BTreeMap<String, String>,
+    /// JSON bodies are recorded as is, other bodies only by their size, and
+    /// streaming bodies aren't recorded
+    pub body: Option<Value>,
+}
+
+impl RecordedRequest {
+    fn new(request: &Request) -> Self {
+        let headers = request
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                let value = if *name == reqwest::header::AUTHORIZATION {
+                    "<redacted>".to_string()
+                } else {
+                    String::from_utf8_lossy(value.as_bytes()).into_owned()
+                };
+                (name.to_string(), value)
+            })
+            .collect();
+        let body = request.body().and_then(|body| body.as_bytes()).map(|body| {
+            serde_json::from_slice(body)
+                .unwrap_or_else(|_| Value::String(format!("<{} bytes>", body.len())))
+        });
+
+        Self {
+            method: request.method().to_string(),
+            url: request.url().to_string(),
+            headers,
+            body,
+        }
+    }
+}
+
+/// Captures every request sent by an `APIClient` and its clones, including
+/// retries and requests sent to fallback hosts, so tests can compare the wire
+/// format against golden files. Redirects that are followed aren't recorded
+/// separately.
+#[derive(Debug, Clone, Default)]
+pub struct RequestRecorder {
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+impl RequestRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes and returns the requests recorded so far
+    pub fn take(&self) -> Vec<RecordedRequest> {
+        std::mem::take(&mut *self.requests.lock().expect("recorder lock poisoned"))
+    }
+
+    /// Removes the requests recorded so far and returns them as pretty
+    /// printed JSON, for comparing against a golden file
+    pub fn take_golden_json(&self) -> String {
+        serde_json::to_string_pretty(&self.take()).expect("recorded requests are serializable")
+    }
+
+    pub(crate) fn record(&self, request: &Request) {
+        self.requests
+            .lock()
+            .expect("recorder lock poisoned")
+            .push(RecordedRequest::new(request));
+    }
+}
+
+impl APIClient {
+    /// Records every outgoing request into `recorder`
+    pub fn with_request_recorder(mut self, recorder: RequestRecorder) -> Self {
+        self.request_recorder = Some(recorder);
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+    use turborepo_vercel_api_mock::start_test_server;
+
+    use super::RequestRecorder;
+    use crate::{spaces::RunPatch, testing::test_auth, APIClient, Client};
+
+    #[tokio::test]
+    async fn test_records_requests() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+        let recorder = RequestRecorder::new();
+        let client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0", false)?
+            .with_request_recorder(recorder.clone());
+        let api_auth = test_auth();
+
+        let patch = RunPatch {
+            name: Some("build web".to_string()),
+            ..Default::default()
+        };
+        // The mock server doesn't have the endpoint, only the request matters
+        let _ = client
+            .update_space_run(&"space".into(), &"run".into(), &api_auth, &patch)
+            .await;
+
+        let requests = recorder.take();
+        let request = requests.first().expect("request was recorded");
+        assert_eq!(request.method, "PATCH");
+        assert_eq!(request.headers["authorization"], "<redacted>");
+        assert_eq!(
+            request.body,
+            Some(serde_json::json!({ "name": "build web" }))
+        );
+
+        // Requests that aren't retried are recorded too
+        let _ = client.get_team(&api_auth.token, &api_auth.team_id).await;
+        let requests = recorder.take();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "GET");
+        assert!(requests[0].url.contains("/v2/team?teamId="));
+
+        handle.abort();
+        Ok(())
+    }
+}
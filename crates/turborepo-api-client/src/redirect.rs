@@ -0,0 +1,216 @@
+use reqwest::{
+    header::{
+        HeaderMap, HeaderName, AUTHORIZATION, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE,
+        COOKIE, LOCATION, PROXY_AUTHORIZATION, TRANSFER_ENCODING,
+    },
+    Method, Request, Response, StatusCode,
+};
+use url::Url;
+
+use crate::{
+    signature::{SIGNATURE_HEADER, TIMESTAMP_HEADER},
+    timing::BodySent,
+};
+
+const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+/// How the client follows redirects from the API.
+///
+/// # Security
+///
+/// Requests carry the user's credentials: the bearer token, the signature of
+/// `AuthMode::Hmac`, or the headers added by an `AuthProvider`. They're never
+/// forwarded to a
+/// different origin, i.e. a different scheme, host or port, since a redirect
+/// to a host we don't control would hand them over. Unlike reqwest's default
+/// policy, this includes redirects from https to http on the same host, which
+/// would send them in plain text. A request that's
+/// redirected to another origin is sent there without credentials, so a
+/// server that expects them will return a 401.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RedirectPolicy {
+    /// The number of redirects followed before the last redirect response is
+    /// returned as is
+    pub max_redirects: usize,
+    /// Whether the bearer token is kept on redirects to the same origin, e.g.
+    /// when a proxy moves the API to a different path
+    pub keep_auth_on_same_origin: bool,
+}
+
+impl Default for RedirectPolicy {
+    fn default() -> Self {
+        Self {
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            keep_auth_on_same_origin: true,
+        }
+    }
+}
+
+impl RedirectPolicy {
+    /// Doesn't follow any redirects
+    pub fn none() -> Self {
+        Self {
+            max_redirects: 0,
+            ..Self::default()
+        }
+    }
+}
+
+/// Executes `request`, following redirects according to `policy`. The
+/// underlying reqwest client doesn't follow redirects itself, so that we
+/// decide which credentials are forwarded.
+///
+/// Redirects are only followed if the request can be cloned, i.e. it doesn't
+/// have a streaming body. Otherwise the redirect response is returned.
+/// `auth_headers` are the headers added by `AuthProvider`s, which are removed
+/// along with the crate's own credentials. `body_sent` records when each
+/// request's body was sent.
+pub(crate) async fn execute(
+    http_client: &reqwest::Client,
+    mut request: Request,
+    policy: &RedirectPolicy,
+    auth_headers: &[HeaderName],
+    body_sent: &BodySent,
+) -> reqwest::Result<Response> {
+    let mut redirects = 0;
+    loop {
+        let next_request = request.try_clone();
+        body_sent.track(&mut request);
+        let response = http_client.execute(request).await?;
+        if redirects >= policy.max_redirects {
+            return Ok(response);
+        }
+        let (Some(location), Some(mut next_request)) = (location(&response), next_request) else {
+            return Ok(response);
+        };
+
+        let same_origin = location.origin() == response.url().origin();
+        if !same_origin || !policy.keep_auth_on_same_origin {
+            remove_credentials(next_request.headers_mut(), auth_headers);
+        }
+
+        // Like browsers, 303s and POSTs redirected by a 301 or 302 are
+        // retried as GETs without a body
+        let status = response.status();
+        let method = next_request.method();
+        if (status == StatusCode::SEE_OTHER && method != Method::HEAD)
+            || (matches!(status, StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND)
+                && method == Method::POST)
+        {
+            *next_request.method_mut() = Method::GET;
+            *next_request.body_mut() = None;
+            for header in [
+                CONTENT_TYPE,
+                CONTENT_LENGTH,
+                CONTENT_ENCODING,
+                TRANSFER_ENCODING,
+            ] {
+                next_request.headers_mut().remove(header);
+            }
+        }
+
+        *next_request.url_mut() = location;
+        request = next_request;
+        redirects += 1;
+    }
+}
+
+fn location(response: &Response) -> Option<Url> {
+    if !response.status().is_redirection() {
+        return None;
+    }
+
+    let location = response.headers().get(LOCATION)?.to_str().ok()?;
+    response.url().join(location).ok()
+}
+
+fn remove_credentials(headers: &mut HeaderMap, auth_headers: &[HeaderName]) {
+    for header in [
+        AUTHORIZATION,
+        COOKIE,
+        PROXY_AUTHORIZATION,
+        HeaderName::from_static(SIGNATURE_HEADER),
+        HeaderName::from_static(TIMESTAMP_HEADER),
+    ]
+    .iter()
+    .chain(auth_headers)
+    {
+        headers.remove(header);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use reqwest::{header::AUTHORIZATION, StatusCode};
+
+    use super::{execute, RedirectPolicy};
+    use crate::{
+        testing::{Canned, CannedServer},
+        timing::BodySent,
+    };
+
+    /// Starts a server that redirects `/same` to `/echo` on the same origin
+    /// and `/cross` to `/echo` on `localhost`, a different origin. `/echo`
+    /// responds with whether the request was authorized.
+    async fn start_redirecting_server() -> u16 {
+        let server = CannedServer::start(|request| match request.path.as_str() {
+            "/same" => Canned::status(307).header("location", "/echo"),
+            "/cross" => {
+                let host = request.header("host").unwrap_or_default();
+                let port = host.rsplit(':').next().unwrap_or_default();
+                Canned::status(307).header("location", format!("http://localhost:{port}/echo"))
+            }
+            _ => {
+                let authorized = request
+                    .header("authorization")
+                    .is_some_and(|auth| auth.starts_with("Bearer"));
+                Canned::ok().body(authorized.to_string())
+            }
+        })
+        .await;
+        server.port
+    }
+
+    async fn is_authorized(port: u16, path: &str, policy: RedirectPolicy) -> (StatusCode, String) {
+        let http_client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+        let request = http_client
+            .get(format!("http://127.0.0.1:{port}{path}"))
+            .header(AUTHORIZATION, "Bearer token")
+            .build()
+            .unwrap();
+        let response = execute(&http_client, request, &policy, &[], &BodySent::default())
+            .await
+            .unwrap();
+        (response.status(), response.text().await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_redirect_auth() {
+        let port = start_redirecting_server().await;
+        let default = RedirectPolicy::default();
+
+        assert_eq!(
+            is_authorized(port, "/same", default).await,
+            (StatusCode::OK, "true".to_string())
+        );
+        assert_eq!(
+            is_authorized(port, "/cross", default).await,
+            (StatusCode::OK, "false".to_string())
+        );
+
+        let strip_auth = RedirectPolicy {
+            keep_auth_on_same_origin: false,
+            ..default
+        };
+        assert_eq!(
+            is_authorized(port, "/same", strip_auth).await,
+            (StatusCode::OK, "false".to_string())
+        );
+
+        let (status, _) = is_authorized(port, "/same", RedirectPolicy::none()).await;
+        assert_eq!(status, StatusCode::TEMPORARY_REDIRECT);
+    }
+}
@@ -0,0 +1,103 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use crate::{APIClient, Error};
+
+/// The API used when no region or URL is configured
+pub const DEFAULT_API_URL: &str = "https://vercel.com/api";
+
+/// A regional API endpoint. Teams whose data must stay in a region talk to
+/// that region's endpoint directly.
+///
+/// Only the US endpoint, which is the default API, is built in. The endpoints
+/// of other regions have to be configured with `APIClient::with_region_url`.
+/// The client doesn't check that the region matches where the team's data
+/// lives, so picking the right one is up to the caller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Region {
+    /// The United States, which is the default API
+    Us,
+    /// The European Union
+    Eu,
+}
+
+/// The endpoints that are known without any configuration
+pub(crate) fn default_region_urls() -> HashMap<Region, String> {
+    HashMap::from([(Region::Us, DEFAULT_API_URL.to_string())])
+}
+
+impl FromStr for Region {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "us" => Ok(Region::Us),
+            "eu" => Ok(Region::Eu),
+            _ => Err(Error::UnknownRegion(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Region::Us => "us",
+            Region::Eu => "eu",
+        })
+    }
+}
+
+impl APIClient {
+    /// Sets the endpoint of `region`, replacing the built-in one if there is
+    /// one. Has to be called before `with_region`.
+    pub fn with_region_url(mut self, region: Region, url: impl Into<String>) -> Self {
+        self.region_urls.insert(region, url.into());
+        self
+    }
+
+    /// Sends requests to `region`'s endpoint. A client created with an
+    /// explicit URL, i.e. anything other than `DEFAULT_API_URL`, keeps its
+    /// URL. Returns `Error::UnconfiguredRegion` if the region has no
+    /// endpoint, rather than silently using the default one.
+    pub fn with_region(mut self, region: Region) -> Result<Self, Error> {
+        let url = self
+            .region_urls
+            .get(&region)
+            .ok_or(Error::UnconfiguredRegion(region))?;
+        if self.base_url.trim_end_matches('/') == DEFAULT_API_URL {
+            self.base_url = url.clone();
+        }
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Region, DEFAULT_API_URL};
+    use crate::{APIClient, Error};
+
+    #[test]
+    fn test_region() -> anyhow::Result<()> {
+        assert_eq!("EU".parse::<Region>()?, Region::Eu);
+        assert!("mars".parse::<Region>().is_err());
+
+        let client = APIClient::new(DEFAULT_API_URL, 0, "", false)?.with_region(Region::Us)?;
+        assert_eq!(client.base_url, DEFAULT_API_URL);
+
+        // The EU endpoint isn't built in
+        assert!(matches!(
+            APIClient::new(DEFAULT_API_URL, 0, "", false)?.with_region(Region::Eu),
+            Err(Error::UnconfiguredRegion(Region::Eu))
+        ));
+        let client = APIClient::new(DEFAULT_API_URL, 0, "", false)?
+            .with_region_url(Region::Eu, "https://eu.example.com/api")
+            .with_region(Region::Eu)?;
+        assert_eq!(client.base_url, "https://eu.example.com/api");
+
+        let client = APIClient::new("https://cache.example.com", 0, "", false)?
+            .with_region_url(Region::Eu, "https://eu.example.com/api")
+            .with_region(Region::Eu)?;
+        assert_eq!(client.base_url, "https://cache.example.com");
+
+        Ok(())
+    }
+}
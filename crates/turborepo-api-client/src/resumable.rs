@@ -0,0 +1,246 @@
+use reqwest::{
+    header::{HeaderMap, HeaderValue, CONTENT_TYPE},
+    Method, RequestBuilder, StatusCode,
+};
+
+use crate::{retry, APIAuth, APIClient, Client, Error};
+
+/// The capability servers advertise when they accept artifacts in chunks
+pub const RESUMABLE_UPLOADS_CAPABILITY: &str = "resumable-artifact-uploads";
+
+const CHUNK_SIZE: usize = 8 * 1024 * 1024;
+/// How many times an upload is resumed after a chunk fails
+const MAX_RESUMES: u32 = 3;
+const UPLOAD_OFFSET_HEADER: &str = "Upload-Offset";
+const UPLOAD_LENGTH_HEADER: &str = "Upload-Length";
+
+impl APIClient {
+    /// Uploads an artifact in chunks. If a chunk fails, the server is asked
+    /// how much of the artifact it has received and the upload resumes from
+    /// there, instead of starting over.
+    ///
+    /// The chunks are authenticated like spaces requests, i.e. with the
+    /// client's auth mode, preflight and request hook. Servers without
+    /// `RESUMABLE_UPLOADS_CAPABILITY` get the whole artifact in one request
+    /// with `put_artifact`.
+    pub async fn put_artifact_resumable(
+        &self,
+        hash: &str,
+        artifact_body: &[u8],
+        duration: u64,
+        tag: Option<&str>,
+        content_type: Option<&str>,
+        api_auth: &APIAuth,
+    ) -> Result<(), Error> {
+        if artifact_body.is_empty()
+            || !self
+                .has_capability(api_auth, RESUMABLE_UPLOADS_CAPABILITY)
+                .await
+        {
+            return self
+                .send_put_artifact(hash, artifact_body, duration, tag, content_type, api_auth)
+                .await;
+        }
+
+        let mut offset = 0;
+        let mut resumes = 0;
+        while offset < artifact_body.len() {
+            let end = (offset + CHUNK_SIZE).min(artifact_body.len());
+            let request_builder = self
+                .upload_request_builder(
+                    hash,
+                    Method::PATCH,
+                    api_auth,
+                    Some(artifact_body[offset..end].to_vec()),
+                )
+                .await?
+                .header("x-artifact-duration", duration.to_string())
+                .header(UPLOAD_OFFSET_HEADER, offset)
+                .header(UPLOAD_LENGTH_HEADER, artifact_body.len());
+            let request_builder = match tag {
+                Some(tag) => request_builder.header("x-artifact-tag", tag),
+                None => request_builder,
+            };
+            let request_builder = match content_type {
+                Some(content_type) => {
+                    request_builder.header("x-artifact-content-type", content_type)
+                }
+                None => request_builder,
+            };
+
+            match self.send_chunk(request_builder).await {
+                Ok(()) => offset = end,
+                Err(err) if resumes < MAX_RESUMES => {
+                    resumes += 1;
+                    offset = self
+                        .get_upload_offset(hash, api_auth)
+                        .await
+                        .map_err(|_| err)?
+                        .min(artifact_body.len());
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn send_chunk(&self, request_builder: RequestBuilder) -> Result<(), Error> {
+        let response = retry::make_retryable_request(request_builder, self).await?;
+        if response.status() == StatusCode::FORBIDDEN {
+            return Err(Self::handle_403(response).await);
+        }
+
+        response.error_for_status()?;
+        Ok(())
+    }
+
+    /// Asks the server how many bytes of an interrupted upload it received
+    async fn get_upload_offset(&self, hash: &str, api_auth: &APIAuth) -> Result<usize, Error> {
+        let request_builder = self
+            .upload_request_builder(hash, Method::HEAD, api_auth, None)
+            .await?;
+        let response = retry::make_retryable_request(request_builder, self)
+            .await?
+            .error_for_status()?;
+
+        let offset = response
+            .headers()
+            .get(UPLOAD_OFFSET_HEADER)
+            .map(|offset| offset.to_str())
+            .transpose()?
+            .and_then(|offset| offset.parse().ok())
+            .unwrap_or(0);
+
+        Ok(offset)
+    }
+
+    async fn upload_request_builder(
+        &self,
+        hash: &str,
+        method: Method,
+        api_auth: &APIAuth,
+        chunk: Option<Vec<u8>>,
+    ) -> Result<RequestBuilder, Error> {
+        let url = format!("/v8/artifacts/{}/upload", hash);
+        let request_builder = self
+            .create_request_builder(&url, api_auth, method, chunk)
+            .await?
+            .header("User-Agent", self.user_agent.clone());
+
+        // Replaces the JSON content type set by `create_request_builder`
+        let headers = HeaderMap::from_iter([(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/octet-stream"),
+        )]);
+        Ok(request_builder.headers(headers))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use anyhow::Result;
+    use turborepo_vercel_api::CapabilitiesResponse;
+    use turborepo_vercel_api_mock::start_test_server;
+
+    use super::{CHUNK_SIZE, RESUMABLE_UPLOADS_CAPABILITY};
+    use crate::{
+        testing::{test_auth, Canned, CannedServer},
+        APIAuth, APIClient, AuthMode, HmacAlgorithm,
+    };
+
+    #[tokio::test]
+    async fn test_falls_back_to_full_upload() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+        let client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0", false)?;
+        let api_auth = test_auth();
+
+        // The mock server doesn't advertise any capabilities
+        client
+            .put_artifact_resumable("resumable", b"artifact", 10, None, None, &api_auth)
+            .await?;
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_resumes_from_server_offset() -> Result<()> {
+        // The server only got the first 1000 bytes of the second chunk
+        let resumed_at = CHUNK_SIZE + 1000;
+        let patches = AtomicUsize::new(0);
+        let server = CannedServer::start(move |request| match request.method.as_str() {
+            "HEAD" => Canned::ok().header("upload-offset", resumed_at.to_string()),
+            _ if patches.fetch_add(1, Ordering::SeqCst) == 1 => Canned::status(500),
+            _ => Canned::ok(),
+        })
+        .await;
+        let client = server.client();
+        client.capabilities.set(Some(CapabilitiesResponse {
+            api_version: 1,
+            min_client_api_version: 1,
+            capabilities: vec![RESUMABLE_UPLOADS_CAPABILITY.to_string()],
+        }))?;
+        let api_auth = APIAuth {
+            mode: AuthMode::Hmac {
+                key: b"secret".to_vec(),
+                algorithm: HmacAlgorithm::Sha256,
+            },
+            ..test_auth()
+        };
+
+        let artifact: Vec<u8> = (0..2 * CHUNK_SIZE + 10).map(|i| i as u8).collect();
+        client
+            .put_artifact_resumable("hash", &artifact, 10, None, None, &api_auth)
+            .await?;
+
+        let requests = server.requests();
+        let sent: Vec<_> = requests
+            .iter()
+            .map(|request| {
+                (
+                    request.method.as_str(),
+                    request.header("upload-offset").map(str::to_string),
+                    request.body.len(),
+                )
+            })
+            .collect();
+        // The failed chunk is resumed from where the server says it stopped,
+        // and the last chunk ends the artifact
+        assert_eq!(
+            sent,
+            [
+                ("PATCH", Some("0".to_string()), CHUNK_SIZE),
+                ("PATCH", Some(CHUNK_SIZE.to_string()), CHUNK_SIZE),
+                ("HEAD", None, 0),
+                (
+                    "PATCH",
+                    Some(resumed_at.to_string()),
+                    artifact.len() - resumed_at
+                ),
+            ]
+        );
+        assert_eq!(requests[3].body, artifact[resumed_at..]);
+        for request in requests {
+            assert_eq!(request.path, "/v8/artifacts/hash/upload");
+            // Signed with the client's auth mode instead of the token
+            assert_eq!(request.header("authorization"), None);
+            assert!(request.header("x-turbo-signature").is_some());
+            if request.method == "PATCH" {
+                assert_eq!(
+                    request.header("upload-length"),
+                    Some(&*artifact.len().to_string())
+                );
+                assert_eq!(
+                    request.header("content-type"),
+                    Some("application/octet-stream")
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,45 +1,284 @@
-use reqwest::{RequestBuilder, Response, StatusCode};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use reqwest::{Method, Request, RequestBuilder, Response, StatusCode};
 use tokio::time::sleep;
+use tracing::debug;
 
-use crate::Error;
+use crate::{
+    failover,
+    timing::{BodySent, RequestTiming},
+    APIClient, Error,
+};
 
 const MIN_SLEEP_TIME_SECS: u64 = 2;
 const MAX_SLEEP_TIME_SECS: u64 = 10;
 const RETRY_MAX: u32 = 2;
 
+/// A POST that carries this header can be safely retried after a timeout,
+/// since the server uses the key to drop a repeat of a request it already
+/// processed.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Decides whether a request attempt should be retried, replacing the
+/// default decision based on the status code. Only the status and headers of
+/// a response can be inspected, since reading the body would consume it.
+pub type RetryDecider = Arc<dyn Fn(Result<&Response, &reqwest::Error>) -> bool + Send + Sync>;
+
 /// Retries a request until `RETRY_MAX` is reached, the `should_retry_request`
-/// function returns false, or the future succeeds. Uses an exponential backoff
-/// with a base of 2 to delay between retries.
+/// function, or the client's `RetryDecider` if it has one, returns false, or
+/// the future succeeds. Uses an exponential backoff with a base of 2 to delay
+/// between retries. Each retry is logged at debug level with the attempt, the
+/// error, the delay and the URL.
+///
+/// Each attempt is limited by the client's attempt timeout, and all attempts
+/// together by its retry budget. An attempt never runs past the budget, e.g.
+/// with an attempt timeout of 10s and a budget of 15s the second attempt gets
+/// at most the 5s that are left. Attempts that time out are only retried if
+/// there's an attempt timeout or a budget.
 ///
 /// # Arguments
 ///
 /// * `request_builder`: The request builder with everything, i.e. headers and
 ///   body already set. NOTE: This must be cloneable, so no streams are allowed.
+/// * `client`: The client making the request. Every attempt waits out the
+///   client's 429 cooldown, its rate limiter and its in-flight request cap, if
+///   any, and its request observer is notified with the timing of every
+///   attempt.
+///
+/// A POST without an `Idempotency-Key` header that times out isn't retried,
+/// since the server may have processed it already. It fails with
+/// `Error::AmbiguousWrite` instead, so the caller can decide whether it's
+/// safe to send it again.
 ///
 /// returns: Result<Response, Error>
 pub(crate) async fn make_retryable_request(
     request_builder: RequestBuilder,
+    client: &APIClient,
+) -> Result<Response, Error> {
+    let deadline = client.retry_budget.map(|budget| Instant::now() + budget);
+    make_retryable_request_with_deadline(request_builder, client, deadline).await
+}
+
+/// Like `make_retryable_request`, but stops retrying once `deadline` has
+/// passed, or would pass before the next attempt, even if attempts remain.
+/// The error from the last attempt is returned.
+pub(crate) async fn make_retryable_request_with_deadline(
+    request_builder: RequestBuilder,
+    client: &APIClient,
+    deadline: Option<Instant>,
+) -> Result<Response, Error> {
+    let build = || request_builder.try_clone().expect("cannot clone request");
+    retry_request(build, client, deadline).await
+}
+
+/// Like `make_retryable_request`, but also returns the number of attempts
+/// that were made
+pub(crate) async fn make_counted_request(
+    request_builder: RequestBuilder,
+    client: &APIClient,
+) -> (Result<Response, Error>, u32) {
+    let deadline = client.retry_budget.map(|budget| Instant::now() + budget);
+    let build = || request_builder.try_clone().expect("cannot clone request");
+    count_retried_request(build, client, deadline).await
+}
+
+/// Like `make_retryable_request`, but the request is built from scratch for
+/// every attempt. Use this for requests that can't be cloned, e.g. ones with
+/// a streaming body.
+pub(crate) async fn make_rebuildable_request(
+    build: impl Fn() -> RequestBuilder,
+    client: &APIClient,
+) -> Result<Response, Error> {
+    let deadline = client.retry_budget.map(|budget| Instant::now() + budget);
+    retry_request(build, client, deadline).await
+}
+
+async fn retry_request(
+    build: impl Fn() -> RequestBuilder,
+    client: &APIClient,
+    deadline: Option<Instant>,
 ) -> Result<Response, Error> {
-    let mut last_error = None;
+    count_retried_request(build, client, deadline).await.0
+}
+
+async fn count_retried_request(
+    build: impl Fn() -> RequestBuilder,
+    client: &APIClient,
+    deadline: Option<Instant>,
+) -> (Result<Response, Error>, u32) {
+    let mut attempts = 0;
+    let mut last_attempt = None;
+    let mut queued_since = Instant::now();
+    if client.network.is_offline() {
+        return (Err(Error::Offline), attempts);
+    }
     for retry_count in 0..RETRY_MAX {
-        let builder = request_builder.try_clone().expect("cannot clone request");
-        match builder.send().await {
-            Ok(value) => return Ok(value),
-            Err(err) => {
-                if !should_retry_request(&err) {
-                    return Err(err.into());
-                }
-                last_error = Some(err);
+        wait_to_send(client).await;
+        let (http_client, request) = build().build_split();
+        let mut request = match request {
+            Ok(request) => request,
+            Err(err) => return (Err(err.into()), attempts),
+        };
+        let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        let timeout = [
+            request.timeout().copied(),
+            client.attempt_timeout,
+            remaining,
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+        *request.timeout_mut() = timeout;
+        let url = request.url().clone();
+        let idempotent = request.method() != Method::POST
+            || request.headers().contains_key(IDEMPOTENCY_KEY_HEADER);
+
+        attempts += 1;
+        let result = send_attempt(client, &http_client, request, retry_count, queued_since).await;
+
+        // A timeout after connecting means the request may have been sent, so
+        // a POST can't be repeated without risking a duplicate
+        let result = match result {
+            Err(err) if !idempotent && err.is_timeout() && !err.is_connect() => {
+                debug!(attempt = retry_count, %url, "POST timed out, not retrying");
+                let url = url.to_string();
+                return (
+                    Err(Error::AmbiguousWrite {
+                        url,
+                        source: Box::new(err),
+                    }),
+                    attempts,
+                );
             }
+            result => result,
+        };
+
+        // A connect error marks the network offline, but the attempts of this
+        // request still run, in case it was only a blip. Timeouts are only
+        // retried if the attempts are bounded, otherwise each retry could
+        // wait for the client's whole timeout again.
+        let bounded = client.attempt_timeout.is_some() || deadline.is_some();
+        let retry = match (&client.retry_decider, &result) {
+            (Some(decider), result) => decider(result.as_ref()),
+            (None, Ok(_)) => false,
+            (None, Err(err)) => should_retry_request(err) || (bounded && err.is_timeout()),
+        };
+        if !retry {
+            return (result.map_err(Error::from), attempts);
+        }
+        let last = last_attempt.insert(result);
+
+        let sleep_period = Duration::from_secs(
+            (2_u64)
+                .pow(retry_count)
+                .clamp(MIN_SLEEP_TIME_SECS, MAX_SLEEP_TIME_SECS),
+        );
+        if deadline.is_some_and(|deadline| Instant::now() + sleep_period >= deadline) {
+            debug!(attempt = retry_count, %url, "retry budget exhausted, not retrying");
+            break;
+        }
+        let (status, error) = match &*last {
+            Ok(response) => (Some(response.status()), None),
+            Err(err) => (err.status(), Some(err)),
+        };
+        debug!(
+            attempt = retry_count,
+            error = error.map(tracing::field::display),
+            status = status.map(|status| status.as_u16()),
+            delay_secs = sleep_period.as_secs(),
+            %url,
+            "retrying request"
+        );
+        queued_since = Instant::now();
+        sleep(sleep_period).await;
+    }
+
+    // A response the decider wanted retried is still returned once the
+    // attempts run out, so the caller can handle it
+    let result = match last_attempt.expect("at least one attempt is made") {
+        Ok(response) => Ok(response),
+        Err(err) => Err(Error::TooManyFailures(Box::new(err))),
+    };
+    (result, attempts)
+}
+
+/// Waits out the client's 429 cooldown and its rate limiter before an attempt
+/// is built, so that its timeout doesn't count the wait
+pub(crate) async fn wait_to_send(client: &APIClient) {
+    client.cooldown.wait().await;
+    if let Some(rate_limiter) = &client.rate_limiter {
+        rate_limiter.acquire().await;
+    }
+}
+
+/// Sends one attempt of a request, after `wait_to_send`, the way every
+/// request of the client is sent: it waits for a slot of the in-flight
+/// request cap, is reported to the request observer, and is executed with
+/// failover and redirects. The result updates the client's
+/// cooldown and whether it considers the network offline.
+///
+/// Requests that handle failures themselves, e.g. the summary stream, call
+/// this directly instead of retrying.
+pub(crate) async fn send_attempt(
+    client: &APIClient,
+    http_client: &reqwest::Client,
+    request: Request,
+    attempt: u32,
+    queued_since: Instant,
+) -> reqwest::Result<Response> {
+    let (method, url) = (request.method().clone(), request.url().clone());
+    // Held until the response headers arrive, not while sleeping between
+    // attempts
+    let permit = match &client.in_flight {
+        Some(in_flight) => Some(
+            in_flight
+                .acquire()
+                .await
+                .expect("semaphore is never closed"),
+        ),
+        None => None,
+    };
+    let sent_at = Instant::now();
+    let body_sent = BodySent::default();
+    client.connection_counter.record_request();
+    let result = failover::execute(client, http_client, request, &body_sent).await;
+    let headers_at = Instant::now();
+    drop(permit);
+    match &result {
+        Ok(response) => {
+            client.network.mark_online();
+            client.cooldown.observe(response);
         }
+        Err(err) if err.is_connect() => client.network.mark_offline(client.offline_window),
+        Err(_) => {}
+    }
 
-        let sleep_period = (2_u64)
-            .pow(retry_count)
-            .clamp(MIN_SLEEP_TIME_SECS, MAX_SLEEP_TIME_SECS);
-        sleep(std::time::Duration::from_secs(sleep_period)).await;
+    if let Some(observer) = &client.request_observer {
+        // The body may still be written after the response arrived, e.g. if
+        // the server responded without reading it, in which case its send
+        // time is unknown
+        let body_sent_at = body_sent
+            .get()
+            .filter(|body_sent_at| *body_sent_at <= headers_at);
+        observer(&RequestTiming {
+            method,
+            url,
+            attempt,
+            status: match &result {
+                Ok(response) => Some(response.status()),
+                Err(err) => err.status(),
+            },
+            queued: sent_at - queued_since,
+            time_to_headers: headers_at - sent_at,
+            send: body_sent_at.map(|body_sent_at| body_sent_at - sent_at),
+            response_wait: headers_at - body_sent_at.unwrap_or(sent_at),
+        });
     }
 
-    Err(Error::TooManyFailures(Box::new(last_error.unwrap())))
+    result
 }
 
 fn should_retry_request(error: &reqwest::Error) -> bool {
@@ -55,3 +294,180 @@ fn should_retry_request(error: &reqwest::Error) -> bool {
 
     false
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use tokio::net::TcpListener;
+
+    use super::{
+        make_counted_request, make_retryable_request, send_attempt, IDEMPOTENCY_KEY_HEADER,
+    };
+    use crate::{
+        spaces::RunPatch,
+        testing::{start_hanging_server, test_auth, Canned, CannedServer},
+        APIClient, Client, Error,
+    };
+
+    #[tokio::test]
+    async fn test_attempt_timeout() -> anyhow::Result<()> {
+        let base_url = start_hanging_server().await.url();
+
+        // Each attempt times out after 100ms. The budget leaves no time to
+        // sleep before a retry, so the request fails after one attempt.
+        let client = APIClient::new(&base_url, 0, "2.0.0", false)?
+            .with_attempt_timeout(Duration::from_millis(100))
+            .with_retry_budget(Duration::from_secs(1));
+        let started = Instant::now();
+        let result = client.get_user("token").await;
+        assert!(matches!(result, Err(Error::TooManyFailures(err)) if err.is_timeout()));
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        // A generous attempt timeout is cut short by the budget
+        let client = APIClient::new(&base_url, 0, "2.0.0", false)?
+            .with_attempt_timeout(Duration::from_secs(10))
+            .with_retry_budget(Duration::from_millis(100));
+        let started = Instant::now();
+        assert!(client.get_user("token").await.is_err());
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unbounded_timeout_not_retried() -> anyhow::Result<()> {
+        let server = start_hanging_server().await;
+
+        // Without an attempt timeout or a budget, the client's timeout fails
+        // the request right away
+        let client = APIClient::new(server.url(), 1, "2.0.0", false)?;
+        let result = client.get_user("token").await;
+        assert!(matches!(result, Err(Error::ReqwestError(err)) if err.is_timeout()));
+        assert_eq!(server.requests().len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_offline_window() -> anyhow::Result<()> {
+        // Nothing listens on the port once the listener is dropped
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        drop(listener);
+        let base_url = format!("http://{}", addr);
+
+        let client = APIClient::new(&base_url, 0, "2.0.0", false)?
+            .with_offline_window(Duration::from_millis(300));
+        assert!(matches!(
+            client.get_user("token").await,
+            Err(Error::ReqwestError(err)) if err.is_connect()
+        ));
+        assert!(matches!(
+            client.get_user("token").await,
+            Err(Error::Offline)
+        ));
+
+        // Once the window has passed, requests are sent again
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(matches!(
+            client.get_user("token").await,
+            Err(Error::ReqwestError(err)) if err.is_connect()
+        ));
+
+        // Off by default
+        let client = APIClient::new(&base_url, 0, "2.0.0", false)?;
+        client.get_user("token").await.unwrap_err();
+        assert!(!matches!(
+            client.get_user("token").await,
+            Err(Error::Offline)
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_offline_cleared_by_success() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let unreachable = format!("http://{}", listener.local_addr()?);
+        drop(listener);
+        let server = CannedServer::always(Canned::ok()).await;
+        let client = server.client().with_offline_window(Duration::from_secs(60));
+        let patch = RunPatch {
+            name: Some("build web".to_string()),
+            ..Default::default()
+        };
+        let (space_id, run_id, api_auth) = ("space".into(), "run".into(), test_auth());
+        let update = || client.update_space_run(&space_id, &run_id, &api_auth, &patch);
+
+        let result = make_retryable_request(client.client.get(&unreachable), &client).await;
+        assert!(matches!(result, Err(Error::ReqwestError(err)) if err.is_connect()));
+        // Spaces calls fail fast too, without reaching the server
+        assert!(matches!(update().await, Err(Error::Offline)));
+        assert!(server.requests().is_empty());
+
+        // A request that gets through, e.g. a keep-alive ping, clears it
+        let ping = client.client.head(server.url()).build()?;
+        send_attempt(&client, &client.client, ping, 0, Instant::now()).await?;
+        update().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ambiguous_write() -> anyhow::Result<()> {
+        let base_url = start_hanging_server().await.url();
+        let client = APIClient::new(&base_url, 0, "2.0.0", false)?
+            .with_attempt_timeout(Duration::from_millis(100))
+            .with_retry_budget(Duration::from_secs(3));
+        let url = format!("{}/v0/spaces/space/runs", base_url);
+
+        // The POST may have reached the server, so it isn't retried
+        let started = Instant::now();
+        let result = make_retryable_request(client.client.post(&url), &client).await;
+        assert!(matches!(result, Err(Error::AmbiguousWrite { source, .. }) if source.is_timeout()));
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        // With an idempotency key it's retried like any other request
+        let request = client
+            .client
+            .post(&url)
+            .header(IDEMPOTENCY_KEY_HEADER, "key");
+        let result = make_retryable_request(request, &client).await;
+        assert!(matches!(result, Err(Error::TooManyFailures(err)) if err.is_timeout()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retry_decider() -> anyhow::Result<()> {
+        let server = CannedServer::always(Canned::ok()).await;
+        let attempts = |client: APIClient| async move {
+            let request_builder = client.client.get(client.make_url("/"));
+            make_counted_request(request_builder, &client).await.1
+        };
+
+        // A success isn't retried by default, but is if the decider says so
+        assert_eq!(attempts(server.client()).await, 1);
+        // The budget only saves the sleep after the last attempt
+        let client = server
+            .client()
+            .with_retry_budget(Duration::from_secs(3))
+            .with_retry_decider(|result| {
+                result.is_ok_and(|response| response.headers().contains_key("content-length"))
+            });
+        assert_eq!(attempts(client).await, 2);
+
+        // A timeout is retried by default, but not if the decider says so
+        let client = start_hanging_server()
+            .await
+            .client()
+            .with_attempt_timeout(Duration::from_millis(100))
+            .with_retry_decider(|_| false);
+        let started = Instant::now();
+        assert_eq!(attempts(client).await, 1);
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+
+use reqwest::{Method, StatusCode};
+use serde::Deserialize;
+
+use super::{casing::wire_casing, RunId, SpaceId, SpaceTaskSummary, SpacesMethod};
+use crate::{retry, APIAuth, APIClient, Error};
+
+/// The capability servers advertise if they accept several task summaries in
+/// one request, see `APIClient::upload_task_batch`
+pub const TASK_BATCHES_CAPABILITY: &str = "tasks:batch";
+
+/// What the server did with one summary of a batch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskUploadOutcome {
+    Accepted,
+    Rejected { reason: String },
+}
+
+wire_casing! {
+    constant,
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+    enum ItemStatus {
+        Accepted,
+        Rejected,
+    }
+}
+
+wire_casing! {
+    run_payload,
+    #[derive(Debug, Deserialize)]
+    struct ItemResult {
+        key: String,
+        status: ItemStatus,
+        #[serde(default)]
+        reason: Option<String>,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MultiStatus {
+    results: Vec<ItemResult>,
+}
+
+impl APIClient {
+    /// Uploads several task summaries to a run in a single request. The
+    /// server may accept some of them and reject others, e.g. because they
+    /// failed validation, which it reports with a 207 Multi-Status. Outcomes
+    /// are returned in the same order as `tasks`:
+    ///
+    /// - with a 207, each summary gets the outcome the server reported for its
+    ///   key. Summaries missing from the response are rejected.
+    /// - with any other 2xx, every summary was accepted
+    ///
+    /// Errors if the request itself fails, or if any summary is invalid
+    /// before it's sent, e.g. its metadata is too large.
+    ///
+    /// Falls back to `create_task_summaries` if the server doesn't advertise
+    /// `TASK_BATCHES_CAPABILITY`. A summary that fails to upload is rejected
+    /// with the error then.
+    pub async fn upload_task_batch(
+        &self,
+        space_id: &SpaceId,
+        run_id: &RunId,
+        api_auth: &APIAuth,
+        mut tasks: Vec<SpaceTaskSummary>,
+    ) -> Result<Vec<TaskUploadOutcome>, Error> {
+        if self.spaces_disabled() {
+            return Ok(vec![TaskUploadOutcome::Accepted; tasks.len()]);
+        }
+
+        self.check_space(space_id)?;
+        if !self.has_capability(api_auth, TASK_BATCHES_CAPABILITY).await {
+            let results = self
+                .create_task_summaries(space_id, run_id, api_auth, tasks)
+                .await;
+            return Ok(results
+                .into_iter()
+                .map(|result| match result {
+                    Ok(()) => TaskUploadOutcome::Accepted,
+                    Err(err) => TaskUploadOutcome::Rejected {
+                        reason: err.to_string(),
+                    },
+                })
+                .collect());
+        }
+
+        for task in &mut tasks {
+            task.check_metadata_size()?;
+            self.apply_task_time_policy(run_id, task)?;
+            self.apply_log_upload_policy(task);
+        }
+
+        let mut recorded = Vec::with_capacity(tasks.len());
+        for task in &tasks {
+            match self.record_task_key(run_id, &task.key) {
+                Ok(is_new) => recorded.push(is_new),
+                Err(err) => {
+                    // Nothing is uploaded, so the summaries before the
+                    // duplicate can be retried
+                    for (task, is_new) in tasks.iter().zip(recorded) {
+                        if is_new {
+                            self.forget_task_key(run_id, &task.key);
+                        }
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        let result = self
+            .cancellable_upload(
+                run_id,
+                self.send_task_batch(space_id, run_id, api_auth, &tasks),
+            )
+            .await;
+        // Retrying a failed or rejected summary isn't a duplicate
+        for (i, task) in tasks.iter().enumerate() {
+            let accepted = matches!(
+                result.as_ref().map(|outcomes| &outcomes[i]),
+                Ok(TaskUploadOutcome::Accepted)
+            );
+            if recorded[i] && !accepted {
+                self.forget_task_key(run_id, &task.key);
+            }
+        }
+
+        result
+    }
+
+    async fn send_task_batch(
+        &self,
+        space_id: &SpaceId,
+        run_id: &RunId,
+        api_auth: &APIAuth,
+        tasks: &[SpaceTaskSummary],
+    ) -> Result<Vec<TaskUploadOutcome>, Error> {
+        if tasks.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let _permit = self.acquire_spaces_slot(SpacesMethod::TaskSummary).await;
+        let request_builder = self
+            .create_request_builder_with_body(
+                SpacesMethod::TaskSummary,
+                &format!("/v0/spaces/{}/runs/{}/tasks/batch", space_id, run_id),
+                api_auth,
+                Method::POST,
+                self.encode_body(api_auth, &tasks).await?,
+            )
+            .await?;
+
+        let result = retry::make_retryable_request(request_builder, self).await;
+        self.record_spaces_outcome(&result);
+        let response = result?.error_for_status()?;
+        if response.status() != StatusCode::MULTI_STATUS {
+            return Ok(vec![TaskUploadOutcome::Accepted; tasks.len()]);
+        }
+
+        let MultiStatus { results } = response.json().await?;
+        let mut outcomes: HashMap<_, _> = results
+            .into_iter()
+            .map(|item| {
+                let outcome = match item.status {
+                    ItemStatus::Accepted => TaskUploadOutcome::Accepted,
+                    ItemStatus::Rejected => TaskUploadOutcome::Rejected {
+                        reason: item.reason.unwrap_or_default(),
+                    },
+                };
+                (item.key, outcome)
+            })
+            .collect();
+
+        Ok(tasks
+            .iter()
+            .map(|task| {
+                outcomes
+                    .remove(&task.key)
+                    .unwrap_or_else(|| TaskUploadOutcome::Rejected {
+                        reason: "missing from the server's response".to_string(),
+                    })
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+
+    use super::{TaskUploadOutcome, TASK_BATCHES_CAPABILITY};
+    use crate::{
+        spaces::{DuplicateTaskKeyPolicy, RunId, SpaceId, SpaceTaskSummary},
+        testing::{set_capabilities, test_auth, Canned, CannedServer},
+        Error,
+    };
+
+    #[tokio::test]
+    async fn test_upload_task_batch() -> Result<()> {
+        let api_auth = test_auth();
+        let tasks = || {
+            ["a#build", "b#build", "c#build"].map(|key| SpaceTaskSummary {
+                key: key.to_string(),
+                ..SpaceTaskSummary::default()
+            })
+        };
+        let upload = |server: CannedServer| {
+            let api_auth = &api_auth;
+            async move {
+                let client = server.client();
+                set_capabilities(&client, &[TASK_BATCHES_CAPABILITY]);
+                client
+                    .upload_task_batch(&"space".into(), &"run".into(), api_auth, tasks().into())
+                    .await
+            }
+        };
+
+        let server = CannedServer::always(Canned::status(207).body(
+            r#"{"results":[
+                {"key":"b#build","status":"REJECTED","reason":"exitCode is required"},
+                {"key":"a#build","status":"ACCEPTED"}
+            ]}"#,
+        ))
+        .await;
+        assert_eq!(
+            upload(server).await?,
+            [
+                TaskUploadOutcome::Accepted,
+                TaskUploadOutcome::Rejected {
+                    reason: "exitCode is required".to_string()
+                },
+                TaskUploadOutcome::Rejected {
+                    reason: "missing from the server's response".to_string()
+                },
+            ]
+        );
+
+        let server = CannedServer::always(Canned::ok()).await;
+        assert_eq!(upload(server).await?, vec![TaskUploadOutcome::Accepted; 3]);
+
+        // Without batches, each summary is uploaded on its own
+        let server = CannedServer::start(|request| {
+            if request.text().contains("b#build") {
+                Canned::status(400)
+            } else {
+                Canned::ok()
+            }
+        })
+        .await;
+        let client = server.client();
+        set_capabilities(&client, &[]);
+        let outcomes = client
+            .upload_task_batch(&"space".into(), &"run".into(), &api_auth, tasks().into())
+            .await?;
+        assert_eq!(outcomes[0], TaskUploadOutcome::Accepted);
+        assert!(matches!(outcomes[1], TaskUploadOutcome::Rejected { .. }));
+        assert_eq!(outcomes[2], TaskUploadOutcome::Accepted);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests
+            .iter()
+            .all(|request| request.path == "/v0/spaces/space/runs/run/tasks"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_in_batch_forgets_keys() -> Result<()> {
+        let server = CannedServer::always(Canned::ok()).await;
+        let client = server
+            .client()
+            .with_duplicate_task_key_policy(DuplicateTaskKeyPolicy::Fail);
+        set_capabilities(&client, &[TASK_BATCHES_CAPABILITY]);
+        let tasks = |keys: &[&str]| {
+            keys.iter()
+                .map(|key| SpaceTaskSummary {
+                    key: key.to_string(),
+                    ..SpaceTaskSummary::default()
+                })
+                .collect()
+        };
+        let (space_id, run_id, api_auth) =
+            (SpaceId::from("space"), RunId::from("run"), test_auth());
+        let upload = |keys| client.upload_task_batch(&space_id, &run_id, &api_auth, tasks(keys));
+
+        assert!(matches!(
+            upload(&["a#build", "b#build", "a#build"]).await,
+            Err(Error::DuplicateTaskKey { key }) if key == "a#build"
+        ));
+        assert!(server.requests().is_empty());
+        // The keys recorded before the duplicate was found were forgotten
+        assert_eq!(
+            upload(&["a#build", "b#build"]).await?,
+            vec![TaskUploadOutcome::Accepted; 2]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+use futures::{stream, StreamExt};
+
+use super::{FinishOutcome, RunId, RunPatch, SpaceId, SpaceTaskSummary};
+use crate::{APIAuth, APIClient, Error};
+
+// How many finish requests are in flight at once
+const MAX_CONCURRENT_FINISHES: usize = 8;
+// How many runs are tagged at once
+const MAX_CONCURRENT_TAGS: usize = 8;
+// How many task summaries are uploaded at once, unless uploads are
+// deterministic
+const MAX_CONCURRENT_TASK_UPLOADS: usize = 8;
+
+/// A run to finish with `finish_space_runs`
+#[derive(Debug, Clone)]
+pub struct RunToFinish {
+    pub space_id: SpaceId,
+    pub run_id: RunId,
+    pub end_time: i64,
+    pub exit_code: i32,
+}
+
+impl APIClient {
+    /// Finishes several runs concurrently, e.g. the per-package sub-runs of a
+    /// large monorepo build. A failure to finish one run doesn't stop the
+    /// others from being finished. Results are returned in the same order as
+    /// `runs`.
+    pub async fn finish_space_runs(
+        &self,
+        api_auth: &APIAuth,
+        runs: Vec<RunToFinish>,
+    ) -> Vec<Result<FinishOutcome, Error>> {
+        stream::iter(runs)
+            .map(|run| async move {
+                self.finish_space_run(
+                    &run.space_id,
+                    &run.run_id,
+                    api_auth,
+                    run.end_time,
+                    run.exit_code,
+                )
+                .await
+            })
+            .buffered(MAX_CONCURRENT_FINISHES)
+            .collect()
+            .await
+    }
+
+    /// Sets the tags of several runs of a space concurrently, e.g. to mark
+    /// the runs of a reverted commit. The tags replace each run's existing
+    /// tags. A failure to tag one run doesn't stop the others from being
+    /// tagged. Results are returned in the same order as `run_ids`.
+    pub async fn tag_runs(
+        &self,
+        space_id: &SpaceId,
+        run_ids: &[RunId],
+        api_auth: &APIAuth,
+        tags: &[String],
+    ) -> Vec<Result<(), Error>> {
+        let patch = RunPatch {
+            tags: Some(tags.to_vec()),
+            ..RunPatch::default()
+        };
+        stream::iter(run_ids)
+            .map(|run_id| self.update_space_run(space_id, run_id, api_auth, &patch))
+            .buffered(MAX_CONCURRENT_TAGS)
+            .collect()
+            .await
+    }
+
+    /// Uploads several task summaries to a run concurrently. A failure to
+    /// upload one summary doesn't stop the others. Results are returned in the
+    /// same order as `tasks`.
+    ///
+    /// The order the requests are sent in is nondeterministic, unless the
+    /// client was configured with `with_deterministic_uploads`, in which case
+    /// the summaries are uploaded one at a time in order.
+    pub async fn create_task_summaries(
+        &self,
+        space_id: &SpaceId,
+        run_id: &RunId,
+        api_auth: &APIAuth,
+        tasks: Vec<SpaceTaskSummary>,
+    ) -> Vec<Result<(), Error>> {
+        let concurrency = if self.deterministic_uploads {
+            1
+        } else {
+            MAX_CONCURRENT_TASK_UPLOADS
+        };
+        stream::iter(tasks)
+            .map(|task| self.create_task_summary(space_id, run_id, api_auth, task))
+            .buffered(concurrency)
+            .collect()
+            .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+    use turborepo_vercel_api_mock::{start_test_server, EXPECTED_SPACE_ID, EXPECTED_SPACE_RUN_ID};
+
+    use super::RunToFinish;
+    use crate::{spaces::FinishOutcome, testing::test_auth, APIClient};
+
+    #[tokio::test]
+    async fn test_finish_space_runs() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+        let base_url = format!("http://localhost:{}", port);
+
+        let client = APIClient::new(&base_url, 200, "2.0.0", true)?;
+        let api_auth = test_auth();
+
+        let run = |run_id: &str| RunToFinish {
+            space_id: EXPECTED_SPACE_ID.into(),
+            run_id: run_id.into(),
+            end_time: 0,
+            exit_code: 0,
+        };
+        let results = client
+            .finish_space_runs(
+                &api_auth,
+                vec![run("unknown_run_id"), run(EXPECTED_SPACE_RUN_ID)],
+            )
+            .await;
+
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_err());
+        assert!(matches!(results[1], Ok(FinishOutcome::Finished)));
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tag_runs() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+        let client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0", false)?;
+        let api_auth = test_auth();
+
+        let results = client
+            .tag_runs(
+                &EXPECTED_SPACE_ID.into(),
+                &[EXPECTED_SPACE_RUN_ID.into(), "unknown_run_id".into()],
+                &api_auth,
+                &["reverted".to_string()],
+            )
+            .await;
+
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_deterministic_task_uploads() -> Result<()> {
+        use crate::{spaces::SpaceTaskSummary, RequestRecorder};
+
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+        let recorder = RequestRecorder::new();
+        let client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0", false)?
+            .with_request_recorder(recorder.clone())
+            .with_deterministic_uploads(true);
+        let api_auth = test_auth();
+
+        let keys = ["a#build", "b#build", "c#build", "d#build"];
+        let tasks = keys
+            .iter()
+            .map(|key| SpaceTaskSummary {
+                key: key.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        let results = client
+            .create_task_summaries(
+                &EXPECTED_SPACE_ID.into(),
+                &EXPECTED_SPACE_RUN_ID.into(),
+                &api_auth,
+                tasks,
+            )
+            .await;
+        assert!(results.iter().all(Result::is_ok));
+
+        let sent: Vec<_> = recorder
+            .take()
+            .into_iter()
+            .filter_map(|request| Some(request.body?["key"].as_str()?.to_string()))
+            .collect();
+        assert_eq!(sent, keys);
+
+        handle.abort();
+        Ok(())
+    }
+}
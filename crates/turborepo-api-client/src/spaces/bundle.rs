@@ -0,0 +1,304 @@
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use turborepo_vercel_api::SpaceRun;
+use uuid::Uuid;
+
+use super::{
+    casing::wire_casing, CreateSpaceRunPayload, FinishSpaceRunPayload, RunId, SpaceId,
+    SpaceTaskSummary, SpacesMethod, DISABLED_RUN_ID,
+};
+use crate::{retry, APIAuth, APIClient, Error};
+
+/// The capability servers advertise if they accept a whole run in one
+/// request, see `APIClient::submit_complete_run`
+pub const COMPLETE_RUNS_CAPABILITY: &str = "runs:complete";
+
+wire_casing! {
+    run_payload,
+    /// Everything needed to upload a run to spaces at a later time, e.g. when
+    /// the original upload failed because the machine was offline. The bundle
+    /// doesn't contain a run id since a new run is created when it is replayed.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct RunBundle {
+        pub space_id: SpaceId,
+        pub create: CreateSpaceRunPayload,
+        pub tasks: Vec<SpaceTaskSummary>,
+        pub finish: FinishSpaceRunPayload,
+    }
+}
+
+/// The run created from a `RunBundle`, see `APIClient::upload_run_bundle`
+#[derive(Debug)]
+pub struct BundleUpload {
+    pub run: SpaceRun,
+    /// The keys of the task summaries that failed to upload, with the error.
+    /// The run was finished without them.
+    pub failed_tasks: Vec<(String, Error)>,
+}
+
+impl APIClient {
+    /// Replays a `RunBundle`: creates a new run, uploads every task summary
+    /// to it and then finishes it. The run is finished even if some of the
+    /// task summaries fail to upload, which are reported in the returned
+    /// `BundleUpload`. Errors creating or finishing the run are returned.
+    pub async fn upload_run_bundle(
+        &self,
+        api_auth: &APIAuth,
+        bundle: RunBundle,
+    ) -> Result<BundleUpload, Error> {
+        let RunBundle {
+            space_id,
+            create,
+            tasks,
+            finish,
+        } = bundle;
+
+        let mut run = self.create_space_run(&space_id, api_auth, create).await?;
+
+        let keys: Vec<_> = tasks.iter().map(|task| task.key.clone()).collect();
+        let failed_tasks = keys
+            .into_iter()
+            .zip(run.upload_tasks(tasks).await)
+            .filter_map(|(key, result)| result.err().map(|err| (key, err)))
+            .collect();
+
+        run.finish_with_payload(&finish).await?;
+
+        Ok(BundleUpload {
+            run: run.run().clone(),
+            failed_tasks,
+        })
+    }
+
+    /// Uploads a run that's already finished, e.g. a short run that was
+    /// buffered instead of streamed, in a single request instead of creating,
+    /// uploading and finishing it separately. Falls back to
+    /// `upload_run_bundle` if the server doesn't advertise
+    /// `COMPLETE_RUNS_CAPABILITY`.
+    ///
+    /// The run is created or rejected as a whole, so only the fallback can
+    /// report failed task summaries. Task logs aren't compressed
+    /// individually, the request body is gzipped instead.
+    pub async fn submit_complete_run(
+        &self,
+        api_auth: &APIAuth,
+        bundle: RunBundle,
+    ) -> Result<BundleUpload, Error> {
+        if self.spaces_disabled() {
+            return Ok(BundleUpload {
+                run: SpaceRun {
+                    id: DISABLED_RUN_ID.to_string(),
+                    url: String::new(),
+                },
+                failed_tasks: Vec::new(),
+            });
+        }
+
+        if !self
+            .has_capability(api_auth, COMPLETE_RUNS_CAPABILITY)
+            .await
+        {
+            return self.upload_run_bundle(api_auth, bundle).await;
+        }
+
+        let RunBundle {
+            space_id,
+            create,
+            tasks,
+            finish,
+        } = bundle;
+        self.check_space(&space_id)?;
+        create.check_turbo_config_hash()?;
+        for task in &tasks {
+            task.check_metadata_size()?;
+        }
+
+        // The run only gets an id once the server creates it, so duplicate
+        // keys are looked for under a placeholder. They're forgotten right
+        // after, since the request finishes the run.
+        let pending_run = RunId::from(format!("pending-{}", Uuid::new_v4()));
+        let result = tasks
+            .iter()
+            .try_for_each(|task| self.record_task_key(&pending_run, &task.key).map(|_| ()));
+        self.forget_run_task_keys(&pending_run);
+        result?;
+
+        let tasks = tasks
+            .into_iter()
+            .map(|mut task| {
+                if self.check_task_times(create.start_time, &task)? {
+                    task.clamp_to_run(create.start_time);
+                }
+                self.apply_log_upload_policy(&mut task);
+                self.encode_value(&task)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let body = json!({
+            "create": self.encode_value(&self.prepare_create_payload(create))?,
+            "tasks": tasks,
+            "finish": self.encode_value(&finish)?,
+        });
+
+        let _permit = self.acquire_spaces_slot(SpacesMethod::CompleteRun).await;
+        let request_builder = self
+            .create_request_builder_with_body(
+                SpacesMethod::CompleteRun,
+                &format!("/v0/spaces/{}/runs/complete", space_id),
+                api_auth,
+                Method::POST,
+                self.encode_body(api_auth, &body).await?,
+            )
+            .await?;
+
+        let result = retry::make_retryable_request(request_builder, self).await;
+        self.record_spaces_outcome(&result);
+        let response = result?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(self.space_not_found(&space_id));
+        }
+
+        Ok(BundleUpload {
+            run: response.error_for_status()?.json().await?,
+            failed_tasks: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+    use chrono::Local;
+    use turborepo_vercel_api::CapabilitiesResponse;
+    use turborepo_vercel_api_mock::{start_test_server, EXPECTED_SPACE_ID, EXPECTED_SPACE_RUN_ID};
+
+    use super::{RunBundle, COMPLETE_RUNS_CAPABILITY};
+    use crate::{
+        spaces::{
+            CreateSpaceRunPayload, DuplicateTaskKeyPolicy, FinishSpaceRunPayload, SpaceTaskSummary,
+        },
+        testing::{test_auth, Canned, CannedServer},
+        APIClient, Error,
+    };
+
+    fn bundle() -> RunBundle {
+        RunBundle {
+            space_id: EXPECTED_SPACE_ID.into(),
+            create: CreateSpaceRunPayload::new(
+                Local::now(),
+                "turbo run build",
+                None,
+                None,
+                None,
+                "".to_string(),
+                "".to_string(),
+            ),
+            tasks: vec![SpaceTaskSummary::default(), SpaceTaskSummary::default()],
+            finish: FinishSpaceRunPayload::new(Local::now().timestamp_millis(), 0),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upload_run_bundle() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+        let client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0", true)?;
+        let api_auth = test_auth();
+
+        let bundle = bundle();
+
+        // Bundles are persisted to disk, so make sure they survive a round trip
+        let bundle: RunBundle = serde_json::from_str(&serde_json::to_string(&bundle)?)?;
+
+        let upload = client.upload_run_bundle(&api_auth, bundle).await?;
+        assert_eq!(upload.run.id, EXPECTED_SPACE_RUN_ID);
+        assert!(upload.failed_tasks.is_empty());
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upload_run_bundle_reports_failed_tasks() -> Result<()> {
+        let server = CannedServer::start(|request| match request.path.as_str() {
+            path if path.ends_with("/runs") => Canned::json(r#"{"id":"run","url":""}"#),
+            path if path.ends_with("/tasks") && request.text().contains("b#build") => {
+                Canned::status(400)
+            }
+            _ => Canned::ok(),
+        })
+        .await;
+        let bundle = RunBundle {
+            tasks: ["a#build", "b#build"]
+                .map(|key| SpaceTaskSummary {
+                    key: key.to_string(),
+                    ..SpaceTaskSummary::default()
+                })
+                .into(),
+            ..bundle()
+        };
+
+        let upload = server
+            .client()
+            .upload_run_bundle(&test_auth(), bundle)
+            .await?;
+        // The run was still created and finished
+        assert_eq!(upload.run.id, "run");
+        let failed: Vec<_> = upload.failed_tasks.iter().map(|(key, _)| key).collect();
+        assert_eq!(failed, ["b#build"]);
+        assert_eq!(server.requests().last().unwrap().method, "PATCH");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_submit_complete_run() -> Result<()> {
+        let api_auth = test_auth();
+
+        // The mock server doesn't advertise the capability, so the run is
+        // uploaded in three steps
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+        let client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0", true)?;
+        let upload = client.submit_complete_run(&api_auth, bundle()).await?;
+        assert_eq!(upload.run.id, EXPECTED_SPACE_RUN_ID);
+        handle.abort();
+
+        // A server that only has the combined endpoint
+        let server = CannedServer::always(Canned::json(r#"{"id":"complete","url":""}"#)).await;
+        let client = server.client();
+        client.capabilities.set(Some(CapabilitiesResponse {
+            api_version: 1,
+            min_client_api_version: 1,
+            capabilities: vec![COMPLETE_RUNS_CAPABILITY.to_string()],
+        }))?;
+        let upload = client.submit_complete_run(&api_auth, bundle()).await?;
+        assert_eq!(upload.run.id, "complete");
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(
+            requests[0].path,
+            format!("/v0/spaces/{}/runs/complete", EXPECTED_SPACE_ID)
+        );
+        assert!(requests[0].text().contains("\"tasks\""));
+
+        // The bundle's two summaries have the same key
+        let client = server
+            .client()
+            .with_duplicate_task_key_policy(DuplicateTaskKeyPolicy::Fail);
+        client.capabilities.set(Some(CapabilitiesResponse {
+            api_version: 1,
+            min_client_api_version: 1,
+            capabilities: vec![COMPLETE_RUNS_CAPABILITY.to_string()],
+        }))?;
+        assert!(matches!(
+            client.submit_complete_run(&api_auth, bundle()).await,
+            Err(Error::DuplicateTaskKey { .. })
+        ));
+        assert_eq!(server.requests().len(), 1);
+
+        Ok(())
+    }
+}
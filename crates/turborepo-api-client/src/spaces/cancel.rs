@@ -0,0 +1,142 @@
+use std::{collections::HashMap, future::Future, sync::Mutex};
+
+use tokio::sync::watch;
+
+use super::RunId;
+use crate::{APIClient, Error};
+
+struct RunUploadState {
+    pending: usize,
+    cancel: watch::Sender<()>,
+}
+
+/// The task uploads that are queued or in flight for each run, so they can
+/// be cancelled with `APIClient::cancel_run_uploads`
+#[derive(Default)]
+pub(crate) struct RunUploads {
+    runs: Mutex<HashMap<RunId, RunUploadState>>,
+}
+
+impl RunUploads {
+    fn register(&self, run_id: &RunId) -> watch::Receiver<()> {
+        let mut runs = self.runs.lock().expect("run uploads lock poisoned");
+        let state = runs
+            .entry(run_id.clone())
+            .or_insert_with(|| RunUploadState {
+                pending: 0,
+                cancel: watch::channel(()).0,
+            });
+        state.pending += 1;
+        state.cancel.subscribe()
+    }
+
+    fn release(&self, run_id: &RunId) {
+        let mut runs = self.runs.lock().expect("run uploads lock poisoned");
+        if let Some(state) = runs.get_mut(run_id) {
+            state.pending -= 1;
+            if state.pending == 0 {
+                runs.remove(run_id);
+            }
+        }
+    }
+}
+
+// Releases an upload when it completes or is dropped
+struct PendingUpload<'a> {
+    uploads: &'a RunUploads,
+    run_id: &'a RunId,
+}
+
+impl Drop for PendingUpload<'_> {
+    fn drop(&mut self) {
+        self.uploads.release(self.run_id);
+    }
+}
+
+impl APIClient {
+    /// Cancels the task summary and log uploads of a run that are queued or
+    /// in flight, e.g. because the run turned out to be uploaded to the wrong
+    /// space. The cancelled uploads fail with `Error::UploadCancelled`, while
+    /// the uploads of other runs and later uploads of this run are
+    /// unaffected. Returns how many uploads were cancelled.
+    pub fn cancel_run_uploads(&self, run_id: &RunId) -> usize {
+        let runs = self
+            .run_uploads
+            .runs
+            .lock()
+            .expect("run uploads lock poisoned");
+        let Some(state) = runs.get(run_id) else {
+            return 0;
+        };
+        state.cancel.send_replace(());
+        state.pending
+    }
+
+    /// Runs an upload for `run_id` so that `cancel_run_uploads` can cancel it
+    pub(crate) async fn cancellable_upload<T>(
+        &self,
+        run_id: &RunId,
+        upload: impl Future<Output = Result<T, Error>>,
+    ) -> Result<T, Error> {
+        let mut cancelled = self.run_uploads.register(run_id);
+        let _pending = PendingUpload {
+            uploads: &self.run_uploads,
+            run_id,
+        };
+
+        tokio::select! {
+            result = upload => result,
+            _ = cancelled.changed() => Err(Error::UploadCancelled {
+                run_id: run_id.to_string(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::{
+        spaces::{RunId, SpaceTaskSummary},
+        testing::{start_hanging_server, test_auth},
+        APIClient, Error,
+    };
+
+    #[tokio::test]
+    async fn test_cancel_run_uploads() -> anyhow::Result<()> {
+        // Uploads stay in flight, since the server never responds
+        let server = start_hanging_server().await;
+        let client = APIClient::new(server.url(), 0, "2.0.0", false)?;
+        let api_auth = test_auth();
+        let upload = |run_id: &'static str, key: &'static str| {
+            let (client, api_auth) = (client.clone(), api_auth.clone());
+            tokio::spawn(async move {
+                let task = SpaceTaskSummary {
+                    key: key.to_string(),
+                    ..SpaceTaskSummary::default()
+                };
+                client
+                    .create_task_summary(&"space".into(), &run_id.into(), &api_auth, task)
+                    .await
+            })
+        };
+
+        let cancelled = [upload("bad", "a#build"), upload("bad", "b#build")];
+        let other = upload("good", "a#build");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(client.cancel_run_uploads(&RunId::from("bad")), 2);
+        for upload in cancelled {
+            assert!(matches!(
+                upload.await?,
+                Err(Error::UploadCancelled { run_id }) if run_id == "bad"
+            ));
+        }
+        assert_eq!(client.cancel_run_uploads(&RunId::from("bad")), 0);
+        assert!(!other.is_finished());
+        other.abort();
+
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+//! The casing conventions of the spaces API. Every type sent to or read
+//! from spaces declares which convention it follows through `wire_casing!`
+//! instead of its own `rename_all`, so new types can't drift from the ones
+//! next to them:
+//!
+//! - `run_payload`: run level payloads use camelCase keys
+//! - `task_payload`: task summaries use snake_case keys
+//! - `state`: states, e.g. a run's status, are lowercase
+//! - `constant`: constants, e.g. a run's type or a cache source, are UPPERCASE
+//!
+//! `PayloadDialect` and `CacheSourceCasing` adjust the encoded payload for
+//! servers that expect something else, they don't change these conventions.
+
+/// Applies one of the spaces casing conventions to a struct or enum. The
+/// item must derive `Serialize` and/or `Deserialize`.
+macro_rules! wire_casing {
+    (run_payload, $(#[$meta:meta])* $vis:vis $kind:ident $name:ident $($body:tt)*) => {
+        $(#[$meta])*
+        #[serde(rename_all = "camelCase")]
+        $vis $kind $name $($body)*
+    };
+    (task_payload, $(#[$meta:meta])* $vis:vis $kind:ident $name:ident $($body:tt)*) => {
+        $(#[$meta])*
+        #[serde(rename_all = "snake_case")]
+        $vis $kind $name $($body)*
+    };
+    (state, $(#[$meta:meta])* $vis:vis $kind:ident $name:ident $($body:tt)*) => {
+        $(#[$meta])*
+        #[serde(rename_all = "lowercase")]
+        $vis $kind $name $($body)*
+    };
+    (constant, $(#[$meta:meta])* $vis:vis $kind:ident $name:ident $($body:tt)*) => {
+        $(#[$meta])*
+        #[serde(rename_all = "UPPERCASE")]
+        $vis $kind $name $($body)*
+    };
+}
+
+pub(crate) use wire_casing;
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use chrono::Local;
+    use serde_json::json;
+
+    use crate::spaces::{
+        CacheSource, CreateSpaceRunPayload, FinishSpaceRunPayload, RunPatch, RunStatus,
+        SpaceRunType, SpaceTaskSummary, SpacesCacheStatus,
+    };
+
+    #[test]
+    fn test_enum_wire_format() -> Result<()> {
+        assert_eq!(serde_json::to_value(RunStatus::Running)?, json!("running"));
+        assert_eq!(
+            serde_json::to_value(RunStatus::Completed)?,
+            json!("completed")
+        );
+        assert_eq!(serde_json::to_value(SpaceRunType::Turbo)?, json!("TURBO"));
+        assert_eq!(serde_json::to_value(CacheSource::Local)?, json!("LOCAL"));
+        assert_eq!(serde_json::to_value(CacheSource::Remote)?, json!("REMOTE"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_payload_wire_format() -> Result<()> {
+        let create = serde_json::to_value(CreateSpaceRunPayload::new(
+            Local::now(),
+            "turbo run build",
+            None,
+            None,
+            None,
+            "".to_string(),
+            "user".to_string(),
+        ))?;
+        assert!(create.get("startTime").is_some());
+        assert!(create.get("gitBranch").is_some());
+        assert!(create.get("originationUser").is_some());
+        assert_eq!(create["type"], json!("TURBO"));
+        assert!(create.get("expiresAt").is_none());
+        assert!(create["client"].get("nodeVersion").is_none());
+
+        let ephemeral = CreateSpaceRunPayload::new(
+            Local::now(),
+            "turbo run build",
+            None,
+            None,
+            None,
+            "".to_string(),
+            "".to_string(),
+        )
+        .ephemeral(Duration::from_secs(60));
+        assert_eq!(ephemeral.expires_at, Some(ephemeral.start_time + 60_000));
+        assert!(serde_json::to_value(ephemeral)?.get("expiresAt").is_some());
+
+        let finish = serde_json::to_value(FinishSpaceRunPayload::new(1, 0))?;
+        assert!(finish.get("endTime").is_some());
+        assert!(finish.get("exitCode").is_some());
+
+        let patch = RunPatch {
+            description: Some("".to_string()),
+            ..Default::default()
+        };
+        assert!(serde_json::to_value(patch)?.get("description").is_some());
+
+        let task = serde_json::to_value(SpaceTaskSummary {
+            cache: SpacesCacheStatus::default(),
+            peak_memory_bytes: Some(u64::MAX),
+            ..Default::default()
+        })?;
+        assert!(task.get("start_time").is_some());
+        assert!(task.get("exit_code").is_some());
+        assert!(task["cache"].get("time_saved").is_some());
+        assert_eq!(task["peakMemoryBytes"], json!(u64::MAX));
+        assert!(task.get("cpuTimeMs").is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,342 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use reqwest::{
+    header::{HeaderMap, HeaderValue, CONTENT_ENCODING, CONTENT_TYPE},
+    Method, RequestBuilder,
+};
+use uuid::Uuid;
+
+use super::{
+    format::{BodyFormat, EncodedBody},
+    SpaceTaskSummary, SpacesMethod,
+};
+use crate::{APIAuth, APIClient, Error, IDEMPOTENCY_KEY_HEADER};
+
+/// The header naming the encoding of a task summary's `logs`. Compressed
+/// logs are sent base64 encoded.
+pub const LOGS_ENCODING_HEADER: &str = "x-turbo-logs-encoding";
+
+/// The capability servers advertise if they accept gzip compressed spaces
+/// request bodies
+pub const GZIP_REQUESTS_CAPABILITY: &str = "request-encoding:gzip";
+
+/// Per-method overrides of `SpacesMethod::default_compression_threshold`
+pub(crate) type CompressionThresholds = HashMap<SpacesMethod, Option<usize>>;
+
+impl SpacesMethod {
+    /// The smallest request body, in bytes, that's gzipped for this method,
+    /// or `None` if its bodies are never compressed. Task summaries and logs
+    /// can be large and compress well, while run payloads are small enough
+    /// that compressing them costs more than it saves.
+    pub fn default_compression_threshold(&self) -> Option<usize> {
+        match self {
+            SpacesMethod::TaskSummary | SpacesMethod::TaskLogs | SpacesMethod::CompleteRun => {
+                Some(1024)
+            }
+            SpacesMethod::CreateRun => Some(8 * 1024),
+            SpacesMethod::FinishRun
+            | SpacesMethod::UpdateRun
+            | SpacesMethod::RunDiagnostics
+            | SpacesMethod::GetTask
+            | SpacesMethod::ListRuns
+            | SpacesMethod::Stats => None,
+        }
+    }
+}
+
+/// A compression algorithm for task logs. Servers advertise the ones they
+/// accept as `logs-encoding:<name>` capabilities.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogCompression {
+    Gzip,
+    /// Usually the smallest for log text
+    Zstd,
+    Brotli,
+}
+
+impl LogCompression {
+    /// The name of the encoding, as used in `LOGS_ENCODING_HEADER`
+    pub fn name(&self) -> &'static str {
+        match self {
+            LogCompression::Gzip => "gzip",
+            LogCompression::Zstd => "zstd",
+            LogCompression::Brotli => "br",
+        }
+    }
+
+    fn capability(&self) -> String {
+        format!("logs-encoding:{}", self.name())
+    }
+
+    fn compress(&self, logs: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            LogCompression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(logs)?;
+                encoder.finish()
+            }
+            LogCompression::Zstd => zstd::encode_all(logs, 0),
+            LogCompression::Brotli => {
+                let mut compressed = Vec::new();
+                {
+                    let mut encoder = brotli::CompressorWriter::new(&mut compressed, 4096, 9, 22);
+                    encoder.write_all(logs)?;
+                }
+                Ok(compressed)
+            }
+        }
+    }
+}
+
+impl APIClient {
+    /// Compresses the task's logs with whichever of the preferred algorithms
+    /// the server accepts gives the smallest payload, ties going to the
+    /// earlier preference. The logs are left uncompressed if the server
+    /// accepts none of them or compressing doesn't make them smaller.
+    ///
+    /// Returns the algorithm the logs were compressed with, if any.
+    pub(crate) async fn compress_task_logs(
+        &self,
+        api_auth: &APIAuth,
+        task: &mut SpaceTaskSummary,
+    ) -> Option<LogCompression> {
+        if task.logs.is_empty() || self.log_compression.is_empty() {
+            return None;
+        }
+
+        let mut best: Option<(LogCompression, String)> = None;
+        for &compression in &self.log_compression {
+            if !self
+                .has_capability(api_auth, &compression.capability())
+                .await
+            {
+                continue;
+            }
+            let Ok(compressed) = compression.compress(task.logs.as_bytes()) else {
+                continue;
+            };
+            let encoded = BASE64_STANDARD.encode(compressed);
+            if best
+                .as_ref()
+                .map_or(true, |(_, smallest)| encoded.len() < smallest.len())
+            {
+                best = Some((compression, encoded));
+            }
+        }
+
+        let (compression, encoded) = best?;
+        if encoded.len() >= task.logs.len() {
+            return None;
+        }
+        task.logs = encoded;
+
+        Some(compression)
+    }
+}
+
+impl APIClient {
+    /// Like `create_request_builder`, but the body is gzipped if it's at
+    /// least `spaces_method`'s compression threshold and the server accepts
+    /// compressed bodies. Signatures cover the compressed body. The
+    /// `Content-Type` matches the body's format.
+    ///
+    /// Each call gets its own `IDEMPOTENCY_KEY_HEADER`, which every attempt of
+    /// the request resends, so a POST that timed out can be retried without
+    /// creating a duplicate.
+    pub(crate) async fn create_request_builder_with_body(
+        &self,
+        spaces_method: SpacesMethod,
+        url: &str,
+        api_auth: &APIAuth,
+        method: Method,
+        body: impl Into<EncodedBody>,
+    ) -> Result<RequestBuilder, Error> {
+        let EncodedBody {
+            bytes: body,
+            format,
+        } = body.into();
+        let threshold = self
+            .compression_thresholds
+            .get(&spaces_method)
+            .copied()
+            .unwrap_or_else(|| spaces_method.default_compression_threshold());
+        let compressed = match threshold {
+            Some(threshold)
+                if body.len() >= threshold
+                    && self
+                        .has_capability(api_auth, GZIP_REQUESTS_CAPABILITY)
+                        .await =>
+            {
+                LogCompression::Gzip
+                    .compress(&body)
+                    .ok()
+                    .filter(|compressed| compressed.len() < body.len())
+            }
+            _ => None,
+        };
+
+        let request_builder = match compressed {
+            Some(compressed) => self
+                .create_request_builder(url, api_auth, method, Some(compressed))
+                .await?
+                .header(CONTENT_ENCODING, "gzip"),
+            None => {
+                self.create_request_builder(url, api_auth, method, Some(body))
+                    .await?
+            }
+        };
+
+        let request_builder =
+            request_builder.header(IDEMPOTENCY_KEY_HEADER, Uuid::new_v4().to_string());
+
+        if format == BodyFormat::Json {
+            return Ok(request_builder);
+        }
+        // Replaces the JSON content type set by `create_request_builder`
+        let headers = HeaderMap::from_iter([(
+            CONTENT_TYPE,
+            HeaderValue::from_static(format.content_type()),
+        )]);
+        Ok(request_builder.headers(headers))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+
+    use base64::{prelude::BASE64_STANDARD, Engine};
+    use reqwest::Method;
+    use turborepo_vercel_api::CapabilitiesResponse;
+
+    use super::{LogCompression, GZIP_REQUESTS_CAPABILITY};
+    use crate::{
+        spaces::{SpaceTaskSummary, SpacesMethod},
+        testing::test_auth,
+        APIClient,
+    };
+
+    #[test]
+    fn test_compression_round_trips() -> anyhow::Result<()> {
+        let logs = "> turbo run build\n".repeat(100);
+
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(&*LogCompression::Gzip.compress(logs.as_bytes())?)
+            .read_to_string(&mut decompressed)?;
+        assert_eq!(decompressed, logs);
+
+        let decompressed = zstd::decode_all(&*LogCompression::Zstd.compress(logs.as_bytes())?)?;
+        assert_eq!(decompressed, logs.as_bytes());
+
+        let mut decompressed = String::new();
+        brotli::Decompressor::new(&*LogCompression::Brotli.compress(logs.as_bytes())?, 4096)
+            .read_to_string(&mut decompressed)?;
+        assert_eq!(decompressed, logs);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_compression_thresholds() -> anyhow::Result<()> {
+        let client = APIClient::new("http://localhost", 0, "2.0.0", false)?
+            .with_compression_threshold(SpacesMethod::FinishRun, Some(0));
+        client.capabilities.set(Some(CapabilitiesResponse {
+            api_version: 1,
+            min_client_api_version: 1,
+            capabilities: vec![GZIP_REQUESTS_CAPABILITY.to_string()],
+        }))?;
+        let api_auth = test_auth();
+        let body = "{}".repeat(1024).into_bytes();
+
+        let encoding = |method| {
+            let client = &client;
+            let api_auth = &api_auth;
+            let body = body.clone();
+            async move {
+                let request = client
+                    .create_request_builder_with_body(method, "/", api_auth, Method::POST, body)
+                    .await?
+                    .build()?;
+                anyhow::Ok(request.headers().get("content-encoding").cloned())
+            }
+        };
+        assert_eq!(encoding(SpacesMethod::TaskSummary).await?.unwrap(), "gzip");
+        // Overridden to compress everything
+        assert_eq!(encoding(SpacesMethod::FinishRun).await?.unwrap(), "gzip");
+        assert!(encoding(SpacesMethod::UpdateRun).await?.is_none());
+
+        Ok(())
+    }
+
+    fn client_accepting(capabilities: &[&str]) -> anyhow::Result<APIClient> {
+        let client =
+            APIClient::new("http://localhost", 0, "2.0.0", false)?.with_log_compression(vec![
+                LogCompression::Gzip,
+                LogCompression::Zstd,
+                LogCompression::Brotli,
+            ]);
+        client.capabilities.set(Some(CapabilitiesResponse {
+            api_version: 1,
+            min_client_api_version: 1,
+            capabilities: capabilities.iter().map(|name| name.to_string()).collect(),
+        }))?;
+        Ok(client)
+    }
+
+    #[tokio::test]
+    async fn test_compress_task_logs() -> anyhow::Result<()> {
+        let logs = "> turbo run build\nbuilding...\n".repeat(200);
+        let task = || SpaceTaskSummary {
+            logs: logs.clone(),
+            ..SpaceTaskSummary::default()
+        };
+        let api_auth = test_auth();
+
+        // Zstd isn't advertised, so the smaller of gzip and brotli is picked
+        let client = client_accepting(&["logs-encoding:gzip", "logs-encoding:br"])?;
+        let encoded_len = |compression: LogCompression| -> anyhow::Result<usize> {
+            Ok(BASE64_STANDARD
+                .encode(compression.compress(logs.as_bytes())?)
+                .len())
+        };
+        let expected = if encoded_len(LogCompression::Brotli)? < encoded_len(LogCompression::Gzip)?
+        {
+            LogCompression::Brotli
+        } else {
+            LogCompression::Gzip
+        };
+        let mut compressed = task();
+        assert_eq!(
+            client.compress_task_logs(&api_auth, &mut compressed).await,
+            Some(expected)
+        );
+        assert_eq!(compressed.logs.len(), encoded_len(expected)?);
+
+        // Left as is if the server accepts none of the preferences
+        let client = client_accepting(&[GZIP_REQUESTS_CAPABILITY])?;
+        let mut uncompressed = task();
+        assert_eq!(
+            client
+                .compress_task_logs(&api_auth, &mut uncompressed)
+                .await,
+            None
+        );
+        assert_eq!(uncompressed.logs, logs);
+
+        // Or if compressing doesn't make the logs smaller
+        let client = client_accepting(&["logs-encoding:gzip"])?;
+        let mut short = SpaceTaskSummary {
+            logs: "ok".to_string(),
+            ..SpaceTaskSummary::default()
+        };
+        assert_eq!(client.compress_task_logs(&api_auth, &mut short).await, None);
+        assert_eq!(short.logs, "ok");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+use reqwest::Method;
+use serde::Serialize;
+
+use super::{casing::wire_casing, is_zero, RunId, SpaceId, SpacesMethod};
+use crate::{retry, APIAuth, APIClient, Error};
+
+/// The most diagnostics sent for a run. The rest are reported as a count.
+pub const MAX_RUN_DIAGNOSTICS: usize = 100;
+
+/// The longest diagnostic message sent, in bytes. Longer messages are cut
+/// off.
+pub const MAX_DIAGNOSTIC_MESSAGE_BYTES: usize = 2048;
+
+wire_casing! {
+    state,
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+    pub enum DiagnosticSeverity {
+        Error,
+        Warning,
+        Info,
+    }
+}
+
+wire_casing! {
+    run_payload,
+    /// A warning or error `turbo` itself reported during a run, e.g. for a
+    /// deprecated config option, as opposed to the logs of a task
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+    pub struct RunDiagnostic {
+        pub severity: DiagnosticSeverity,
+        /// A stable identifier of the kind of diagnostic, e.g.
+        /// `deprecated-config`, so the dashboard can group them
+        pub code: String,
+        pub message: String,
+    }
+}
+
+wire_casing! {
+    run_payload,
+    #[derive(Debug, Serialize)]
+    struct RunDiagnosticsPayload {
+        diagnostics: Vec<RunDiagnostic>,
+        #[serde(skip_serializing_if = "is_zero")]
+        omitted_diagnostics: usize,
+    }
+}
+
+impl RunDiagnosticsPayload {
+    fn new(mut diagnostics: Vec<RunDiagnostic>) -> Self {
+        let omitted_diagnostics = diagnostics.len().saturating_sub(MAX_RUN_DIAGNOSTICS);
+        diagnostics.truncate(MAX_RUN_DIAGNOSTICS);
+        for diagnostic in &mut diagnostics {
+            if diagnostic.message.len() > MAX_DIAGNOSTIC_MESSAGE_BYTES {
+                let mut end = MAX_DIAGNOSTIC_MESSAGE_BYTES;
+                while !diagnostic.message.is_char_boundary(end) {
+                    end -= 1;
+                }
+                diagnostic.message.truncate(end);
+            }
+        }
+
+        Self {
+            diagnostics,
+            omitted_diagnostics,
+        }
+    }
+}
+
+impl APIClient {
+    /// Reports the warnings and errors `turbo` ran into during a run, so the
+    /// dashboard can show them with the run. Only the first
+    /// `MAX_RUN_DIAGNOSTICS` are sent and messages are cut off at
+    /// `MAX_DIAGNOSTIC_MESSAGE_BYTES`, the rest are reported as a count.
+    pub async fn report_run_diagnostics(
+        &self,
+        space_id: &SpaceId,
+        run_id: &RunId,
+        api_auth: &APIAuth,
+        diagnostics: Vec<RunDiagnostic>,
+    ) -> Result<(), Error> {
+        if self.spaces_disabled() || diagnostics.is_empty() {
+            return Ok(());
+        }
+
+        self.check_space(space_id)?;
+
+        let _permit = self.acquire_spaces_slot(SpacesMethod::RunDiagnostics).await;
+        let request_builder = self
+            .create_request_builder_with_body(
+                SpacesMethod::RunDiagnostics,
+                &format!("/v0/spaces/{}/runs/{}/diagnostics", space_id, run_id),
+                api_auth,
+                Method::POST,
+                self.encode_payload(&RunDiagnosticsPayload::new(diagnostics))?,
+            )
+            .await?;
+
+        let result = retry::make_retryable_request(request_builder, self).await;
+        self.record_spaces_outcome(&result);
+        result?.error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{
+        DiagnosticSeverity, RunDiagnostic, RunDiagnosticsPayload, MAX_DIAGNOSTIC_MESSAGE_BYTES,
+        MAX_RUN_DIAGNOSTICS,
+    };
+
+    #[test]
+    fn test_diagnostics_are_capped() -> anyhow::Result<()> {
+        let diagnostic = |message: String| RunDiagnostic {
+            severity: DiagnosticSeverity::Warning,
+            code: "deprecated-config".to_string(),
+            message,
+        };
+
+        let payload =
+            RunDiagnosticsPayload::new(vec![diagnostic("`pipeline` is deprecated".into())]);
+        assert_eq!(
+            serde_json::to_value(payload)?,
+            json!({
+                "diagnostics": [{
+                    "severity": "warning",
+                    "code": "deprecated-config",
+                    "message": "`pipeline` is deprecated",
+                }],
+            })
+        );
+
+        // Messages are cut off on a character boundary
+        let long = "é".repeat(MAX_DIAGNOSTIC_MESSAGE_BYTES);
+        let payload = RunDiagnosticsPayload::new(vec![diagnostic(long); MAX_RUN_DIAGNOSTICS + 3]);
+        assert_eq!(payload.diagnostics.len(), MAX_RUN_DIAGNOSTICS);
+        assert_eq!(payload.omitted_diagnostics, 3);
+        assert_eq!(
+            payload.diagnostics[0].message.len(),
+            MAX_DIAGNOSTIC_MESSAGE_BYTES
+        );
+
+        Ok(())
+    }
+}
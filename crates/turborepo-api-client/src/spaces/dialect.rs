@@ -0,0 +1,129 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use super::{logs::omit_empty_logs, CacheSourceCasing};
+use crate::{APIClient, Error};
+
+/// The field naming used for spaces payloads. Some community forks of the
+/// dashboard server expect snake_case field names.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PayloadDialect {
+    /// The field names used by the official dashboard, which are mostly
+    /// camelCase
+    #[default]
+    CamelCase,
+    SnakeCase,
+}
+
+// Fields whose contents are provided by the caller, so their keys are left as
+// is
+pub(crate) const OPAQUE_FIELDS: &[&str] = &["metadata"];
+
+impl PayloadDialect {
+    pub(crate) fn apply(&self, value: &mut Value) {
+        match self {
+            PayloadDialect::CamelCase => {}
+            PayloadDialect::SnakeCase => rename_keys(value, &to_snake_case),
+        }
+    }
+}
+
+fn rename_keys(value: &mut Value, rename: &dyn Fn(&str) -> String) {
+    match value {
+        Value::Object(map) => {
+            *map = std::mem::take(map)
+                .into_iter()
+                .map(|(key, mut value)| {
+                    if !OPAQUE_FIELDS.contains(&key.as_str()) {
+                        rename_keys(&mut value, rename);
+                    }
+                    (rename(&key), value)
+                })
+                .collect();
+        }
+        Value::Array(values) => {
+            for value in values {
+                rename_keys(value, rename);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn to_snake_case(key: &str) -> String {
+    let mut snake = String::with_capacity(key.len() + 4);
+    for c in key.chars() {
+        if c.is_ascii_uppercase() {
+            if !snake.is_empty() {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+impl APIClient {
+    /// Serializes a spaces payload with the client's dialect and casing
+    /// settings applied.
+    pub(crate) fn encode_payload(&self, payload: &impl Serialize) -> Result<Vec<u8>, Error> {
+        Ok(self.buffer_pool.encode_json(&self.encode_value(payload)?)?)
+    }
+
+    /// Like `encode_payload`, but returns the JSON value, e.g. to embed the
+    /// payload in a larger one
+    pub(crate) fn encode_value(&self, payload: &impl Serialize) -> Result<Value, Error> {
+        let mut value = serde_json::to_value(payload)?;
+        self.correct_timestamps(&mut value);
+        if self.cache_source_casing == CacheSourceCasing::Lowercase {
+            if let Some(source) = value.pointer_mut("/cache/source") {
+                if let Some(lowercase) = source.as_str().map(str::to_lowercase) {
+                    *source = lowercase.into();
+                }
+            }
+        }
+        if self.streamed_logs {
+            omit_empty_logs(&mut value);
+        }
+        self.payload_dialect.apply(&mut value);
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{to_snake_case, PayloadDialect};
+
+    #[test]
+    fn test_to_snake_case() {
+        assert_eq!(to_snake_case("originationUser"), "origination_user");
+        assert_eq!(to_snake_case("start_time"), "start_time");
+        assert_eq!(to_snake_case("type"), "type");
+    }
+
+    #[test]
+    fn test_snake_case_dialect() {
+        let mut value = json!({
+            "startTime": 1,
+            "client": { "id": "turbo" },
+            "tasks": [{ "exitCode": 0 }],
+            "metadata": { "testCount": 3 },
+        });
+        PayloadDialect::SnakeCase.apply(&mut value);
+
+        assert_eq!(
+            value,
+            json!({
+                "start_time": 1,
+                "client": { "id": "turbo" },
+                "tasks": [{ "exit_code": 0 }],
+                "metadata": { "testCount": 3 },
+            })
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use turborepo_vercel_api::SpaceRun;
+
+use super::{
+    casing::wire_casing, CreateSpaceRunPayload, FinishSpaceRunPayload, RunBundle, SpaceId,
+    SpaceTaskSummary,
+};
+
+/// The version of the `RunDocument` format. Bumped whenever the format
+/// changes in a way older readers can't handle.
+pub const RUN_DOCUMENT_SCHEMA_VERSION: u32 = 1;
+
+wire_casing! {
+    run_payload,
+    /// A complete run as one self-contained JSON document, for archiving or
+    /// analyzing runs independently of the dashboard. A finished document can
+    /// be uploaded again with `into_bundle` and `upload_run_bundle`.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct RunDocument {
+        pub schema_version: u32,
+        pub space_id: SpaceId,
+        /// The run as created on the server. `None` for documents built from
+        /// a bundle that wasn't uploaded.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub run: Option<SpaceRun>,
+        pub create: CreateSpaceRunPayload,
+        pub tasks: Vec<SpaceTaskSummary>,
+        /// `None` while the run is in progress
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub finish: Option<FinishSpaceRunPayload>,
+    }
+}
+
+impl RunDocument {
+    /// Converts a finished document to a bundle that can be replayed to
+    /// create the run again. Returns `None` if the run isn't finished.
+    pub fn into_bundle(self) -> Option<RunBundle> {
+        Some(RunBundle {
+            space_id: self.space_id,
+            create: self.create,
+            tasks: self.tasks,
+            finish: self.finish?,
+        })
+    }
+}
+
+impl From<RunBundle> for RunDocument {
+    fn from(bundle: RunBundle) -> Self {
+        Self {
+            schema_version: RUN_DOCUMENT_SCHEMA_VERSION,
+            space_id: bundle.space_id,
+            run: None,
+            create: bundle.create,
+            tasks: bundle.tasks,
+            finish: Some(bundle.finish),
+        }
+    }
+}
+
+/// What a `SpaceSession` has uploaded so far, when run recording is enabled
+/// with `APIClient::with_run_recording`. The recording outlives the session,
+/// so the run can still be exported once it's finished.
+#[derive(Debug, Clone)]
+pub struct RunRecording {
+    document: Arc<Mutex<RunDocument>>,
+}
+
+impl RunRecording {
+    pub(crate) fn new(space_id: &SpaceId, run: &SpaceRun, create: CreateSpaceRunPayload) -> Self {
+        Self {
+            document: Arc::new(Mutex::new(RunDocument {
+                schema_version: RUN_DOCUMENT_SCHEMA_VERSION,
+                space_id: space_id.clone(),
+                run: Some(run.clone()),
+                create,
+                tasks: Vec::new(),
+                finish: None,
+            })),
+        }
+    }
+
+    pub(crate) fn record_task(&self, task: SpaceTaskSummary) {
+        self.document
+            .lock()
+            .expect("recording lock poisoned")
+            .tasks
+            .push(task);
+    }
+
+    pub(crate) fn record_finish(&self, finish: &FinishSpaceRunPayload) {
+        self.document
+            .lock()
+            .expect("recording lock poisoned")
+            .finish = Some(finish.clone());
+    }
+
+    /// Returns the run as uploaded so far: its create payload, the task
+    /// summaries that were uploaded successfully and, once the run is
+    /// finished, its finish payload.
+    pub fn export_run_document(&self) -> RunDocument {
+        self.document
+            .lock()
+            .expect("recording lock poisoned")
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+    use chrono::Local;
+    use turborepo_vercel_api_mock::{start_test_server, EXPECTED_SPACE_ID};
+
+    use super::{RunDocument, RUN_DOCUMENT_SCHEMA_VERSION};
+    use crate::{
+        spaces::{CreateSpaceRunPayload, SpaceTaskSummary},
+        testing::test_auth,
+        APIClient,
+    };
+
+    #[tokio::test]
+    async fn test_export_run_document() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+        let client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0", false)?
+            .with_run_recording(true);
+        let api_auth = test_auth();
+
+        let payload = CreateSpaceRunPayload::new(
+            Local::now(),
+            "turbo run build",
+            None,
+            None,
+            None,
+            "".to_string(),
+            "".to_string(),
+        );
+        let session = client
+            .create_space_run(&EXPECTED_SPACE_ID.into(), &api_auth, payload)
+            .await?;
+        session
+            .upload_task(SpaceTaskSummary {
+                key: "web#build".to_string(),
+                ..SpaceTaskSummary::default()
+            })
+            .await?;
+        let recording = session.recording().cloned().unwrap();
+        assert!(recording.export_run_document().finish.is_none());
+
+        session.finish(0, 0).await?;
+        let document = recording.export_run_document();
+        assert_eq!(document.schema_version, RUN_DOCUMENT_SCHEMA_VERSION);
+        assert_eq!(document.tasks.len(), 1);
+        assert!(document.run.is_some());
+
+        // Documents round trip through JSON and can be replayed
+        let json = serde_json::to_string(&document)?;
+        let document: RunDocument = serde_json::from_str(&json)?;
+        let bundle = document.into_bundle().unwrap();
+        assert_eq!(bundle.tasks[0].key, "web#build");
+
+        handle.abort();
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::{HashMap, HashSet};
+
+use super::RunId;
+use crate::{APIClient, Error, Warning};
+
+/// How a task summary is handled when another summary with the same key was
+/// already uploaded to the run. The server keeps only one of them, so a
+/// duplicate key usually means a bug in how keys are generated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicateTaskKeyPolicy {
+    /// Upload the summary anyway and report a `Warning::DuplicateTaskKey`
+    #[default]
+    Warn,
+    /// Don't upload the summary and return `Error::DuplicateTaskKey`
+    Fail,
+}
+
+/// The keys of the task summaries uploaded to each open run
+pub(crate) type TaskKeys = HashMap<RunId, HashSet<String>>;
+
+impl APIClient {
+    /// Records that a summary with `key` is being uploaded to the run,
+    /// applying the duplicate key policy if one already was. Returns whether
+    /// the key was recorded, i.e. it's not a duplicate.
+    pub(crate) fn record_task_key(&self, run_id: &RunId, key: &str) -> Result<bool, Error> {
+        let is_new = self
+            .task_keys
+            .lock()
+            .expect("task keys lock poisoned")
+            .entry(run_id.clone())
+            .or_default()
+            .insert(key.to_string());
+        if is_new {
+            return Ok(true);
+        }
+
+        match self.duplicate_task_key_policy {
+            DuplicateTaskKeyPolicy::Warn => {
+                self.warn(Warning::DuplicateTaskKey {
+                    key: key.to_string(),
+                });
+                Ok(false)
+            }
+            DuplicateTaskKeyPolicy::Fail => Err(Error::DuplicateTaskKey {
+                key: key.to_string(),
+            }),
+        }
+    }
+
+    /// Forgets a key recorded by `record_task_key` because the upload failed
+    /// and may be retried
+    pub(crate) fn forget_task_key(&self, run_id: &RunId, key: &str) {
+        if let Some(keys) = self
+            .task_keys
+            .lock()
+            .expect("task keys lock poisoned")
+            .get_mut(run_id)
+        {
+            keys.remove(key);
+        }
+    }
+
+    /// Forgets all the keys of a run once it's finished
+    pub(crate) fn forget_run_task_keys(&self, run_id: &RunId) {
+        self.task_keys
+            .lock()
+            .expect("task keys lock poisoned")
+            .remove(run_id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DuplicateTaskKeyPolicy;
+    use crate::{
+        spaces::{RunId, SpaceTaskSummary},
+        testing::{test_auth, Canned, CannedServer},
+        APIClient, Error, Warning, WarningSink,
+    };
+
+    #[test]
+    fn test_duplicate_task_keys() -> anyhow::Result<()> {
+        let warnings = WarningSink::new();
+        let client =
+            APIClient::new("http://localhost", 0, "2.0.0", false)?.with_warnings(warnings.clone());
+        let run_id = RunId::from("run");
+
+        assert!(client.record_task_key(&run_id, "web#build")?);
+        assert!(client.record_task_key(&run_id, "docs#build")?);
+        assert!(warnings.take().is_empty());
+        assert!(!client.record_task_key(&run_id, "web#build")?);
+        assert_eq!(
+            warnings.take(),
+            vec![Warning::DuplicateTaskKey {
+                key: "web#build".to_string()
+            }]
+        );
+
+        // Another run can have the same keys
+        assert!(client.record_task_key(&RunId::from("other"), "web#build")?);
+
+        let client = client.with_duplicate_task_key_policy(DuplicateTaskKeyPolicy::Fail);
+        assert!(matches!(
+            client.record_task_key(&run_id, "docs#build"),
+            Err(Error::DuplicateTaskKey { key }) if key == "docs#build"
+        ));
+
+        client.forget_task_key(&run_id, "docs#build");
+        assert!(client.record_task_key(&run_id, "docs#build")?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fail_on_duplicate_upload() -> anyhow::Result<()> {
+        let server = CannedServer::always(Canned::ok()).await;
+        let client = server
+            .client()
+            .with_duplicate_task_key_policy(DuplicateTaskKeyPolicy::Fail);
+        let (space_id, run_id, api_auth) = ("space".into(), RunId::from("run"), test_auth());
+        let upload = || {
+            let task = SpaceTaskSummary {
+                key: "web#build".to_string(),
+                ..SpaceTaskSummary::default()
+            };
+            client.create_task_summary(&space_id, &run_id, &api_auth, task)
+        };
+
+        upload().await?;
+        assert!(matches!(
+            upload().await,
+            Err(Error::DuplicateTaskKey { key }) if key == "web#build"
+        ));
+        // The duplicate isn't sent
+        let uploads = server
+            .requests()
+            .into_iter()
+            .filter(|request| request.method == "POST")
+            .count();
+        assert_eq!(uploads, 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+use std::{env, thread};
+
+use serde::{Deserialize, Serialize};
+
+use super::{casing::wire_casing, CreateSpaceRunPayload};
+
+wire_casing! {
+    run_payload,
+    /// Facts about the machine a run happened on, so runs that only fail in
+    /// some environments can be told apart. Only facts that can't be secret
+    /// are collected: environment variables are reported by name when
+    /// they're set, their values are never included.
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct EnvironmentFingerprint {
+        /// The operating system, e.g. `linux`
+        pub os: String,
+        /// The CPU architecture, e.g. `aarch64`
+        pub arch: String,
+        pub cpu_count: usize,
+        /// The CI vendor the run happened on, e.g. `GITHUB_ACTIONS`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub ci: Option<String>,
+        /// Which of the variables passed to `collect` are set, sorted
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub env_vars: Vec<String>,
+    }
+}
+
+impl EnvironmentFingerprint {
+    /// Collects the fingerprint of the current machine. `env_vars` are the
+    /// names of the variables whose presence is relevant, e.g. the ones in
+    /// the run's `globalEnv`.
+    pub fn collect<'a>(env_vars: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut env_vars: Vec<String> = env_vars
+            .into_iter()
+            .filter(|name| env::var_os(name).is_some())
+            .map(str::to_string)
+            .collect();
+        env_vars.sort();
+        env_vars.dedup();
+
+        Self {
+            os: env::consts::OS.to_string(),
+            arch: env::consts::ARCH.to_string(),
+            cpu_count: thread::available_parallelism().map_or(1, usize::from),
+            ci: turborepo_ci::Vendor::get_constant().map(str::to_string),
+            env_vars,
+        }
+    }
+}
+
+impl CreateSpaceRunPayload {
+    /// Sends an `EnvironmentFingerprint` with the run. Servers that don't
+    /// know it ignore it.
+    pub fn with_environment(mut self, environment: EnvironmentFingerprint) -> Self {
+        self.environment = Some(environment);
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use super::EnvironmentFingerprint;
+
+    #[test]
+    fn test_environment_fingerprint() -> anyhow::Result<()> {
+        env::set_var("TURBO_FINGERPRINT_TEST_TOKEN", "super-secret-value");
+        env::remove_var("TURBO_FINGERPRINT_TEST_UNSET");
+
+        let fingerprint = EnvironmentFingerprint::collect([
+            "TURBO_FINGERPRINT_TEST_UNSET",
+            "TURBO_FINGERPRINT_TEST_TOKEN",
+        ]);
+        assert_eq!(fingerprint.os, env::consts::OS);
+        assert!(fingerprint.cpu_count >= 1);
+        assert_eq!(fingerprint.env_vars, ["TURBO_FINGERPRINT_TEST_TOKEN"]);
+
+        let json = serde_json::to_string(&fingerprint)?;
+        assert!(json.contains("\"envVars\":[\"TURBO_FINGERPRINT_TEST_TOKEN\"]"));
+        assert!(!json.contains("super-secret-value"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,307 @@
+use std::time::{Duration, Instant};
+
+use reqwest::RequestBuilder;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use turbopath::AbsoluteSystemPathBuf;
+
+use super::{casing::wire_casing, FinishOutcome, FinishSpaceRunPayload, RunId, SpaceId};
+use crate::{retry, APIAuth, APIClient, Error, Warning};
+
+/// How finishing a run retries server errors. Runs are finished during
+/// teardown, so retries are few and quick. A run that can't be finished
+/// stays "Running" on the dashboard, see `APIClient::with_deferred_finishes`
+/// to finish it on a later invocation instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FinishRetryPolicy {
+    /// The number of times a 5xx is retried
+    pub retries: u32,
+    /// The delay between retries
+    pub delay: Duration,
+}
+
+impl Default for FinishRetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 2,
+            delay: Duration::from_millis(500),
+        }
+    }
+}
+
+wire_casing! {
+    run_payload,
+    /// A finish that failed, stored to be retried by `finish_deferred_runs`.
+    /// The credentials aren't stored, they're passed again when retrying.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct DeferredFinish {
+        space_id: SpaceId,
+        run_id: RunId,
+        payload: FinishSpaceRunPayload,
+    }
+}
+
+/// Whether a finish that failed with `err` could succeed later
+fn is_transient(err: &Error) -> bool {
+    match err {
+        Error::ReqwestError(err) => err.status().map_or(true, |status| status.is_server_error()),
+        Error::TooManyFailures(_)
+        | Error::ConnectionError(_)
+        | Error::Offline
+        | Error::AmbiguousWrite { .. } => true,
+        _ => false,
+    }
+}
+
+impl APIClient {
+    /// Sends a finish request, retrying 5xx responses and ambiguous writes
+    /// according to the client's `FinishRetryPolicy` until `deadline`. If the
+    /// run still can't be finished and deferred finishes are enabled, the
+    /// finish is stored to be retried later.
+    pub(crate) async fn send_finish_request(
+        &self,
+        request_builder: RequestBuilder,
+        space_id: &SpaceId,
+        run_id: &RunId,
+        payload: &FinishSpaceRunPayload,
+        deadline: Instant,
+    ) -> Result<FinishOutcome, Error> {
+        let mut retries_left = self.finish_retry_policy.retries;
+        let result = loop {
+            let request_builder = request_builder.try_clone().expect("cannot clone request");
+            let result = match retry::make_retryable_request_with_deadline(
+                request_builder,
+                self,
+                Some(deadline),
+            )
+            .await
+            {
+                Ok(response) => response.error_for_status().map_err(Error::from),
+                Err(err) => Err(err),
+            };
+            // Finishing a run twice is harmless, so a finish that may have
+            // been processed is retried too
+            let retryable = match &result {
+                Err(Error::ReqwestError(err)) => {
+                    err.status().is_some_and(|status| status.is_server_error())
+                }
+                Err(Error::AmbiguousWrite { .. }) => true,
+                _ => false,
+            };
+            match result {
+                Err(err)
+                    if retryable
+                        && retries_left > 0
+                        && Instant::now() + self.finish_retry_policy.delay < deadline =>
+                {
+                    debug!(%run_id, error = %err, "retrying finish");
+                    retries_left -= 1;
+                    tokio::time::sleep(self.finish_retry_policy.delay).await;
+                }
+                result => break result,
+            }
+        };
+
+        match (result, &self.deferred_finish_dir) {
+            (Ok(_), _) => Ok(FinishOutcome::Finished),
+            (Err(err), Some(dir)) if is_transient(&err) => {
+                debug!(%run_id, error = %err, "deferring finish");
+                self.defer_finish(dir, space_id, run_id, payload).await?;
+                self.warn(Warning::FinishDeferred {
+                    run_id: run_id.to_string(),
+                });
+                Ok(FinishOutcome::Deferred)
+            }
+            (Err(err), _) => Err(err),
+        }
+    }
+
+    async fn defer_finish(
+        &self,
+        dir: &AbsoluteSystemPathBuf,
+        space_id: &SpaceId,
+        run_id: &RunId,
+        payload: &FinishSpaceRunPayload,
+    ) -> Result<(), Error> {
+        let deferred = DeferredFinish {
+            space_id: space_id.clone(),
+            run_id: run_id.clone(),
+            payload: payload.clone(),
+        };
+        tokio::fs::create_dir_all(dir.as_std_path())
+            .await
+            .map_err(Error::FileError)?;
+        let path = dir.join_component(&deferred_file_name(run_id));
+        tokio::fs::write(path.as_std_path(), serde_json::to_vec(&deferred)?)
+            .await
+            .map_err(Error::FileError)?;
+        Ok(())
+    }
+
+    /// Retries the finishes that were deferred by an earlier invocation, see
+    /// `with_deferred_finishes`. This should be called once on startup, with
+    /// credentials for the team that owns the runs.
+    ///
+    /// Returns the outcome for each deferred run. Runs that are finished, or
+    /// that failed with an error that retrying won't fix, are forgotten.
+    /// Others stay deferred until the next call.
+    pub async fn finish_deferred_runs(
+        &self,
+        api_auth: &APIAuth,
+    ) -> Vec<(RunId, Result<FinishOutcome, Error>)> {
+        let Some(dir) = &self.deferred_finish_dir else {
+            return Vec::new();
+        };
+        let Ok(mut entries) = tokio::fs::read_dir(dir.as_std_path()).await else {
+            return Vec::new();
+        };
+
+        let mut outcomes = Vec::new();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            if path
+                .extension()
+                .map_or(true, |extension| extension != "json")
+            {
+                continue;
+            }
+            let deferred = match tokio::fs::read(&path)
+                .await
+                .map(|contents| serde_json::from_slice::<DeferredFinish>(&contents))
+            {
+                Ok(Ok(deferred)) => deferred,
+                Ok(Err(err)) => {
+                    debug!(path = %path.display(), error = %err, "removing invalid deferred finish");
+                    let _ = tokio::fs::remove_file(&path).await;
+                    continue;
+                }
+                Err(_) => continue,
+            };
+
+            // Removed first, since it's written again if the finish is
+            // deferred again
+            let _ = tokio::fs::remove_file(&path).await;
+            let result = self
+                .send_finish_payload(
+                    &deferred.space_id,
+                    &deferred.run_id,
+                    api_auth,
+                    &deferred.payload,
+                )
+                .await;
+            outcomes.push((deferred.run_id, result));
+        }
+
+        outcomes
+    }
+}
+
+/// Run ids come from the server, so anything but alphanumerics is replaced
+/// to keep the file inside the directory
+fn deferred_file_name(run_id: &RunId) -> String {
+    let name: String = run_id
+        .as_str()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{name}.json")
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::{Duration, Instant},
+    };
+
+    use anyhow::Result;
+    use turbopath::AbsoluteSystemPathBuf;
+
+    use super::FinishRetryPolicy;
+    use crate::{
+        spaces::{FinishOutcome, RunId, SpaceId},
+        testing::{test_auth, Canned, CannedServer},
+    };
+
+    /// Starts a server that responds with a 503 to the first `failures`
+    /// requests and with a 200 afterwards
+    async fn start_failing_server(failures: usize) -> CannedServer {
+        let requests = AtomicUsize::new(0);
+        CannedServer::start(move |_| {
+            if requests.fetch_add(1, Ordering::SeqCst) < failures {
+                Canned::status(503)
+            } else {
+                Canned::ok()
+            }
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_deferred_finish() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dir = AbsoluteSystemPathBuf::try_from(dir.path())?;
+        let client = start_failing_server(4)
+            .await
+            .client()
+            .with_finish_retry_policy(FinishRetryPolicy {
+                retries: 1,
+                delay: Duration::ZERO,
+            })
+            .with_deferred_finishes(dir);
+        let api_auth = test_auth();
+        let (space_id, run_id) = (SpaceId::from("space"), RunId::from("run"));
+
+        // Both attempts fail, so the finish is kept for later
+        assert_eq!(
+            client
+                .finish_space_run(&space_id, &run_id, &api_auth, 0, 0)
+                .await?,
+            FinishOutcome::Deferred
+        );
+
+        // The first retry fails again, and is deferred again
+        let outcomes = client.finish_deferred_runs(&api_auth).await;
+        assert_eq!(outcomes.len(), 1);
+        assert!(matches!(outcomes[0], (_, Ok(FinishOutcome::Deferred))));
+
+        let outcomes = client.finish_deferred_runs(&api_auth).await;
+        assert_eq!(outcomes[0].0, run_id);
+        assert!(matches!(outcomes[0].1, Ok(FinishOutcome::Finished)));
+        assert!(client.finish_deferred_runs(&api_auth).await.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_finish_timeout_bounds_retries() -> Result<()> {
+        let server =
+            CannedServer::always(Canned::status(503).delay(Duration::from_millis(100))).await;
+        let client = server
+            .client()
+            .with_finish_timeout(Duration::from_millis(300))
+            .with_finish_retry_policy(FinishRetryPolicy {
+                retries: 10,
+                delay: Duration::ZERO,
+            });
+        let (space_id, run_id) = (SpaceId::from("space"), RunId::from("run"));
+
+        let started_at = Instant::now();
+        let result = client
+            .finish_space_run(&space_id, &run_id, &test_auth(), 0, 0)
+            .await;
+        assert!(result.is_err());
+        // The retries stop once the finish timeout is up, rather than each
+        // attempt getting its own
+        assert!(started_at.elapsed() < Duration::from_secs(1));
+        assert!(server.requests().len() <= 4);
+
+        Ok(())
+    }
+}
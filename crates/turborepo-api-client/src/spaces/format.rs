@@ -0,0 +1,107 @@
+use serde::Serialize;
+
+use crate::{APIAuth, APIClient, Error};
+
+/// The capability servers advertise if they accept MessagePack encoded
+/// spaces request bodies
+pub const MSGPACK_CAPABILITY: &str = "request-format:msgpack";
+
+/// The format large spaces request bodies, i.e. run creations and task
+/// summaries, are sent in. The official dashboard only accepts JSON.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BodyFormat {
+    #[default]
+    Json,
+    /// MessagePack, if the server advertises `MSGPACK_CAPABILITY`, and JSON
+    /// otherwise. Smaller and cheaper to encode than JSON for large
+    /// summaries.
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+}
+
+impl BodyFormat {
+    pub(crate) fn content_type(&self) -> &'static str {
+        match self {
+            BodyFormat::Json => "application/json",
+            #[cfg(feature = "msgpack")]
+            BodyFormat::MessagePack => "application/msgpack",
+        }
+    }
+}
+
+/// A serialized request body and the format it's in
+pub(crate) struct EncodedBody {
+    pub(crate) bytes: Vec<u8>,
+    pub(crate) format: BodyFormat,
+}
+
+impl From<Vec<u8>> for EncodedBody {
+    fn from(json: Vec<u8>) -> Self {
+        Self {
+            bytes: json,
+            format: BodyFormat::Json,
+        }
+    }
+}
+
+impl APIClient {
+    /// Like `encode_payload`, but in the client's `BodyFormat` if the server
+    /// accepts it
+    pub(crate) async fn encode_body(
+        &self,
+        api_auth: &APIAuth,
+        payload: &impl Serialize,
+    ) -> Result<EncodedBody, Error> {
+        #[cfg(feature = "msgpack")]
+        if self.body_format == BodyFormat::MessagePack
+            && self.has_capability(api_auth, MSGPACK_CAPABILITY).await
+        {
+            // Encoded from the same value as JSON, so the casing and dialect
+            // are applied
+            return Ok(EncodedBody {
+                bytes: rmp_serde::to_vec_named(&self.encode_value(payload)?)?,
+                format: BodyFormat::MessagePack,
+            });
+        }
+        #[cfg(not(feature = "msgpack"))]
+        let _ = api_auth;
+
+        Ok(self.encode_payload(payload)?.into())
+    }
+}
+
+#[cfg(all(test, feature = "msgpack"))]
+mod test {
+    use serde_json::{json, Value};
+    use turborepo_vercel_api::CapabilitiesResponse;
+
+    use super::{BodyFormat, MSGPACK_CAPABILITY};
+    use crate::{testing::test_auth, APIClient};
+
+    #[tokio::test]
+    async fn test_msgpack_body() -> anyhow::Result<()> {
+        let api_auth = test_auth();
+        let payload = json!({ "key": "web#build", "exitCode": 0 });
+
+        let client = APIClient::new("http://localhost", 0, "2.0.0", false)?
+            .with_body_format(BodyFormat::MessagePack);
+        client.capabilities.set(Some(CapabilitiesResponse {
+            api_version: 1,
+            min_client_api_version: 1,
+            capabilities: vec![MSGPACK_CAPABILITY.to_string()],
+        }))?;
+        let body = client.encode_body(&api_auth, &payload).await?;
+        assert_eq!(body.format, BodyFormat::MessagePack);
+        assert_eq!(rmp_serde::from_slice::<Value>(&body.bytes)?, payload);
+
+        // Servers that don't advertise it get JSON
+        let client = APIClient::new("http://localhost", 0, "2.0.0", false)?
+            .with_body_format(BodyFormat::MessagePack);
+        client.capabilities.set(None)?;
+        let body = client.encode_body(&api_auth, &payload).await?;
+        assert_eq!(body.format, BodyFormat::Json);
+        assert_eq!(serde_json::from_slice::<Value>(&body.bytes)?, payload);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,161 @@
+use reqwest::Method;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::{casing::wire_casing, dialect::OPAQUE_FIELDS, RunId, SpaceId, SpacesMethod};
+use crate::{retry, APIAuth, APIClient, Error};
+
+wire_casing! {
+    run_payload,
+    #[derive(Debug, Clone, Serialize)]
+    struct AppendTaskLogsPayload<'a> {
+        offset: u64,
+        chunk: &'a str,
+    }
+}
+
+/// Removes the empty `logs` fields of the task summaries in a payload,
+/// including those of batches and bundles
+pub(super) fn omit_empty_logs(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            if object.get("logs").and_then(Value::as_str) == Some("") {
+                object.remove("logs");
+            }
+            for (key, value) in object.iter_mut() {
+                if !OPAQUE_FIELDS.contains(&key.as_str()) {
+                    omit_empty_logs(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(omit_empty_logs),
+        _ => {}
+    }
+}
+
+impl APIClient {
+    /// Appends a chunk of a task's logs, starting at the byte `offset` of the
+    /// task's log, so long running tasks can show their output before they
+    /// finish. The server ignores bytes it already has, so resending a chunk
+    /// after a failure or out of order is safe.
+    ///
+    /// Returns the offset of the next chunk. Once the logs have been streamed,
+    /// leave `logs` empty in the task summary and enable
+    /// `APIClient::with_streamed_logs`, so they aren't overwritten.
+    pub async fn append_task_logs(
+        &self,
+        space_id: &SpaceId,
+        run_id: &RunId,
+        task_key: &str,
+        api_auth: &APIAuth,
+        chunk: &str,
+        offset: u64,
+    ) -> Result<u64, Error> {
+        let next_offset = offset + chunk.len() as u64;
+        if self.spaces_disabled() {
+            return Ok(next_offset);
+        }
+
+        self.check_space(space_id)?;
+
+        self.cancellable_upload(run_id, async {
+            let _permit = self.acquire_spaces_slot(SpacesMethod::TaskLogs).await;
+            let payload = AppendTaskLogsPayload { offset, chunk };
+            let request_builder = self
+                .create_request_builder_with_body(
+                    SpacesMethod::TaskLogs,
+                    &format!(
+                        "/v0/spaces/{}/runs/{}/tasks/{}/logs",
+                        space_id,
+                        run_id,
+                        urlencoding::encode(task_key)
+                    ),
+                    api_auth,
+                    Method::POST,
+                    self.encode_payload(&payload)?,
+                )
+                .await?;
+
+            retry::make_retryable_request(request_builder, self)
+                .await?
+                .error_for_status()?;
+
+            Ok(next_offset)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+    use serde_json::{json, Value};
+
+    use crate::{
+        spaces::SpaceTaskSummary,
+        testing::{test_auth, Canned, CannedServer},
+        APIClient,
+    };
+
+    #[tokio::test]
+    async fn test_append_task_logs() -> Result<()> {
+        let server = CannedServer::always(Canned::ok()).await;
+        let client = server.client();
+        let api_auth = test_auth();
+
+        let mut offset = 0;
+        for chunk in ["hello ", "world"] {
+            offset = client
+                .append_task_logs(
+                    &"space".into(),
+                    &"run".into(),
+                    "web#build",
+                    &api_auth,
+                    chunk,
+                    offset,
+                )
+                .await?;
+        }
+        assert_eq!(offset, 11);
+
+        let requests = server.requests();
+        let bodies = requests
+            .iter()
+            .map(|request| serde_json::from_slice(&request.body))
+            .collect::<Result<Vec<Value>, _>>()?;
+        assert_eq!(
+            bodies,
+            [
+                json!({ "offset": 0, "chunk": "hello " }),
+                json!({ "offset": 6, "chunk": "world" })
+            ]
+        );
+        for request in &requests {
+            assert_eq!(
+                request.path,
+                "/v0/spaces/space/runs/run/tasks/web%23build/logs"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_streamed_logs() -> Result<()> {
+        let task = SpaceTaskSummary {
+            metadata: Some(json!({ "logs": "" })),
+            ..SpaceTaskSummary::default()
+        };
+        let client = APIClient::new("http://localhost", 0, "2.0.0", false)?;
+        let encoded = client.encode_value(&json!({ "tasks": [&task] }))?;
+        assert_eq!(encoded["tasks"][0]["logs"], "");
+
+        let client = client.with_streamed_logs(true);
+        let encoded = client.encode_value(&json!({ "tasks": [&task] }))?;
+        assert!(encoded["tasks"][0].get("logs").is_none());
+        // Caller provided metadata is left as is
+        assert_eq!(encoded["tasks"][0]["metadata"], json!({ "logs": "" }));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1430 @@
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use chrono::{DateTime, Local};
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+use turbopath::AnchoredSystemPath;
+use turborepo_vercel_api::SpaceRun;
+
+use self::casing::wire_casing;
+pub use self::{
+    batch::{TaskUploadOutcome, TASK_BATCHES_CAPABILITY},
+    bulk::RunToFinish,
+    bundle::{BundleUpload, RunBundle, COMPLETE_RUNS_CAPABILITY},
+    compression::{LogCompression, GZIP_REQUESTS_CAPABILITY, LOGS_ENCODING_HEADER},
+    diagnostics::{
+        DiagnosticSeverity, RunDiagnostic, MAX_DIAGNOSTIC_MESSAGE_BYTES, MAX_RUN_DIAGNOSTICS,
+    },
+    dialect::PayloadDialect,
+    document::{RunDocument, RunRecording, RUN_DOCUMENT_SCHEMA_VERSION},
+    duplicates::DuplicateTaskKeyPolicy,
+    environment::EnvironmentFingerprint,
+    finish::FinishRetryPolicy,
+    format::{BodyFormat, MSGPACK_CAPABILITY},
+    ids::{RunId, SpaceId},
+    patch::RunPatch,
+    queue::{RequestPriority, SpacesMethod},
+    reconcile::{LocalRunState, ReconcileReport},
+    session::SpaceSession,
+    shutdown::Unflushed,
+    stats::{SpaceStats, StatsRange},
+    stream::{StreamReconnectPolicy, SummaryStream},
+    times::TaskTimePolicy,
+    visibility::DEFAULT_RUN_VISIBILITY_TIMEOUT,
+};
+pub(crate) use self::{
+    cancel::RunUploads,
+    compression::CompressionThresholds,
+    dialect::OPAQUE_FIELDS,
+    duplicates::TaskKeys,
+    queue::{RequestQueue, SpacesPriorities},
+    shutdown::OpenWork,
+    times::RunStartTimes,
+};
+use crate::{retry, signature, APIAuth, APIClient, Client, Error, HmacAlgorithm, Warning};
+
+mod batch;
+mod bulk;
+mod bundle;
+mod cancel;
+mod casing;
+mod compression;
+mod diagnostics;
+mod dialect;
+mod document;
+mod duplicates;
+mod environment;
+mod finish;
+mod format;
+mod ids;
+mod logs;
+mod patch;
+mod queue;
+mod reconcile;
+mod runs;
+mod sanitize;
+mod serialization;
+mod session;
+mod shutdown;
+mod stats;
+mod stream;
+mod times;
+mod visibility;
+
+/// Tells the server how long the client waits for a response, see
+/// `APIClient::with_deadline_header`
+const DEADLINE_HEADER: &str = "grpc-timeout";
+
+/// The id of the run returned by `create_space_run` when spaces are disabled
+const DISABLED_RUN_ID: &str = "";
+
+/// Formats a timeout as a `grpc-timeout` value, in milliseconds unless it's a
+/// whole number of seconds, so that sub-second timeouts aren't rounded down
+fn format_deadline(timeout: Duration) -> String {
+    if timeout.subsec_nanos() == 0 {
+        format!("{}S", timeout.as_secs())
+    } else {
+        format!("{}m", timeout.as_millis().max(1))
+    }
+}
+
+/// Returns whether `run` is the placeholder returned by `create_space_run`
+/// when spaces are disabled.
+pub fn is_disabled_run(run: &SpaceRun) -> bool {
+    run.id == DISABLED_RUN_ID
+}
+
+wire_casing! {
+    state,
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub enum RunStatus {
+        Running,
+        Completed,
+    }
+}
+
+wire_casing! {
+    run_payload,
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct SpaceClientSummary {
+        pub id: String,
+        pub name: String,
+        pub version: String,
+        /// The package manager and its version, e.g. `pnpm@8.6.0`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub package_manager: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub node_version: Option<String>,
+        /// The operating system the run happened on, e.g. `linux`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub platform: Option<String>,
+    }
+}
+
+wire_casing! {
+    constant,
+    /// Where a cache hit was restored from. Serialized in uppercase, which is
+    /// what the dashboard expects, but servers that expect another casing can be
+    /// targeted with `APIClient::with_cache_source_casing`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum CacheSource {
+        #[serde(alias = "local")]
+        Local,
+        #[serde(alias = "remote")]
+        Remote,
+    }
+}
+
+/// What a run should do when it can't be recorded in its space, i.e. creating
+/// or finishing the run fails. This is consulted by the run orchestration,
+/// the client itself always returns errors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SpacesFailurePolicy {
+    /// Don't report the failure
+    Ignore,
+    /// Report the failure but let the build succeed
+    #[default]
+    Warn,
+    /// Fail the build, for teams that need every build to be recorded
+    Fail,
+}
+
+/// The casing used for `CacheSource` on the wire
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CacheSourceCasing {
+    #[default]
+    Uppercase,
+    Lowercase,
+}
+
+/// How the user that started a run is reported to the server
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum UserIdentity {
+    /// Send the user as is
+    #[default]
+    Raw,
+    /// Send an HMAC of the user keyed with a team-provided salt. Runs from the
+    /// same user can still be grouped together, but the user can't be
+    /// identified without the salt.
+    Pseudonymized { salt: Vec<u8> },
+}
+
+impl UserIdentity {
+    fn apply(&self, user: &str) -> String {
+        match self {
+            UserIdentity::Raw => user.to_string(),
+            UserIdentity::Pseudonymized { salt } => {
+                signature::hmac_hex(salt, HmacAlgorithm::Sha256, user.as_bytes())
+            }
+        }
+    }
+}
+
+/// What's reported as the user that started a run when the user couldn't be
+/// detected
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum EmptyUserPolicy {
+    /// Leave `originationUser` out of the payload
+    #[default]
+    Omit,
+    /// Report a placeholder, e.g. `unknown`, instead
+    Fallback(String),
+}
+
+/// Which task summaries include their logs. Logs are most of a summary's
+/// size, and are rarely looked at for tasks that succeeded, so leaving them
+/// out cuts the upload volume of mostly cached runs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogUploadPolicy {
+    #[default]
+    Always,
+    /// Only tasks that exited with a nonzero code
+    OnFailure,
+    /// Tasks that failed or weren't restored from the cache
+    OnMiss,
+    Never,
+}
+
+impl LogUploadPolicy {
+    /// Whether `task`'s logs are uploaded, based on its exit code and cache
+    /// status
+    pub fn uploads_logs(&self, task: &SpaceTaskSummary) -> bool {
+        let failed = task.exit_code != 0;
+        match self {
+            LogUploadPolicy::Always => true,
+            LogUploadPolicy::OnFailure => failed,
+            LogUploadPolicy::OnMiss => failed || task.cache.status != SpacesCacheStatus::HIT,
+            LogUploadPolicy::Never => false,
+        }
+    }
+}
+
+wire_casing! {
+    task_payload,
+    #[derive(Default, Debug, Clone, Serialize, Deserialize)]
+    pub struct SpacesCacheStatus {
+        /// One of `HIT`, `MISS` or `RESTORE_FAILED`
+        pub status: String,
+        pub source: Option<CacheSource>,
+        pub time_saved: u32,
+    }
+}
+
+impl SpacesCacheStatus {
+    pub const HIT: &'static str = "HIT";
+    pub const MISS: &'static str = "MISS";
+    /// The task was a cache hit, but restoring its outputs failed, e.g.
+    /// because the artifact was corrupt, so the task was run instead
+    pub const RESTORE_FAILED: &'static str = "RESTORE_FAILED";
+
+    /// Marks a cache hit as failing to restore. The source is kept, so the
+    /// dashboard can tell which cache is unhealthy, but no time was saved.
+    pub fn restore_failed(mut self) -> Self {
+        self.status = Self::RESTORE_FAILED.to_string();
+        self.time_saved = 0;
+        self
+    }
+}
+
+wire_casing! {
+    task_payload,
+    #[derive(Default, Debug, Clone, Serialize, Deserialize)]
+    pub struct SpaceTaskSummary {
+        pub key: String,
+        pub name: String,
+        /// Normalized to a POSIX path when serialized, see `normalize_path`
+        #[serde(serialize_with = "serialize_normalized_path")]
+        pub workspace: String,
+        pub hash: String,
+        pub start_time: i64,
+        pub end_time: i64,
+        pub cache: SpacesCacheStatus,
+        pub exit_code: u32,
+        pub dependencies: Vec<String>,
+        pub dependents: Vec<String>,
+        #[serde(default)]
+        pub logs: String,
+        /// Arbitrary tool-specific data, e.g. test counts or coverage. Limited to
+        /// `MAX_TASK_METADATA_BYTES` once serialized.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub metadata: Option<serde_json::Value>,
+        /// CPU time spent by the task's process tree, from process accounting.
+        /// Unlike the rest of the summary, the resource usage fields are
+        /// camelCase.
+        #[serde(rename = "cpuTimeMs", default, skip_serializing_if = "Option::is_none")]
+        pub cpu_time_ms: Option<u64>,
+        /// The task's peak resident memory. A u64, since peaks of large
+        /// builds can exceed the range of a u32.
+        #[serde(
+            rename = "peakMemoryBytes",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        pub peak_memory_bytes: Option<u64>,
+        #[serde(rename = "wallTimeMs", default, skip_serializing_if = "Option::is_none")]
+        pub wall_time_ms: Option<u64>,
+    }
+}
+
+const ROOT_WORKSPACE: &str = "//";
+
+/// Normalizes a workspace or repository path to the POSIX form the dashboard
+/// groups by, so the same workspace isn't split in two depending on which
+/// platform a run happened on: `.\apps\web\` becomes `apps/web`. The root
+/// workspace, `//`, is kept as is.
+fn normalize_path(path: &str) -> String {
+    if path == ROOT_WORKSPACE {
+        return path.to_string();
+    }
+
+    path.replace('\\', "/")
+        .split('/')
+        .filter(|segment| !segment.is_empty() && *segment != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn serialize_normalized_path<S: serde::Serializer>(
+    path: &str,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&normalize_path(path))
+}
+
+/// The maximum size of a task summary's serialized metadata
+pub const MAX_TASK_METADATA_BYTES: usize = 16 * 1024;
+
+impl SpaceTaskSummary {
+    /// Attaches structured metadata to the summary. Errors if the metadata
+    /// is larger than `MAX_TASK_METADATA_BYTES` once serialized.
+    pub fn with_metadata(mut self, metadata: serde_json::Value) -> Result<Self, Error> {
+        self.metadata = Some(metadata);
+        self.check_metadata_size()?;
+        Ok(self)
+    }
+
+    fn check_metadata_size(&self) -> Result<(), Error> {
+        let Some(metadata) = &self.metadata else {
+            return Ok(());
+        };
+
+        let size = serde_json::to_vec(metadata)?.len();
+        if size > MAX_TASK_METADATA_BYTES {
+            return Err(Error::MetadataTooLarge {
+                size,
+                limit: MAX_TASK_METADATA_BYTES,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+wire_casing! {
+    constant,
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub enum SpaceRunType {
+        Turbo,
+    }
+}
+
+wire_casing! {
+    run_payload,
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct CreateSpaceRunPayload {
+        pub start_time: i64,
+        pub status: RunStatus,
+        #[serde(rename = "type")]
+        pub ty: SpaceRunType, // Hardcoded to "TURBO"
+        pub command: String,
+        /// The command as it was run, if `command` was canonicalized for
+        /// grouping
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub raw_command: Option<String>,
+        #[serde(
+            rename = "repositoryPath",
+            serialize_with = "serialize_normalized_path"
+        )]
+        pub package_inference_root: String,
+        #[serde(rename = "context")]
+        pub run_context: String,
+        pub git_branch: Option<String>,
+        pub git_sha: Option<String>,
+        /// Left out of the payload when empty, see `EmptyUserPolicy`
+        #[serde(
+            rename = "originationUser",
+            default,
+            skip_serializing_if = "String::is_empty"
+        )]
+        pub user: String,
+        pub client: SpaceClientSummary,
+        /// When the server should delete the run, as a millisecond timestamp.
+        /// Servers that don't support expiry keep the run.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub expires_at: Option<i64>,
+        /// The packages the run affected, see `with_affected_packages`
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub affected_packages: Vec<String>,
+        /// How many affected packages were left out of `affected_packages`
+        #[serde(default, skip_serializing_if = "is_zero")]
+        pub omitted_affected_packages: usize,
+        /// The hash of the resolved `turbo.json`, so runs can be correlated
+        /// with config changes, see `with_turbo_config_hash`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub turbo_config_hash: Option<String>,
+        /// The machine the run happened on, see `with_environment`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub environment: Option<EnvironmentFingerprint>,
+    }
+}
+
+/// The length of a turbo config hash, which is a hex encoded 64-bit hash
+pub const TURBO_CONFIG_HASH_LEN: usize = 16;
+
+/// The most affected packages sent with a run. Huge monorepos report the
+/// rest as a count.
+pub const MAX_AFFECTED_PACKAGES: usize = 200;
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+impl CreateSpaceRunPayload {
+    pub fn new(
+        start_time: DateTime<Local>,
+        synthesized_command: &str,
+        package_inference_root: Option<&AnchoredSystemPath>,
+        git_branch: Option<String>,
+        git_sha: Option<String>,
+        version: String,
+        user: String,
+    ) -> Self {
+        let start_time = start_time.timestamp_millis();
+        let vendor = turborepo_ci::Vendor::infer();
+        let run_context = vendor.map(|v| v.constant).unwrap_or("LOCAL");
+
+        CreateSpaceRunPayload {
+            start_time,
+            status: RunStatus::Running,
+            command: synthesized_command.to_string(),
+            raw_command: None,
+            package_inference_root: package_inference_root
+                .map(|p| p.to_string())
+                .unwrap_or_default(),
+            ty: SpaceRunType::Turbo,
+            run_context: run_context.to_string(),
+            git_branch,
+            git_sha,
+            user,
+            client: SpaceClientSummary {
+                id: "turbo".to_string(),
+                name: "Turbo".to_string(),
+                version,
+                package_manager: None,
+                node_version: None,
+                platform: None,
+            },
+            expires_at: None,
+            affected_packages: Vec::new(),
+            omitted_affected_packages: 0,
+            turbo_config_hash: None,
+            environment: None,
+        }
+    }
+
+    /// Sets the packages the run affected, e.g. from the task graph of a
+    /// PR-scoped run. Only the first `MAX_AFFECTED_PACKAGES` are sent, the
+    /// rest are counted in `omitted_affected_packages`.
+    pub fn with_affected_packages(mut self, mut packages: Vec<String>) -> Self {
+        self.omitted_affected_packages = packages.len().saturating_sub(MAX_AFFECTED_PACKAGES);
+        packages.truncate(MAX_AFFECTED_PACKAGES);
+        self.affected_packages = packages;
+        self
+    }
+
+    /// Marks the run as short-lived, e.g. for preview builds, so the server
+    /// deletes it once `ttl` has passed since the run started.
+    pub fn ephemeral(mut self, ttl: Duration) -> Self {
+        let ttl = i64::try_from(ttl.as_millis()).unwrap_or(i64::MAX);
+        self.expires_at = Some(self.start_time.saturating_add(ttl));
+        self
+    }
+
+    /// Sets the hash of the run's resolved `turbo.json`. Errors if it isn't
+    /// `TURBO_CONFIG_HASH_LEN` hex digits.
+    pub fn with_turbo_config_hash(mut self, hash: impl Into<String>) -> Result<Self, Error> {
+        self.turbo_config_hash = Some(hash.into());
+        self.check_turbo_config_hash()?;
+        Ok(self)
+    }
+
+    fn check_turbo_config_hash(&self) -> Result<(), Error> {
+        match &self.turbo_config_hash {
+            Some(hash)
+                if hash.len() != TURBO_CONFIG_HASH_LEN
+                    || !hash.bytes().all(|b| b.is_ascii_hexdigit()) =>
+            {
+                Err(Error::InvalidConfigHash { hash: hash.clone() })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+wire_casing! {
+    run_payload,
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct FinishSpaceRunPayload {
+        status: RunStatus,
+        end_time: i64,
+        exit_code: i32,
+    }
+}
+
+/// The result of finishing a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FinishOutcome {
+    Finished,
+    /// The run was already finished on the server, so it wasn't updated.
+    /// Only returned when the finish pre-check is enabled.
+    AlreadyFinished,
+    /// The run couldn't be finished, and was stored to be finished by
+    /// `finish_deferred_runs`. Only returned when deferred finishes are
+    /// enabled.
+    Deferred,
+}
+
+/// How `create_space_run_with_outcome` created a run
+pub struct CreateOutcome {
+    pub session: SpaceSession,
+    /// The number of times the create request was sent, including retries.
+    /// Zero when spaces are disabled.
+    pub attempts: u32,
+    /// Whether a preflight request was made before creating the run
+    pub used_preflight: bool,
+    /// The time it took to create the run, including preflight and retries
+    pub elapsed: Duration,
+}
+
+impl CreateOutcome {
+    pub fn run(&self) -> &SpaceRun {
+        self.session.run()
+    }
+
+    pub fn into_session(self) -> SpaceSession {
+        self.session
+    }
+}
+
+#[derive(Deserialize)]
+struct RunStateResponse {
+    status: RunStatus,
+}
+
+impl FinishSpaceRunPayload {
+    pub fn new(end_time: i64, exit_code: i32) -> Self {
+        Self {
+            status: RunStatus::Completed,
+            end_time,
+            exit_code,
+        }
+    }
+}
+
+impl APIClient {
+    /// Create a new request builder with the preflight check done,
+    /// team parameters added, CI header, and authentication. The body
+    /// is passed in already serialized so that it can be included in
+    /// a request signature. In the future this should be extended to all
+    /// of the APIClient methods.
+    pub(crate) async fn create_request_builder(
+        &self,
+        url: &str,
+        api_auth: &APIAuth,
+        method: Method,
+        body: Option<Vec<u8>>,
+    ) -> Result<RequestBuilder, Error> {
+        let mut url = self.make_url(url);
+        let mut allow_auth = true;
+
+        let APIAuth {
+            team_id, team_slug, ..
+        } = api_auth;
+
+        let deadline = self
+            .deadline_header
+            .then(|| {
+                let timeout = (self.timeout != 0).then(|| Duration::from_secs(self.timeout));
+                timeout.into_iter().chain(self.attempt_timeout).min()
+            })
+            .flatten();
+
+        if self.use_preflight {
+            let (preflight_token, auth_headers) = api_auth.preflight_credentials();
+            let mut request_headers = format!("{}, User-Agent", auth_headers);
+            if deadline.is_some() {
+                request_headers.push_str(", ");
+                request_headers.push_str(DEADLINE_HEADER);
+            }
+            let preflight_response = self
+                .send_preflight(preflight_token, &url, method.as_str(), &request_headers)
+                .await?;
+
+            allow_auth = preflight_response.allow_authorization_header();
+            url = preflight_response.location.to_string();
+        }
+
+        let mut request_builder = self
+            .client
+            .request(method.clone(), &url)
+            .header("Content-Type", "application/json")
+            .header(
+                "Accept",
+                format!("application/vnd.turbo.v{}+json", self.spaces_api_version),
+            );
+
+        request_builder = Self::add_team_params(request_builder, team_id, team_slug.as_deref());
+
+        // Tells the server how long we'll wait, in the `grpc-timeout` format,
+        // so it can abandon work for requests we've given up on
+        if let Some(timeout) = deadline {
+            request_builder = request_builder.header(DEADLINE_HEADER, format_deadline(timeout));
+        }
+
+        if let Some(constant) = turborepo_ci::Vendor::get_constant() {
+            request_builder = request_builder.header("x-artifact-client-ci", constant);
+        }
+
+        let body = body.map(Bytes::from);
+        if let Some(body) = &body {
+            request_builder = request_builder.body(body.clone());
+        }
+
+        request_builder = self
+            .authorize(
+                request_builder,
+                api_auth,
+                body.as_deref().unwrap_or_default(),
+                allow_auth,
+            )
+            .await?;
+
+        if let Some(hook) = &self.request_hook {
+            request_builder = hook(request_builder);
+        }
+
+        Ok(request_builder)
+    }
+
+    /// Returns an error without making a request if the space is already
+    /// known not to exist.
+    fn check_space(&self, space_id: &SpaceId) -> Result<(), Error> {
+        let invalid_spaces = self
+            .invalid_spaces
+            .lock()
+            .expect("invalid spaces lock poisoned");
+        if invalid_spaces.contains(space_id.as_str()) {
+            return Err(Error::SpaceNotFound {
+                space_id: space_id.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// The user to report for a run. Empty users are never pseudonymized, so
+    /// that runs whose user wasn't detected aren't grouped as one user.
+    fn report_user(&self, user: &str) -> String {
+        match (user.is_empty(), &self.empty_user_policy) {
+            (false, _) => self.user_identity.apply(user),
+            (true, EmptyUserPolicy::Omit) => String::new(),
+            (true, EmptyUserPolicy::Fallback(fallback)) => fallback.clone(),
+        }
+    }
+
+    /// Creates a run in the given space. The returned session finishes the
+    /// run if it's dropped without being finished explicitly.
+    pub async fn create_space_run(
+        &self,
+        space_id: &SpaceId,
+        api_auth: &APIAuth,
+        payload: CreateSpaceRunPayload,
+    ) -> Result<SpaceSession, Error> {
+        self.create_space_run_with_outcome(space_id, api_auth, payload)
+            .await
+            .map(CreateOutcome::into_session)
+    }
+
+    /// Like `create_space_run`, but also reports how the run was created,
+    /// e.g. for debugging slow or flaky run creation
+    pub async fn create_space_run_with_outcome(
+        &self,
+        space_id: &SpaceId,
+        api_auth: &APIAuth,
+        payload: CreateSpaceRunPayload,
+    ) -> Result<CreateOutcome, Error> {
+        let started_at = Instant::now();
+        if self.spaces_disabled() {
+            let run = SpaceRun {
+                id: DISABLED_RUN_ID.to_string(),
+                url: String::new(),
+            };
+            return Ok(CreateOutcome {
+                session: SpaceSession::new(run, space_id, self, api_auth, None),
+                attempts: 0,
+                used_preflight: false,
+                elapsed: started_at.elapsed(),
+            });
+        }
+
+        self.check_space(space_id)?;
+        payload.check_turbo_config_hash()?;
+
+        let payload = self.prepare_create_payload(payload);
+        let recorded_create = self.run_recording.then(|| payload.clone());
+        let _permit = self.acquire_spaces_slot(SpacesMethod::CreateRun).await;
+        let url = format!("/v0/spaces/{}/runs", space_id);
+        let request_builder = self
+            .create_request_builder_with_body(
+                SpacesMethod::CreateRun,
+                &url,
+                api_auth,
+                Method::POST,
+                self.encode_body(api_auth, &payload).await?,
+            )
+            .await?;
+
+        let (response, attempts) = retry::make_counted_request(request_builder, self).await;
+        let response = response?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(self.space_not_found(space_id));
+        }
+
+        let run: SpaceRun = response.error_for_status()?.json().await?;
+        self.record_run_start(&run.id.clone().into(), payload.start_time);
+        let recording = recorded_create.map(|create| RunRecording::new(space_id, &run, create));
+        Ok(CreateOutcome {
+            session: SpaceSession::new(run, space_id, self, api_auth, recording),
+            attempts,
+            used_preflight: self.use_preflight,
+            elapsed: started_at.elapsed(),
+        })
+    }
+
+    /// Applies the client's user reporting and command sanitization and
+    /// canonicalization to a run that's about to be created
+    fn prepare_create_payload(&self, mut payload: CreateSpaceRunPayload) -> CreateSpaceRunPayload {
+        payload.user = self.report_user(&payload.user);
+        if self.sanitize_commands {
+            payload.command = sanitize::sanitize_command(&payload.command);
+        }
+        if self.canonicalize_commands {
+            let canonical = sanitize::canonicalize_command(&payload.command);
+            payload.raw_command = Some(std::mem::replace(&mut payload.command, canonical));
+        }
+        payload
+    }
+
+    /// Drops `task`'s logs if the client's `LogUploadPolicy` leaves them out
+    fn apply_log_upload_policy(&self, task: &mut SpaceTaskSummary) {
+        if !self.log_upload_policy.uploads_logs(task) {
+            task.logs.clear();
+        }
+    }
+
+    /// Remembers that `space_id` doesn't exist, so later requests to it fail
+    /// without a round trip
+    fn space_not_found(&self, space_id: &SpaceId) -> Error {
+        self.invalid_spaces
+            .lock()
+            .expect("invalid spaces lock poisoned")
+            .insert(space_id.to_string());
+
+        Error::SpaceNotFound {
+            space_id: space_id.to_string(),
+        }
+    }
+
+    pub async fn create_task_summary(
+        &self,
+        space_id: &SpaceId,
+        run_id: &RunId,
+        api_auth: &APIAuth,
+        mut task: SpaceTaskSummary,
+    ) -> Result<(), Error> {
+        if self.spaces_disabled() {
+            return Ok(());
+        }
+
+        self.check_space(space_id)?;
+        task.check_metadata_size()?;
+        self.apply_task_time_policy(run_id, &mut task)?;
+
+        let key = task.key.clone();
+        let recorded = self.record_task_key(run_id, &key)?;
+        let result = self
+            .cancellable_upload(
+                run_id,
+                self.upload_task_summary(space_id, run_id, api_auth, task),
+            )
+            .await;
+        // Retrying a failed upload isn't a duplicate
+        if result.is_err() && recorded {
+            self.forget_task_key(run_id, &key);
+        }
+
+        result
+    }
+
+    async fn upload_task_summary(
+        &self,
+        space_id: &SpaceId,
+        run_id: &RunId,
+        api_auth: &APIAuth,
+        mut task: SpaceTaskSummary,
+    ) -> Result<(), Error> {
+        self.apply_log_upload_policy(&mut task);
+        let _permit = self.acquire_spaces_slot(SpacesMethod::TaskSummary).await;
+        let logs_compression = self.compress_task_logs(api_auth, &mut task).await;
+        let mut request_builder = self
+            .create_request_builder_with_body(
+                SpacesMethod::TaskSummary,
+                &format!("/v0/spaces/{}/runs/{}/tasks", space_id, run_id),
+                api_auth,
+                Method::POST,
+                self.encode_body(api_auth, &task).await?,
+            )
+            .await?;
+        if let Some(compression) = logs_compression {
+            request_builder = request_builder.header(LOGS_ENCODING_HEADER, compression.name());
+        }
+
+        let result = retry::make_retryable_request(request_builder, self).await;
+        self.record_spaces_outcome(&result);
+        result?.error_for_status()?;
+
+        Ok(())
+    }
+
+    /// Reads back a task summary uploaded with `create_task_summary`, e.g.
+    /// to audit what was recorded.
+    pub async fn get_task_summary(
+        &self,
+        space_id: &SpaceId,
+        run_id: &RunId,
+        task_key: &str,
+        api_auth: &APIAuth,
+    ) -> Result<SpaceTaskSummary, Error> {
+        let not_found = || Error::TaskNotFound {
+            task_key: task_key.to_string(),
+        };
+        // Nothing is uploaded while spaces are disabled
+        if self.spaces_disabled() {
+            return Err(not_found());
+        }
+
+        self.check_space(space_id)?;
+
+        let _permit = self.acquire_spaces_slot(SpacesMethod::GetTask).await;
+        let request_builder = self
+            .create_request_builder(
+                &format!(
+                    "/v0/spaces/{}/runs/{}/tasks/{}",
+                    space_id,
+                    run_id,
+                    urlencoding::encode(task_key)
+                ),
+                api_auth,
+                Method::GET,
+                None,
+            )
+            .await?;
+
+        let response = retry::make_retryable_request(request_builder, self).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(not_found());
+        }
+
+        Ok(response.error_for_status()?.json().await?)
+    }
+
+    pub async fn finish_space_run(
+        &self,
+        space_id: &SpaceId,
+        run_id: &RunId,
+        api_auth: &APIAuth,
+        end_time: i64,
+        exit_code: i32,
+    ) -> Result<FinishOutcome, Error> {
+        let payload = FinishSpaceRunPayload::new(end_time, exit_code);
+        self.send_finish_payload(space_id, run_id, api_auth, &payload)
+            .await
+    }
+
+    pub(crate) async fn send_finish_payload(
+        &self,
+        space_id: &SpaceId,
+        run_id: &RunId,
+        api_auth: &APIAuth,
+        payload: &FinishSpaceRunPayload,
+    ) -> Result<FinishOutcome, Error> {
+        if self.spaces_disabled() {
+            return Ok(FinishOutcome::Finished);
+        }
+
+        self.check_space(space_id)?;
+        self.forget_run_task_keys(run_id);
+        self.forget_run_start(run_id);
+
+        let _permit = self.acquire_spaces_slot(SpacesMethod::FinishRun).await;
+        let url = format!("/v0/spaces/{}/runs/{}", space_id, run_id);
+
+        let deadline = self.finish_deadline();
+        if self.finish_precheck && self.is_run_finished(&url, run_id, api_auth, deadline).await {
+            return Ok(FinishOutcome::AlreadyFinished);
+        }
+
+        let request_builder = self
+            .create_request_builder_with_body(
+                SpacesMethod::FinishRun,
+                &url,
+                api_auth,
+                Method::PATCH,
+                self.encode_payload(payload)?,
+            )
+            .await?;
+
+        self.send_finish_request(request_builder, space_id, run_id, payload, deadline)
+            .await
+    }
+
+    /// When finishing a run that starts now has to be done by, see
+    /// `with_finish_timeout`. The retry budget still applies if it's shorter.
+    pub(crate) fn finish_deadline(&self) -> Instant {
+        let deadline = Instant::now() + self.finish_timeout;
+        match self.retry_budget {
+            Some(budget) => deadline.min(Instant::now() + budget),
+            None => deadline,
+        }
+    }
+
+    /// Checks whether the server already considers the run finished, e.g.
+    /// because it was closed by a timeout. Any failure to read the run's
+    /// state is treated as not finished, so that we still try to finish it.
+    async fn is_run_finished(
+        &self,
+        url: &str,
+        run_id: &RunId,
+        api_auth: &APIAuth,
+        deadline: Instant,
+    ) -> bool {
+        match self.get_run_status(url, api_auth, deadline).await {
+            Ok(status) => matches!(status, RunStatus::Completed),
+            Err(_) => {
+                self.warn(Warning::FinishPrecheckFailed {
+                    run_id: run_id.to_string(),
+                });
+                false
+            }
+        }
+    }
+
+    async fn get_run_status(
+        &self,
+        url: &str,
+        api_auth: &APIAuth,
+        deadline: Instant,
+    ) -> Result<RunStatus, Error> {
+        let request_builder = self
+            .create_request_builder(url, api_auth, Method::GET, None)
+            .await?;
+
+        let response =
+            retry::make_retryable_request_with_deadline(request_builder, self, Some(deadline))
+                .await?
+                .error_for_status()?;
+
+        Ok(response.json::<RunStateResponse>().await?.status)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, time::Duration};
+
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use chrono::Local;
+    use reqwest::{Method, RequestBuilder};
+    use turborepo_vercel_api_mock::{start_test_server, EXPECTED_SPACE_ID, EXPECTED_SPACE_RUN_ID};
+
+    use super::format_deadline;
+    use crate::{
+        retry,
+        spaces::{
+            CacheSource, CreateSpaceRunPayload, EmptyUserPolicy, LogUploadPolicy, RunId, SpaceId,
+            SpaceTaskSummary, SpacesCacheStatus, UserIdentity, MAX_AFFECTED_PACKAGES,
+            MAX_TASK_METADATA_BYTES,
+        },
+        testing::{set_capabilities, start_hanging_server, test_auth, Canned, CannedServer},
+        APIAuth, APIClient, AuthMode, AuthProvider, Error, HmacAlgorithm, IDEMPOTENCY_KEY_HEADER,
+    };
+
+    #[test]
+    fn test_task_metadata() -> Result<()> {
+        let task = SpaceTaskSummary::default();
+        let json = serde_json::to_value(&task)?;
+        assert!(json.get("metadata").is_none());
+
+        let task = task.with_metadata(serde_json::json!({ "tests": 12 }))?;
+        let json = serde_json::to_value(task)?;
+        assert_eq!(json["metadata"]["tests"], 12);
+
+        let too_large = serde_json::Value::String("a".repeat(MAX_TASK_METADATA_BYTES));
+        assert!(matches!(
+            SpaceTaskSummary::default().with_metadata(too_large),
+            Err(Error::MetadataTooLarge { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_workspace_normalization() -> Result<()> {
+        let task = SpaceTaskSummary {
+            workspace: r".\packages\ui\".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(serde_json::to_value(task)?["workspace"], "packages/ui");
+
+        let task = SpaceTaskSummary {
+            workspace: "packages//ui".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(serde_json::to_value(task)?["workspace"], "packages/ui");
+
+        let task = SpaceTaskSummary {
+            workspace: "//".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(serde_json::to_value(task)?["workspace"], "//");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_restore_failed() -> Result<()> {
+        let status = SpacesCacheStatus {
+            status: SpacesCacheStatus::HIT.to_string(),
+            source: Some(CacheSource::Remote),
+            time_saved: 100,
+        }
+        .restore_failed();
+
+        let json = serde_json::to_value(status)?;
+        assert_eq!(json["status"], "RESTORE_FAILED");
+        assert_eq!(json["source"], "REMOTE");
+        assert_eq!(json["time_saved"], 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_user_identity() {
+        assert_eq!(UserIdentity::Raw.apply("user"), "user");
+
+        let pseudonymized = UserIdentity::Pseudonymized {
+            salt: b"salt".to_vec(),
+        };
+        let user = pseudonymized.apply("user");
+        assert_ne!(user, "user");
+        assert_eq!(user, pseudonymized.apply("user"));
+        assert_ne!(user, pseudonymized.apply("other"));
+    }
+
+    #[test]
+    fn test_affected_packages() -> Result<()> {
+        let payload = CreateSpaceRunPayload::new(
+            Local::now(),
+            "turbo run build",
+            None,
+            None,
+            None,
+            "".to_string(),
+            "".to_string(),
+        );
+        let json = serde_json::to_value(&payload)?;
+        assert!(json.get("affectedPackages").is_none());
+        assert!(json.get("omittedAffectedPackages").is_none());
+
+        let packages = (0..MAX_AFFECTED_PACKAGES + 5)
+            .map(|i| format!("pkg-{i}"))
+            .collect();
+        let json = serde_json::to_value(payload.with_affected_packages(packages))?;
+        assert_eq!(
+            json["affectedPackages"].as_array().map(Vec::len),
+            Some(MAX_AFFECTED_PACKAGES)
+        );
+        assert_eq!(json["omittedAffectedPackages"], 5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_turbo_config_hash() -> Result<()> {
+        let payload = || {
+            CreateSpaceRunPayload::new(
+                Local::now(),
+                "turbo run build",
+                None,
+                None,
+                None,
+                "".to_string(),
+                "".to_string(),
+            )
+        };
+        assert!(serde_json::to_value(payload())?
+            .get("turboConfigHash")
+            .is_none());
+
+        let json = serde_json::to_value(payload().with_turbo_config_hash("0123456789abcdef")?)?;
+        assert_eq!(json["turboConfigHash"], "0123456789abcdef");
+
+        for hash in ["0123456789abcde", "0123456789abcdefg", "0123456789abcdez"] {
+            assert!(matches!(
+                payload().with_turbo_config_hash(hash),
+                Err(Error::InvalidConfigHash { .. })
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_user() -> Result<()> {
+        let payload = |client: &APIClient| {
+            let mut payload = CreateSpaceRunPayload::new(
+                Local::now(),
+                "turbo run build",
+                None,
+                None,
+                None,
+                "".to_string(),
+                "".to_string(),
+            );
+            payload.user = client.report_user(&payload.user);
+            serde_json::to_value(payload)
+        };
+
+        // Not even pseudonymized, the user is left out
+        let client = APIClient::new("http://localhost", 0, "2.0.0", false)?.with_user_identity(
+            UserIdentity::Pseudonymized {
+                salt: b"salt".to_vec(),
+            },
+        );
+        assert!(payload(&client)?.get("originationUser").is_none());
+
+        let client =
+            client.with_empty_user_policy(EmptyUserPolicy::Fallback("unknown".to_string()));
+        assert_eq!(payload(&client)?["originationUser"], "unknown");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_log_upload_policy() {
+        let task = |exit_code, status: &str| SpaceTaskSummary {
+            exit_code,
+            cache: SpacesCacheStatus {
+                status: status.to_string(),
+                ..SpacesCacheStatus::default()
+            },
+            ..SpaceTaskSummary::default()
+        };
+        let cached = task(0, SpacesCacheStatus::HIT);
+        let missed = task(0, SpacesCacheStatus::MISS);
+        let failed = task(1, SpacesCacheStatus::MISS);
+
+        let uploaded = |policy: LogUploadPolicy| {
+            [&cached, &missed, &failed].map(|task| policy.uploads_logs(task))
+        };
+        assert_eq!(uploaded(LogUploadPolicy::Always), [true, true, true]);
+        assert_eq!(uploaded(LogUploadPolicy::OnFailure), [false, false, true]);
+        assert_eq!(uploaded(LogUploadPolicy::OnMiss), [false, true, true]);
+        assert_eq!(uploaded(LogUploadPolicy::Never), [false, false, false]);
+    }
+
+    #[test]
+    fn test_cache_source_wire_format() -> Result<()> {
+        assert_eq!(serde_json::to_string(&CacheSource::Local)?, r#""LOCAL""#);
+        assert_eq!(serde_json::to_string(&CacheSource::Remote)?, r#""REMOTE""#);
+
+        // Both casings are accepted when reading a summary back
+        let source: CacheSource = serde_json::from_str(r#""remote""#)?;
+        assert_eq!(source, CacheSource::Remote);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_space_not_found_short_circuits() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+        let client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0", true)?;
+        let api_auth = test_auth();
+
+        let payload = CreateSpaceRunPayload::new(
+            Local::now(),
+            "turbo run build",
+            None,
+            None,
+            None,
+            "".to_string(),
+            "".to_string(),
+        );
+        let space_id = SpaceId::from("deleted_space");
+        let result = client.create_space_run(&space_id, &api_auth, payload).await;
+        assert!(
+            matches!(result, Err(Error::SpaceNotFound { space_id }) if space_id == "deleted_space")
+        );
+
+        // The server is no longer needed, the space is known to be bad
+        handle.abort();
+        let result = client
+            .create_task_summary(
+                &space_id,
+                &RunId::from("run"),
+                &api_auth,
+                SpaceTaskSummary::default(),
+            )
+            .await;
+        assert!(matches!(result, Err(Error::SpaceNotFound { .. })));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_missing_task_summary() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+        let client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0", false)?;
+        let api_auth = test_auth();
+
+        let result = client
+            .get_task_summary(
+                &SpaceId::from("space"),
+                &RunId::from("run"),
+                "web#build",
+                &api_auth,
+            )
+            .await;
+        assert!(matches!(result, Err(Error::TaskNotFound { task_key }) if task_key == "web#build"));
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_outcome() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+        let client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0", true)?;
+        let api_auth = test_auth();
+
+        let payload = CreateSpaceRunPayload::new(
+            Local::now(),
+            "turbo run build",
+            None,
+            None,
+            None,
+            "".to_string(),
+            "".to_string(),
+        );
+        let outcome = client
+            .create_space_run_with_outcome(&EXPECTED_SPACE_ID.into(), &api_auth, payload)
+            .await?;
+        assert_eq!(outcome.run().id, EXPECTED_SPACE_RUN_ID);
+        assert_eq!(outcome.attempts, 1);
+        assert!(outcome.used_preflight);
+        outcome.into_session().disarm();
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_hmac_preflight() -> Result<()> {
+        let server = CannedServer::start(|request| match request.method.as_str() {
+            "OPTIONS" => Canned::ok().header(
+                "access-control-allow-headers",
+                "x-turbo-signature, x-turbo-timestamp, User-Agent",
+            ),
+            _ => Canned::ok(),
+        })
+        .await;
+        let client = APIClient::new(server.url(), 200, "2.0.0", true)?;
+        let api_auth = APIAuth {
+            mode: AuthMode::Hmac {
+                key: b"secret".to_vec(),
+                algorithm: HmacAlgorithm::Sha256,
+            },
+            ..test_auth()
+        };
+        client
+            .create_request_builder("/v0/spaces/space/runs", &api_auth, Method::POST, None)
+            .await?
+            .send()
+            .await?;
+
+        let requests = server.requests();
+        assert_eq!(requests[0].method, "OPTIONS");
+        // The token isn't sent, since signed requests don't use it
+        assert_eq!(requests[0].header("authorization"), None);
+        assert_eq!(
+            requests[0].header("access-control-request-headers"),
+            Some("x-turbo-signature, x-turbo-timestamp, User-Agent")
+        );
+        assert_eq!(requests[1].header("authorization"), None);
+        assert!(requests[1].header("x-turbo-signature").is_some());
+
+        Ok(())
+    }
+
+    struct HeaderAuth;
+
+    #[async_trait]
+    impl AuthProvider for HeaderAuth {
+        async fn authorize(&self, request: RequestBuilder) -> crate::Result<RequestBuilder> {
+            Ok(request.header("x-oidc-token", "minted"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cross_origin_redirect_strips_credentials() -> Result<()> {
+        // Redirects to `localhost`, a different origin than the client's
+        // `127.0.0.1`
+        let server = CannedServer::start(|request| match request.path.as_str() {
+            "/v0/spaces" => {
+                let host = request.header("host").unwrap_or_default();
+                let port = host.rsplit(':').next().unwrap_or_default();
+                Canned::status(307).header("location", format!("http://localhost:{port}/moved"))
+            }
+            _ => Canned::ok(),
+        })
+        .await;
+        let client = server.client();
+        let hmac = AuthMode::Hmac {
+            key: b"secret".to_vec(),
+            algorithm: HmacAlgorithm::Sha256,
+        };
+
+        for mode in [hmac, AuthMode::Custom(Arc::new(HeaderAuth))] {
+            let api_auth = APIAuth {
+                mode,
+                ..test_auth()
+            };
+            let request = client.create_request_builder("/v0/spaces", &api_auth, Method::GET, None);
+            retry::make_retryable_request(request.await?, &client).await?;
+        }
+
+        let requests = server.requests();
+        let [signed, moved_signed, custom, moved_custom] = requests.as_slice() else {
+            panic!("expected 4 requests, got {}", requests.len());
+        };
+        assert!(signed.header("x-turbo-signature").is_some());
+        assert!(signed.header("x-turbo-timestamp").is_some());
+        assert_eq!(moved_signed.path, "/moved");
+        assert_eq!(moved_signed.header("x-turbo-signature"), None);
+        assert_eq!(moved_signed.header("x-turbo-timestamp"), None);
+        assert_eq!(custom.header("x-oidc-token"), Some("minted"));
+        assert_eq!(moved_custom.path, "/moved");
+        assert_eq!(moved_custom.header("x-oidc-token"), None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upload_retries_timeout() -> Result<()> {
+        let server = start_hanging_server().await;
+        let client = server
+            .client()
+            .with_attempt_timeout(Duration::from_millis(100))
+            .with_retry_budget(Duration::from_secs(3));
+        set_capabilities(&client, &[]);
+
+        let result = client
+            .create_task_summary(
+                &"space".into(),
+                &"run".into(),
+                &test_auth(),
+                SpaceTaskSummary::default(),
+            )
+            .await;
+        assert!(matches!(result, Err(Error::TooManyFailures(err)) if err.is_timeout()));
+
+        // Both attempts carry the same key, so the server can drop the repeat
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        let key = requests[0].header(IDEMPOTENCY_KEY_HEADER);
+        assert!(key.is_some());
+        assert_eq!(requests[1].header(IDEMPOTENCY_KEY_HEADER), key);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_deadline() {
+        assert_eq!(format_deadline(Duration::from_secs(30)), "30S");
+        assert_eq!(format_deadline(Duration::from_millis(1_500)), "1500m");
+        assert_eq!(format_deadline(Duration::from_millis(250)), "250m");
+        assert_eq!(format_deadline(Duration::from_micros(10)), "1m");
+    }
+
+    #[tokio::test]
+    async fn test_deadline_header() -> Result<()> {
+        async fn deadline(server: &CannedServer, client: APIClient) -> Result<Option<String>> {
+            client
+                .create_request_builder("/v0/spaces", &test_auth(), Method::GET, None)
+                .await?
+                .send()
+                .await?;
+            let request = server.requests().pop().unwrap();
+            Ok(request.header("grpc-timeout").map(str::to_string))
+        }
+
+        let server = CannedServer::always(Canned::ok()).await;
+        assert_eq!(deadline(&server, server.client()).await?, None);
+        let client = server.client().with_deadline_header(true);
+        assert_eq!(
+            deadline(&server, client.clone()).await?.as_deref(),
+            Some("200S")
+        );
+        // A shorter attempt timeout is what the server has to work with
+        let client = client.with_attempt_timeout(Duration::from_millis(500));
+        assert_eq!(deadline(&server, client).await?.as_deref(), Some("500m"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+use reqwest::Method;
+use serde::Serialize;
+
+use super::{casing::wire_casing, RunId, SpaceId, SpacesMethod};
+use crate::{retry, APIAuth, APIClient, Error};
+
+wire_casing! {
+    run_payload,
+    /// A partial update of a run's details. Only the fields that are set are
+    /// sent, so the rest of the run is left as is. Servers that don't know a
+    /// field ignore it.
+    #[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+    pub struct RunPatch {
+        /// A human-friendly name shown on the dashboard instead of the command
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub name: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub description: Option<String>,
+        /// Labels for filtering runs on the dashboard, e.g. `reverted`.
+        /// Replaces the run's tags.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub tags: Option<Vec<String>>,
+    }
+}
+
+impl RunPatch {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.description.is_none() && self.tags.is_none()
+    }
+}
+
+impl APIClient {
+    /// Updates a run's details after it was created, e.g. to name it once
+    /// the affected packages are known.
+    pub async fn update_space_run(
+        &self,
+        space_id: &SpaceId,
+        run_id: &RunId,
+        api_auth: &APIAuth,
+        patch: &RunPatch,
+    ) -> Result<(), Error> {
+        if self.spaces_disabled() || patch.is_empty() {
+            return Ok(());
+        }
+
+        self.check_space(space_id)?;
+
+        let _permit = self.acquire_spaces_slot(SpacesMethod::UpdateRun).await;
+        let request_builder = self
+            .create_request_builder_with_body(
+                SpacesMethod::UpdateRun,
+                &format!("/v0/spaces/{}/runs/{}", space_id, run_id),
+                api_auth,
+                Method::PATCH,
+                self.encode_payload(patch)?,
+            )
+            .await?;
+
+        retry::make_retryable_request(request_builder, self)
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RunPatch;
+
+    #[test]
+    fn test_run_patch_only_sends_set_fields() -> anyhow::Result<()> {
+        let patch = RunPatch {
+            name: Some("build web".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(serde_json::to_string(&patch)?, r#"{"name":"build web"}"#);
+        assert!(RunPatch::default().is_empty());
+
+        Ok(())
+    }
+}
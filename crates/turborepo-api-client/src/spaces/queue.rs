@@ -0,0 +1,307 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+    sync::{Arc, Mutex},
+};
+
+use reqwest::{Response, StatusCode};
+use tokio::sync::oneshot;
+
+use crate::{APIClient, Error};
+
+/// The priority of a spaces request when the number of concurrent spaces
+/// requests is limited. Higher priority requests are sent first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RequestPriority {
+    Low,
+    Normal,
+    High,
+}
+
+/// The spaces requests that can be assigned a priority
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SpacesMethod {
+    CreateRun,
+    CompleteRun,
+    FinishRun,
+    UpdateRun,
+    RunDiagnostics,
+    TaskSummary,
+    TaskLogs,
+    GetTask,
+    ListRuns,
+    Stats,
+}
+
+impl SpacesMethod {
+    /// Creating and finishing a run go ahead of task uploads, so the run's
+    /// state on the dashboard is accurate even with a large upload backlog.
+    pub fn default_priority(&self) -> RequestPriority {
+        match self {
+            SpacesMethod::CreateRun | SpacesMethod::CompleteRun | SpacesMethod::FinishRun => {
+                RequestPriority::High
+            }
+            SpacesMethod::UpdateRun
+            | SpacesMethod::RunDiagnostics
+            | SpacesMethod::GetTask
+            | SpacesMethod::ListRuns
+            | SpacesMethod::Stats => RequestPriority::Normal,
+            SpacesMethod::TaskSummary | SpacesMethod::TaskLogs => RequestPriority::Low,
+        }
+    }
+}
+
+/// Per-method overrides of `SpacesMethod::default_priority`
+pub(crate) type SpacesPriorities = HashMap<SpacesMethod, RequestPriority>;
+
+/// Limits the number of concurrent requests, handing out free slots to the
+/// highest priority waiter first, and in order of arrival within a priority.
+///
+/// An adaptive queue adjusts its limit to the server's health: the limit is
+/// halved when the server signals congestion and grows by one after a full
+/// limit's worth of successful requests, up to the configured maximum.
+pub(crate) struct RequestQueue {
+    state: Mutex<QueueState>,
+}
+
+struct QueueState {
+    limit: usize,
+    max: usize,
+    adaptive: bool,
+    successes: usize,
+    in_flight: usize,
+    next_seq: u64,
+    waiters: BinaryHeap<Waiter>,
+}
+
+struct Waiter {
+    priority: RequestPriority,
+    seq: u64,
+    tx: oneshot::Sender<QueuePermit>,
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // The heap pops the greatest waiter, so earlier arrivals compare greater
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+/// A slot in the queue, released on drop
+pub(crate) struct QueuePermit {
+    // Only `None` while the permit is being handed back to the queue
+    queue: Option<Arc<RequestQueue>>,
+}
+
+impl Drop for QueuePermit {
+    fn drop(&mut self) {
+        if let Some(queue) = self.queue.take() {
+            queue.release();
+        }
+    }
+}
+
+impl RequestQueue {
+    pub(crate) fn new(max_concurrent: usize) -> Self {
+        Self::with_adaptive(max_concurrent, false)
+    }
+
+    pub(crate) fn adaptive(max_concurrent: usize) -> Self {
+        Self::with_adaptive(max_concurrent, true)
+    }
+
+    fn with_adaptive(max_concurrent: usize, adaptive: bool) -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                limit: max_concurrent,
+                max: max_concurrent,
+                adaptive,
+                successes: 0,
+                in_flight: 0,
+                next_seq: 0,
+                waiters: BinaryHeap::new(),
+            }),
+        }
+    }
+
+    pub(crate) async fn acquire(self: &Arc<Self>, priority: RequestPriority) -> QueuePermit {
+        let rx = {
+            let mut state = self.state.lock().expect("request queue lock poisoned");
+            if state.in_flight < state.limit && state.waiters.is_empty() {
+                state.in_flight += 1;
+                return QueuePermit {
+                    queue: Some(self.clone()),
+                };
+            }
+
+            let (tx, rx) = oneshot::channel();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.waiters.push(Waiter { priority, seq, tx });
+            rx
+        };
+
+        // The sender is only dropped after sending a permit
+        rx.await.expect("request queue dropped a waiter")
+    }
+
+    /// The current limit on concurrent requests
+    pub(crate) fn limit(&self) -> usize {
+        self.state
+            .lock()
+            .expect("request queue lock poisoned")
+            .limit
+    }
+
+    /// Adjusts the limit of an adaptive queue after a request completes
+    pub(crate) fn record(self: &Arc<Self>, congested: bool) {
+        let mut state = self.state.lock().expect("request queue lock poisoned");
+        if !state.adaptive {
+            return;
+        }
+
+        if congested {
+            state.limit = (state.limit / 2).max(1);
+            state.successes = 0;
+        } else {
+            state.successes += 1;
+            if state.successes >= state.limit && state.limit < state.max {
+                state.limit += 1;
+                state.successes = 0;
+            }
+        }
+        self.wake(&mut state);
+    }
+
+    fn release(self: &Arc<Self>) {
+        let mut state = self.state.lock().expect("request queue lock poisoned");
+        state.in_flight -= 1;
+        self.wake(&mut state);
+    }
+
+    /// Hands out slots to waiters while the limit allows
+    fn wake(self: &Arc<Self>, state: &mut QueueState) {
+        while state.in_flight < state.limit {
+            let Some(waiter) = state.waiters.pop() else {
+                return;
+            };
+            // If the waiter was cancelled the permit comes back, so try the
+            // next one. A permit that's sent but never received is dropped
+            // with the channel, which releases it again.
+            match waiter.tx.send(QueuePermit {
+                queue: Some(self.clone()),
+            }) {
+                Ok(()) => state.in_flight += 1,
+                Err(mut permit) => permit.queue = None,
+            }
+        }
+    }
+}
+
+impl APIClient {
+    /// Waits for a free slot for a spaces request, if the number of
+    /// concurrent spaces requests is limited.
+    pub(crate) async fn acquire_spaces_slot(&self, method: SpacesMethod) -> Option<QueuePermit> {
+        let queue = self.spaces_queue.as_ref()?;
+        let priority = self.spaces_priorities.get(&method).copied();
+        Some(
+            queue
+                .acquire(priority.unwrap_or_else(|| method.default_priority()))
+                .await,
+        )
+    }
+
+    /// Feeds the outcome of a spaces request to the queue's concurrency
+    /// controller, if it's adaptive. 429s and timeouts count as congestion.
+    pub(crate) fn record_spaces_outcome(&self, result: &Result<Response, Error>) {
+        let Some(queue) = &self.spaces_queue else {
+            return;
+        };
+        let congested = match result {
+            Ok(response) => response.status() == StatusCode::TOO_MANY_REQUESTS,
+            Err(Error::ReqwestError(err)) => err.is_timeout(),
+            Err(Error::TooManyFailures(err)) => err.is_timeout(),
+            Err(Error::AmbiguousWrite { .. }) => true,
+            Err(_) => false,
+        };
+        queue.record(congested);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::{RequestPriority, RequestQueue};
+
+    #[test]
+    fn test_adaptive_limit() {
+        let queue = Arc::new(RequestQueue::adaptive(8));
+        queue.record(true);
+        assert_eq!(queue.limit(), 4);
+        queue.record(true);
+        queue.record(true);
+        queue.record(true);
+        assert_eq!(queue.limit(), 1);
+
+        queue.record(false);
+        assert_eq!(queue.limit(), 2);
+        queue.record(false);
+        queue.record(false);
+        assert_eq!(queue.limit(), 3);
+
+        let fixed = Arc::new(RequestQueue::new(8));
+        fixed.record(true);
+        assert_eq!(fixed.limit(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_priority_order() {
+        let queue = Arc::new(RequestQueue::new(1));
+        let held = queue.acquire(RequestPriority::Low).await;
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut handles = Vec::new();
+        for (name, priority) in [
+            ("low", RequestPriority::Low),
+            ("high", RequestPriority::High),
+            ("normal", RequestPriority::Normal),
+            ("second high", RequestPriority::High),
+        ] {
+            let queue = queue.clone();
+            let order = order.clone();
+            handles.push(tokio::spawn(async move {
+                let _permit = queue.acquire(priority).await;
+                order.lock().unwrap().push(name);
+            }));
+            // Let the task join the queue before the next one
+            tokio::task::yield_now().await;
+        }
+
+        drop(held);
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        assert_eq!(
+            *order.lock().unwrap(),
+            vec!["high", "second high", "normal", "low"]
+        );
+    }
+}
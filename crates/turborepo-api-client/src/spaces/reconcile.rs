@@ -0,0 +1,217 @@
+use std::collections::HashSet;
+
+use reqwest::Method;
+use serde::Deserialize;
+
+use super::{FinishOutcome, RunId, RunStatus, SpaceId, SpaceTaskSummary, TaskUploadOutcome};
+use crate::{retry, APIAuth, APIClient, Error};
+
+/// What was recorded locally about a run, e.g. before the process that ran
+/// it was interrupted
+#[derive(Debug, Clone, Default)]
+pub struct LocalRunState {
+    pub tasks: Vec<SpaceTaskSummary>,
+    /// When the run finished, if it did
+    pub end_time: Option<i64>,
+    pub exit_code: i32,
+}
+
+/// What `reconcile_run` changed on the server
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReconcileReport {
+    /// The keys of the task summaries that were missing and were uploaded
+    pub uploaded: Vec<String>,
+    /// The keys of the missing task summaries the server rejected, with the
+    /// reason it gave
+    pub rejected: Vec<(String, String)>,
+    /// How the run was finished, if it was still running on the server but
+    /// finished locally
+    pub finish: Option<FinishOutcome>,
+}
+
+// `tasks` is required: a view without it can't tell which summaries are
+// missing, and treating it as empty would upload every one of them again
+#[derive(Deserialize)]
+struct RunView {
+    status: RunStatus,
+    tasks: Vec<TaskView>,
+}
+
+#[derive(Deserialize)]
+struct TaskView {
+    key: String,
+}
+
+impl APIClient {
+    /// Repairs a run whose upload may have been interrupted, e.g. by a crash.
+    /// The server's view of the run is compared with `local`: task summaries
+    /// the server doesn't have are uploaded with `upload_task_batch`, one by
+    /// one if the server doesn't accept batches, and if the run finished
+    /// locally but is still running on the server, it's finished.
+    ///
+    /// Errors if the run can't be read, including when the server doesn't
+    /// list its task summaries, or if uploading or finishing it fails.
+    /// Nothing is done while spaces are disabled.
+    pub async fn reconcile_run(
+        &self,
+        space_id: &SpaceId,
+        run_id: &RunId,
+        api_auth: &APIAuth,
+        local: LocalRunState,
+    ) -> Result<ReconcileReport, Error> {
+        let mut report = ReconcileReport::default();
+        if self.spaces_disabled() {
+            return Ok(report);
+        }
+
+        self.check_space(space_id)?;
+        let url = format!("/v0/spaces/{}/runs/{}", space_id, run_id);
+        let view = self.get_run_view(&url, api_auth).await?;
+
+        let uploaded: HashSet<_> = view.tasks.into_iter().map(|task| task.key).collect();
+        let missing: Vec<_> = local
+            .tasks
+            .into_iter()
+            .filter(|task| !uploaded.contains(&task.key))
+            .collect();
+        if !missing.is_empty() {
+            let keys: Vec<_> = missing.iter().map(|task| task.key.clone()).collect();
+            let outcomes = self
+                .upload_task_batch(space_id, run_id, api_auth, missing)
+                .await?;
+            for (key, outcome) in keys.into_iter().zip(outcomes) {
+                match outcome {
+                    TaskUploadOutcome::Accepted => report.uploaded.push(key),
+                    TaskUploadOutcome::Rejected { reason } => report.rejected.push((key, reason)),
+                }
+            }
+        }
+
+        if let (RunStatus::Running, Some(end_time)) = (view.status, local.end_time) {
+            let outcome = self
+                .finish_space_run(space_id, run_id, api_auth, end_time, local.exit_code)
+                .await?;
+            report.finish = Some(outcome);
+        }
+
+        Ok(report)
+    }
+
+    async fn get_run_view(&self, url: &str, api_auth: &APIAuth) -> Result<RunView, Error> {
+        let request_builder = self
+            .create_request_builder(url, api_auth, Method::GET, None)
+            .await?;
+
+        Ok(retry::make_retryable_request(request_builder, self)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+
+    use super::{LocalRunState, ReconcileReport};
+    use crate::{
+        spaces::{FinishOutcome, SpaceTaskSummary, TASK_BATCHES_CAPABILITY},
+        testing::{set_capabilities, test_auth, Canned, CannedServer},
+    };
+
+    /// Starts a server where the run is still running with one task
+    /// uploaded
+    async fn start_server() -> CannedServer {
+        CannedServer::start(|request| match request.method.as_str() {
+            "GET" => Canned::json(r#"{"status":"running","tasks":[{"key":"a#build"}]}"#),
+            _ => Canned::ok(),
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_run() -> Result<()> {
+        let server = start_server().await;
+        let client = server.client();
+        set_capabilities(&client, &[TASK_BATCHES_CAPABILITY]);
+        let methods = || -> Vec<String> {
+            server
+                .requests()
+                .into_iter()
+                .map(|request| request.method)
+                .collect()
+        };
+        let api_auth = test_auth();
+        let local = LocalRunState {
+            tasks: ["a#build", "b#build"]
+                .map(|key| SpaceTaskSummary {
+                    key: key.to_string(),
+                    ..SpaceTaskSummary::default()
+                })
+                .into(),
+            end_time: Some(1_000),
+            exit_code: 0,
+        };
+
+        let report = client
+            .reconcile_run(&"space".into(), &"run".into(), &api_auth, local.clone())
+            .await?;
+        assert_eq!(
+            report,
+            ReconcileReport {
+                uploaded: vec!["b#build".to_string()],
+                rejected: Vec::new(),
+                finish: Some(FinishOutcome::Finished),
+            }
+        );
+        assert_eq!(methods(), ["GET", "POST", "PATCH"]);
+
+        // A run that's still running locally isn't finished
+        let local = LocalRunState {
+            tasks: local.tasks[..1].to_vec(),
+            end_time: None,
+            ..local
+        };
+        let report = client
+            .reconcile_run(&"space".into(), &"run".into(), &api_auth, local)
+            .await?;
+        assert_eq!(report, ReconcileReport::default());
+        assert_eq!(methods()[3..], ["GET"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_without_task_list() -> Result<()> {
+        let server = CannedServer::start(|request| match request.method.as_str() {
+            "GET" => Canned::json(r#"{"status":"running"}"#),
+            _ => Canned::ok(),
+        })
+        .await;
+        let client = server.client();
+        set_capabilities(&client, &[]);
+        let local = LocalRunState {
+            tasks: vec![SpaceTaskSummary {
+                key: "a#build".to_string(),
+                ..SpaceTaskSummary::default()
+            }],
+            end_time: Some(1_000),
+            exit_code: 0,
+        };
+
+        // Nothing is uploaded or finished if the missing tasks can't be told
+        assert!(client
+            .reconcile_run(&"space".into(), &"run".into(), &test_auth(), local)
+            .await
+            .is_err());
+        let methods: Vec<_> = server
+            .requests()
+            .into_iter()
+            .map(|request| request.method)
+            .collect();
+        assert_eq!(methods, ["GET"]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+use chrono::{DateTime, Utc};
+use futures::{stream, Stream};
+use reqwest::Method;
+use turborepo_vercel_api::{SpaceRunListing, SpaceRunsResponse};
+
+use super::{SpaceId, SpacesMethod};
+use crate::{retry, APIAuth, APIClient, Error};
+
+impl APIClient {
+    /// Lists the runs in a space. With `since`, only runs created or updated
+    /// after it are returned, so a dashboard can poll for new runs cheaply.
+    ///
+    /// The filter is applied by the server. Servers that don't support it
+    /// return the first page of runs, which is then filtered here instead,
+    /// so runs past the first page aren't returned by those servers.
+    pub async fn list_space_runs(
+        &self,
+        space_id: &SpaceId,
+        api_auth: &APIAuth,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<SpaceRunListing>, Error> {
+        self.check_space(space_id)?;
+
+        let url = match since {
+            Some(since) => format!(
+                "/v0/spaces/{}/runs?since={}",
+                space_id,
+                since.timestamp_millis()
+            ),
+            None => format!("/v0/spaces/{}/runs", space_id),
+        };
+        let response = self.get_runs_page(&url, api_auth).await?;
+
+        Ok(filter_since(response.runs, since))
+    }
+
+    /// Lists all the runs in a space, following the server's cursors. Pages
+    /// are only fetched as the stream is polled, so the runs never have to be
+    /// held in memory all at once.
+    ///
+    /// If a page can't be fetched, its error is yielded and the stream ends.
+    pub fn list_space_runs_stream<'a>(
+        &'a self,
+        space_id: &'a SpaceId,
+        api_auth: &'a APIAuth,
+    ) -> impl Stream<Item = Result<SpaceRunListing, Error>> + 'a {
+        let state = RunPages {
+            runs: Vec::new().into_iter(),
+            cursor: None,
+            done: false,
+        };
+        stream::unfold(state, move |mut state| async move {
+            loop {
+                if let Some(run) = state.runs.next() {
+                    return Some((Ok(run), state));
+                }
+                if state.done {
+                    return None;
+                }
+
+                let url = match state.cursor.take() {
+                    Some(cursor) => format!(
+                        "/v0/spaces/{}/runs?cursor={}",
+                        space_id,
+                        urlencoding::encode(&cursor)
+                    ),
+                    None => format!("/v0/spaces/{}/runs", space_id),
+                };
+                let page = match self.check_space(space_id) {
+                    Ok(()) => self.get_runs_page(&url, api_auth).await,
+                    Err(err) => Err(err),
+                };
+                match page {
+                    Ok(page) => {
+                        state.done = page.next.is_none();
+                        state.cursor = page.next;
+                        state.runs = page.runs.into_iter();
+                    }
+                    Err(err) => {
+                        state.done = true;
+                        return Some((Err(err), state));
+                    }
+                }
+            }
+        })
+    }
+
+    async fn get_runs_page(
+        &self,
+        url: &str,
+        api_auth: &APIAuth,
+    ) -> Result<SpaceRunsResponse, Error> {
+        let _permit = self.acquire_spaces_slot(SpacesMethod::ListRuns).await;
+        let request_builder = self
+            .create_request_builder(url, api_auth, Method::GET, None)
+            .await?;
+
+        Ok(retry::make_retryable_request(request_builder, self)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+}
+
+/// The state of `list_space_runs_stream`: the runs of the current page that
+/// haven't been yielded yet and where the next page starts
+struct RunPages {
+    runs: std::vec::IntoIter<SpaceRunListing>,
+    cursor: Option<String>,
+    done: bool,
+}
+
+fn filter_since(runs: Vec<SpaceRunListing>, since: Option<DateTime<Utc>>) -> Vec<SpaceRunListing> {
+    let Some(since) = since.map(|since| since.timestamp_millis()) else {
+        return runs;
+    };
+
+    runs.into_iter()
+        .filter(|run| run.created_at > since || run.updated_at > since)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+    use futures::StreamExt;
+    use turborepo_vercel_api::SpaceRunListing;
+
+    use super::filter_since;
+    use crate::{
+        spaces::SpaceId,
+        testing::{test_auth, Canned, CannedServer},
+        Error,
+    };
+
+    #[test]
+    fn test_filter_since() {
+        let run = |id: &str, created_at, updated_at| SpaceRunListing {
+            id: id.to_string(),
+            url: String::new(),
+            created_at,
+            updated_at,
+        };
+        let runs = vec![
+            run("old", 1_000, 1_000),
+            run("updated", 1_000, 3_000),
+            run("new", 3_000, 3_000),
+        ];
+
+        let since = Utc.timestamp_millis_opt(2_000).unwrap();
+        let ids: Vec<_> = filter_since(runs.clone(), Some(since))
+            .into_iter()
+            .map(|run| run.id)
+            .collect();
+        assert_eq!(ids, vec!["updated", "new"]);
+
+        assert_eq!(filter_since(runs, None).len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_list_space_runs_stream() -> anyhow::Result<()> {
+        // Two pages of runs, then the cursor of the third page is rejected
+        let server = CannedServer::start(|request| {
+            if request.path.ends_with("cursor=page%202") {
+                Canned::json(
+                    r#"{"runs":[{"id":"3","url":"","createdAt":0,"updatedAt":0}],"next":"page 3"}"#,
+                )
+            } else if request.path.contains("cursor=") {
+                Canned::status(404)
+            } else {
+                Canned::json(
+                    r#"{"runs":[
+                        {"id":"1","url":"","createdAt":0,"updatedAt":0},
+                        {"id":"2","url":"","createdAt":0,"updatedAt":0}
+                    ],"next":"page 2"}"#,
+                )
+            }
+        })
+        .await;
+
+        let client = server.client();
+        let space_id = SpaceId::from("space");
+        let api_auth = test_auth();
+        let items: Vec<_> = client
+            .list_space_runs_stream(&space_id, &api_auth)
+            .collect()
+            .await;
+
+        let ids: Vec<_> = items
+            .iter()
+            .filter_map(|item| item.as_ref().ok())
+            .map(|run| run.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["1", "2", "3"]);
+        assert_eq!(items.len(), 4);
+        assert!(matches!(&items[3], Err(Error::ReqwestError(err)) if err.status().is_some()));
+
+        Ok(())
+    }
+}
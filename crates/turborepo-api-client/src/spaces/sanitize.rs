@@ -0,0 +1,176 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+const REDACTED: &str = "<redacted>";
+
+lazy_static! {
+    // A flag or environment variable whose name suggests its value is a secret.
+    // The secret word has to be a whole part of the name, so `--auth-token`
+    // matches but `--author` doesn't.
+    static ref SECRET_NAME: Regex = Regex::new(
+        r"(?i)^-{0,2}([\w-]*[-_])?(token|secret|password|passwd|api[-_]?key|auth)([-_][\w-]*)?$"
+    )
+    .unwrap();
+    // An environment variable assignment before the command, e.g. `CI=1`
+    static ref ENV_ASSIGNMENT: Regex = Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*=").unwrap();
+}
+
+/// Canonicalizes a command before it's sent to the server. An absolute path to
+/// the binary, after any leading environment variable assignments, is
+/// shortened to the binary's name, and the values of arguments that look like
+/// secrets, e.g. `--token=...` or `NPM_TOKEN=...`, are redacted.
+pub(crate) fn sanitize_command(command: &str) -> String {
+    let mut words = Vec::new();
+    let mut redact_next = false;
+    let mut in_env = true;
+
+    for word in command.split_whitespace() {
+        let is_binary = in_env && !ENV_ASSIGNMENT.is_match(word);
+        in_env = in_env && !is_binary;
+
+        if redact_next && !word.starts_with('-') {
+            words.push(REDACTED.to_string());
+            redact_next = false;
+            continue;
+        }
+        redact_next = false;
+
+        if let Some((name, _)) = word.split_once('=') {
+            if SECRET_NAME.is_match(name) {
+                words.push(format!("{}={}", name, REDACTED));
+                continue;
+            }
+        } else if word.starts_with('-') && SECRET_NAME.is_match(word) {
+            // The value is the next argument, e.g. `--token abc`
+            redact_next = true;
+        }
+
+        if is_binary && is_absolute(word) {
+            let binary = word.rsplit(['/', '\\']).next().unwrap_or(word);
+            words.push(binary.to_string());
+            continue;
+        }
+
+        words.push(word.to_string());
+    }
+
+    words.join(" ")
+}
+
+/// Rewrites a command into a stable form for grouping runs:
+///
+/// - whitespace is normalized
+/// - absolute paths are shortened to their last component, e.g.
+///   `--cwd=/home/me/repo` to `--cwd=repo`
+/// - flags are sorted, each with the values that follow it, while the words
+///   before the first flag, e.g. `turbo run build`, stay in order
+pub(crate) fn canonicalize_command(command: &str) -> String {
+    let mut prefix = Vec::new();
+    let mut flags: Vec<Vec<String>> = Vec::new();
+
+    for word in command.split_whitespace() {
+        let word = match word.split_once('=') {
+            Some((name, value)) if is_absolute(value) => {
+                format!("{}={}", name, last_component(value))
+            }
+            _ if is_absolute(word) => last_component(word).to_string(),
+            _ => word.to_string(),
+        };
+
+        if word.starts_with('-') {
+            flags.push(vec![word]);
+        } else if let Some(flag) = flags.last_mut() {
+            flag.push(word);
+        } else {
+            prefix.push(word);
+        }
+    }
+
+    flags.sort();
+    prefix
+        .into_iter()
+        .chain(flags.into_iter().flatten())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn last_component(path: &str) -> &str {
+    path.trim_end_matches(['/', '\\'])
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or(path)
+}
+
+// Checked by hand rather than with `Path`, since the command may have been
+// run on a different platform
+fn is_absolute(word: &str) -> bool {
+    let bytes = word.as_bytes();
+    word.starts_with('/')
+        || word.starts_with('\\')
+        || (bytes.len() > 2 && bytes[0].is_ascii_alphabetic() && &bytes[1..3] == b":\\")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{canonicalize_command, sanitize_command};
+
+    #[test]
+    fn test_sanitize_command() {
+        assert_eq!(sanitize_command("turbo run build"), "turbo run build");
+        assert_eq!(
+            sanitize_command("/usr/local/bin/turbo run build --filter=web"),
+            "turbo run build --filter=web"
+        );
+        assert_eq!(
+            sanitize_command(r"C:\tools\turbo.exe run build"),
+            "turbo.exe run build"
+        );
+        assert_eq!(
+            sanitize_command("turbo run build --token=abc --team=acme"),
+            "turbo run build --token=<redacted> --team=acme"
+        );
+        assert_eq!(
+            sanitize_command("turbo run build --api-key abc --force"),
+            "turbo run build --api-key <redacted> --force"
+        );
+        assert_eq!(
+            sanitize_command("NPM_TOKEN=abc turbo run build"),
+            "NPM_TOKEN=<redacted> turbo run build"
+        );
+        // Only whole parts of a name make it a secret
+        assert_eq!(
+            sanitize_command("git log --author=me --auth-token=abc"),
+            "git log --author=me --auth-token=<redacted>"
+        );
+        assert_eq!(
+            sanitize_command("turbo run build --author me"),
+            "turbo run build --author me"
+        );
+        // The binary comes after the environment variables
+        assert_eq!(
+            sanitize_command("CI=1 NPM_TOKEN=abc /usr/local/bin/turbo run build"),
+            "CI=1 NPM_TOKEN=<redacted> turbo run build"
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_command() {
+        assert_eq!(canonicalize_command("turbo run build"), "turbo run build");
+        assert_eq!(
+            canonicalize_command("turbo  run build --filter web\t--force"),
+            "turbo run build --filter web --force"
+        );
+        assert_eq!(
+            canonicalize_command("/usr/local/bin/turbo run build --force --filter=web"),
+            canonicalize_command("turbo run build --filter=web --force")
+        );
+        assert_eq!(
+            canonicalize_command("turbo run lint --cwd=/home/me/repo/ --concurrency 2"),
+            "turbo run lint --concurrency 2 --cwd=repo"
+        );
+        assert_eq!(
+            canonicalize_command(r"C:\tools\turbo.exe run build"),
+            "turbo.exe run build"
+        );
+    }
+}
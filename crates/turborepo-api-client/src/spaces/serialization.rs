@@ -0,0 +1,33 @@
+use super::SpaceTaskSummary;
+
+impl SpaceTaskSummary {
+    /// Sets the summary's logs from captured output, replacing invalid UTF-8
+    /// with U+FFFD so the summary can always be serialized
+    pub fn with_log_bytes(mut self, logs: &[u8]) -> Self {
+        self.logs = String::from_utf8_lossy(logs).into_owned();
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::spaces::SpaceTaskSummary;
+
+    #[test]
+    fn test_log_bytes_with_invalid_utf8() -> anyhow::Result<()> {
+        let task = SpaceTaskSummary {
+            key: "web#build".to_string(),
+            ..SpaceTaskSummary::default()
+        }
+        .with_log_bytes(b"compiled \xF0\x28\x8C\xBC successfully\n");
+
+        assert_eq!(
+            task.logs,
+            "compiled \u{FFFD}(\u{FFFD}\u{FFFD} successfully\n"
+        );
+        let encoded = serde_json::to_value(&task)?;
+        assert_eq!(encoded["logs"], task.logs);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,352 @@
+use std::ops::Deref;
+
+use chrono::Local;
+use tokio::runtime::RuntimeFlavor;
+use turborepo_vercel_api::SpaceRun;
+
+use super::{
+    is_disabled_run, FinishOutcome, FinishSpaceRunPayload, RunId, RunPatch, RunRecording, SpaceId,
+    SpaceTaskSummary, Unflushed,
+};
+use crate::{APIAuth, APIClient, Error, Warning};
+
+// Exit code reported for runs that are aborted or finished by the session
+// being dropped
+pub(super) const ABANDONED_EXIT_CODE: i32 = 1;
+
+/// A run created by `create_space_run`, along with the space and credentials
+/// it was created with, so they don't have to be passed to every call. The
+/// `APIClient` methods these wrap remain available for callers that manage
+/// the identifiers themselves.
+///
+/// If the session is dropped before `finish` or `abort` is called, e.g.
+/// because the run orchestration panicked, the run is finished with a failing
+/// exit code so it isn't left as running on the dashboard.
+///
+/// Since `Drop` can't be async, on a multi thread tokio runtime the drop
+/// blocks until the finish request completes. On a current thread runtime the
+/// request is spawned instead, and `APIClient::shutdown` waits for it. If
+/// there is no runtime, the run is left as is.
+pub struct SpaceSession {
+    run: SpaceRun,
+    run_id: RunId,
+    space_id: SpaceId,
+    client: APIClient,
+    api_auth: APIAuth,
+    recording: Option<RunRecording>,
+    finished: bool,
+}
+
+impl SpaceSession {
+    pub(crate) fn new(
+        run: SpaceRun,
+        space_id: &SpaceId,
+        client: &APIClient,
+        api_auth: &APIAuth,
+        recording: Option<RunRecording>,
+    ) -> Self {
+        let run_id = RunId::from(run.id.clone());
+        if !is_disabled_run(&run) {
+            client.open_work.open_run(&run_id, space_id, api_auth);
+        }
+
+        Self {
+            run_id,
+            run,
+            space_id: space_id.clone(),
+            client: client.clone(),
+            api_auth: api_auth.clone(),
+            recording,
+            finished: false,
+        }
+    }
+
+    /// Stops `APIClient::shutdown` from finishing the run. Returns false if
+    /// it's already finishing it.
+    fn release(&mut self) -> bool {
+        self.finished = true;
+        is_disabled_run(&self.run) || self.client.open_work.release_run(&self.run_id)
+    }
+
+    pub fn run(&self) -> &SpaceRun {
+        &self.run
+    }
+
+    /// The id to pass to other methods for this run, e.g.
+    /// `create_task_summary`
+    pub fn run_id(&self) -> &RunId {
+        &self.run_id
+    }
+
+    pub fn space_id(&self) -> &SpaceId {
+        &self.space_id
+    }
+
+    /// Uploads a task summary to the run, see `APIClient::create_task_summary`
+    pub async fn upload_task(&self, summary: SpaceTaskSummary) -> Result<(), Error> {
+        let recorded = self.recording.is_some().then(|| summary.clone());
+        self.client
+            .create_task_summary(&self.space_id, &self.run_id, &self.api_auth, summary)
+            .await?;
+        if let (Some(recording), Some(summary)) = (&self.recording, recorded) {
+            recording.record_task(summary);
+        }
+
+        Ok(())
+    }
+
+    /// Uploads several task summaries to the run concurrently, see
+    /// `APIClient::create_task_summaries`
+    pub async fn upload_tasks(&self, summaries: Vec<SpaceTaskSummary>) -> Vec<Result<(), Error>> {
+        let recorded = self.recording.is_some().then(|| summaries.clone());
+        let results = self
+            .client
+            .create_task_summaries(&self.space_id, &self.run_id, &self.api_auth, summaries)
+            .await;
+        if let (Some(recording), Some(summaries)) = (&self.recording, recorded) {
+            for (summary, result) in summaries.into_iter().zip(&results) {
+                if result.is_ok() {
+                    recording.record_task(summary);
+                }
+            }
+        }
+
+        results
+    }
+
+    /// What the session has uploaded so far, if run recording is enabled,
+    /// see `APIClient::with_run_recording`
+    pub fn recording(&self) -> Option<&RunRecording> {
+        self.recording.as_ref()
+    }
+
+    /// Reads back a task summary, see `APIClient::get_task_summary`
+    pub async fn get_task(&self, task_key: &str) -> Result<SpaceTaskSummary, Error> {
+        self.client
+            .get_task_summary(&self.space_id, &self.run_id, task_key, &self.api_auth)
+            .await
+    }
+
+    /// Appends a chunk of a task's logs, see `APIClient::append_task_logs`
+    pub async fn append_task_logs(
+        &self,
+        task_key: &str,
+        chunk: &str,
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.client
+            .append_task_logs(
+                &self.space_id,
+                &self.run_id,
+                task_key,
+                &self.api_auth,
+                chunk,
+                offset,
+            )
+            .await
+    }
+
+    /// Updates the run's metadata, see `APIClient::update_space_run`
+    pub async fn update(&self, patch: &RunPatch) -> Result<(), Error> {
+        self.client
+            .update_space_run(&self.space_id, &self.run_id, &self.api_auth, patch)
+            .await
+    }
+
+    pub async fn finish(mut self, end_time: i64, exit_code: i32) -> Result<FinishOutcome, Error> {
+        self.finish_with_payload(&FinishSpaceRunPayload::new(end_time, exit_code))
+            .await
+    }
+
+    pub(crate) async fn finish_with_payload(
+        &mut self,
+        payload: &FinishSpaceRunPayload,
+    ) -> Result<FinishOutcome, Error> {
+        // Even if the request fails or is cancelled, we don't want to try to
+        // finish the run a second time on drop
+        if !self.release() {
+            return Ok(FinishOutcome::AlreadyFinished);
+        }
+        let outcome = self
+            .client
+            .send_finish_payload(&self.space_id, &self.run_id, &self.api_auth, payload)
+            .await?;
+        if let Some(recording) = &self.recording {
+            if outcome != FinishOutcome::Deferred {
+                recording.record_finish(payload);
+            }
+        }
+
+        Ok(outcome)
+    }
+
+    /// Finishes the run now with a failing exit code, e.g. when the run is
+    /// interrupted. Unlike dropping the session, this waits for the run to be
+    /// finished.
+    pub async fn abort(mut self) -> Result<FinishOutcome, Error> {
+        let payload =
+            FinishSpaceRunPayload::new(Local::now().timestamp_millis(), ABANDONED_EXIT_CODE);
+        self.finish_with_payload(&payload).await
+    }
+
+    /// Releases the run without finishing it. The caller becomes responsible
+    /// for calling `finish_space_run`.
+    pub fn disarm(mut self) -> SpaceRun {
+        self.release();
+        self.run.clone()
+    }
+}
+
+impl Deref for SpaceSession {
+    type Target = SpaceRun;
+
+    fn deref(&self) -> &Self::Target {
+        &self.run
+    }
+}
+
+impl Drop for SpaceSession {
+    fn drop(&mut self) {
+        if self.finished || is_disabled_run(&self.run) {
+            return;
+        }
+
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        if !self.release() {
+            return;
+        }
+
+        let client = self.client.clone();
+        let api_auth = self.api_auth.clone();
+        let space_id = std::mem::take(&mut self.space_id);
+        let run_id = std::mem::take(&mut self.run_id);
+        let recording = self.recording.take();
+        let payload =
+            FinishSpaceRunPayload::new(Local::now().timestamp_millis(), ABANDONED_EXIT_CODE);
+        let unflushed = Unflushed::Run {
+            run_id: run_id.to_string(),
+        };
+        let finish = async move {
+            // There's nobody left to report an error to, except `shutdown`
+            let result = client
+                .send_finish_payload(&space_id, &run_id, &api_auth, &payload)
+                .await;
+            let finished = matches!(
+                result,
+                Ok(FinishOutcome::Finished | FinishOutcome::AlreadyFinished)
+            );
+            if finished {
+                if let Some(recording) = recording {
+                    recording.record_finish(&payload);
+                }
+                client.warn(Warning::AbandonedRunFinished {
+                    run_id: run_id.to_string(),
+                });
+            }
+            finished
+        };
+
+        // The finish is bounded by the client's finish timeout, so blocking
+        // the worker for it is fine. A current thread runtime can't block
+        // without stalling the request, so there it's left to `shutdown`.
+        match handle.runtime_flavor() {
+            RuntimeFlavor::MultiThread => {
+                tokio::task::block_in_place(|| handle.block_on(finish));
+            }
+            _ => self.client.open_work.spawn(unflushed, finish),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use chrono::Local;
+    use serde_json::Value;
+
+    use crate::{
+        spaces::CreateSpaceRunPayload,
+        testing::{test_auth, Canned, CannedServer},
+        Warning, WarningSink,
+    };
+
+    #[tokio::test]
+    async fn test_drop_finishes_run() -> Result<()> {
+        let server = CannedServer::start(|request| match request.method.as_str() {
+            "POST" => Canned::json(r#"{"id":"run","url":"https://example.com"}"#),
+            _ => Canned::ok(),
+        })
+        .await;
+        let warnings = WarningSink::new();
+        let client = server.client().with_warnings(warnings.clone());
+        let payload = CreateSpaceRunPayload::new(
+            Local::now(),
+            "turbo run build",
+            None,
+            None,
+            None,
+            "".to_string(),
+            "".to_string(),
+        );
+
+        let session = client
+            .create_space_run(&"space".into(), &test_auth(), payload)
+            .await?;
+        drop(session);
+
+        // The finish spawned by the drop is what `shutdown` waits for
+        assert!(client.shutdown(Duration::from_secs(5)).await.is_empty());
+        let requests = server.requests();
+        let finish = requests.last().unwrap();
+        assert_eq!(finish.method, "PATCH");
+        assert_eq!(finish.path, "/v0/spaces/space/runs/run");
+        let finish: Value = serde_json::from_slice(&finish.body)?;
+        assert_eq!(finish["exitCode"], 1);
+        assert_eq!(
+            warnings.take(),
+            [Warning::AbandonedRunFinished {
+                run_id: "run".to_string()
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_drop_finishes_run_without_shutdown() -> Result<()> {
+        let server = CannedServer::start(|request| match request.method.as_str() {
+            "POST" => Canned::json(r#"{"id":"run","url":"https://example.com"}"#),
+            _ => Canned::ok(),
+        })
+        .await;
+        let client = server.client();
+        let payload = CreateSpaceRunPayload::new(
+            Local::now(),
+            "turbo run build",
+            None,
+            None,
+            None,
+            "".to_string(),
+            "".to_string(),
+        );
+
+        let session = client
+            .create_space_run(&"space".into(), &test_auth(), payload)
+            .await?;
+        drop(session);
+
+        // The drop only returns once the finish was sent
+        let requests = server.requests();
+        let finish = requests.last().unwrap();
+        assert_eq!(finish.method, "PATCH");
+        assert_eq!(finish.path, "/v0/spaces/space/runs/run");
+        let finish: Value = serde_json::from_slice(&finish.body)?;
+        assert_eq!(finish["exitCode"], 1);
+
+        Ok(())
+    }
+}
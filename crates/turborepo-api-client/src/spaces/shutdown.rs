@@ -0,0 +1,208 @@
+use std::{collections::HashMap, fmt, future::Future, sync::Mutex, time::Duration};
+
+use chrono::Local;
+use tokio::{
+    sync::watch,
+    task::JoinHandle,
+    time::{timeout_at, Instant},
+};
+
+use super::{session::ABANDONED_EXIT_CODE, FinishOutcome, FinishSpaceRunPayload, RunId, SpaceId};
+use crate::{APIAuth, APIClient};
+
+/// Work that `APIClient::shutdown` couldn't flush before its deadline, or
+/// that failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Unflushed {
+    /// A run that couldn't be finished
+    Run { run_id: String },
+    /// A summary stream whose summaries weren't all acknowledged
+    SummaryStream { run_id: String },
+}
+
+impl fmt::Display for Unflushed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unflushed::Run { run_id } => write!(f, "run {run_id}"),
+            Unflushed::SummaryStream { run_id } => write!(f, "the summary stream of run {run_id}"),
+        }
+    }
+}
+
+struct OpenRun {
+    space_id: SpaceId,
+    api_auth: APIAuth,
+}
+
+/// The runs and background uploads that are still open, shared by an
+/// `APIClient` and its clones so that `shutdown` can flush them
+pub(crate) struct OpenWork {
+    runs: Mutex<HashMap<RunId, OpenRun>>,
+    background: Mutex<Vec<(Unflushed, JoinHandle<bool>)>>,
+    closing: watch::Sender<bool>,
+}
+
+impl Default for OpenWork {
+    fn default() -> Self {
+        Self {
+            runs: Mutex::default(),
+            background: Mutex::default(),
+            closing: watch::channel(false).0,
+        }
+    }
+}
+
+impl OpenWork {
+    pub(crate) fn open_run(&self, run_id: &RunId, space_id: &SpaceId, api_auth: &APIAuth) {
+        self.runs.lock().expect("open runs lock poisoned").insert(
+            run_id.clone(),
+            OpenRun {
+                space_id: space_id.clone(),
+                api_auth: api_auth.clone(),
+            },
+        );
+    }
+
+    /// Stops tracking a run because its session finished it or gave it up.
+    /// Returns false if the run was already released, i.e. `shutdown` is
+    /// finishing it.
+    pub(crate) fn release_run(&self, run_id: &RunId) -> bool {
+        self.runs
+            .lock()
+            .expect("open runs lock poisoned")
+            .remove(run_id)
+            .is_some()
+    }
+
+    /// Spawns `work` onto the current runtime, so that `shutdown` waits for
+    /// it. The future resolves to whether the work was flushed.
+    pub(crate) fn spawn(
+        &self,
+        unflushed: Unflushed,
+        work: impl Future<Output = bool> + Send + 'static,
+    ) {
+        let mut background = self.background.lock().expect("background lock poisoned");
+        background.retain(|(_, handle)| !handle.is_finished());
+        background.push((unflushed, tokio::spawn(work)));
+    }
+
+    /// Changes to true once `shutdown` is called
+    pub(crate) fn closing(&self) -> watch::Receiver<bool> {
+        self.closing.subscribe()
+    }
+}
+
+impl APIClient {
+    /// Flushes the spaces work that's still in flight, e.g. when `turbo`
+    /// exits, waiting at most `deadline`:
+    ///
+    /// - open summary streams are ended, and their summaries are waited on
+    /// - runs whose `SpaceSession` is still open are finished with a failing
+    ///   exit code. Finishing such a session afterwards is a no-op that returns
+    ///   `FinishOutcome::AlreadyFinished`.
+    /// - finishes spawned by dropped sessions are waited on
+    ///
+    /// Returns the work that failed or didn't complete before the deadline,
+    /// which is cancelled. Summary streams opened after `shutdown` are ended
+    /// right away.
+    pub async fn shutdown(&self, deadline: Duration) -> Vec<Unflushed> {
+        let deadline = Instant::now() + deadline;
+        self.open_work.closing.send_replace(true);
+
+        let runs =
+            std::mem::take(&mut *self.open_work.runs.lock().expect("open runs lock poisoned"));
+        for (run_id, OpenRun { space_id, api_auth }) in runs {
+            let client = self.clone();
+            let payload =
+                FinishSpaceRunPayload::new(Local::now().timestamp_millis(), ABANDONED_EXIT_CODE);
+            let unflushed = Unflushed::Run {
+                run_id: run_id.to_string(),
+            };
+            self.open_work.spawn(unflushed, async move {
+                client
+                    .send_finish_payload(&space_id, &run_id, &api_auth, &payload)
+                    .await
+                    .is_ok_and(|outcome| outcome != FinishOutcome::Deferred)
+            });
+        }
+
+        let background = std::mem::take(
+            &mut *self
+                .open_work
+                .background
+                .lock()
+                .expect("background lock poisoned"),
+        );
+        let mut unflushed = Vec::new();
+        for (work, mut handle) in background {
+            match timeout_at(deadline, &mut handle).await {
+                Ok(Ok(true)) => {}
+                Ok(_) => unflushed.push(work),
+                Err(_) => {
+                    handle.abort();
+                    unflushed.push(work);
+                }
+            }
+        }
+
+        unflushed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use chrono::Local;
+    use turborepo_vercel_api_mock::{start_test_server, EXPECTED_SPACE_ID};
+
+    use crate::{
+        spaces::{CreateSpaceRunPayload, FinishOutcome, SpaceTaskSummary, Unflushed},
+        testing::test_auth,
+        APIClient,
+    };
+
+    #[tokio::test]
+    async fn test_shutdown() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+        let client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0", false)?;
+        let api_auth = test_auth();
+
+        let payload = CreateSpaceRunPayload::new(
+            Local::now(),
+            "turbo run build",
+            None,
+            None,
+            None,
+            "".to_string(),
+            "".to_string(),
+        );
+        let session = client
+            .create_space_run(&EXPECTED_SPACE_ID.into(), &api_auth, payload)
+            .await?;
+        // The mock server doesn't have the stream endpoint, so the stream
+        // can't be flushed
+        let stream = client
+            .open_summary_stream(session.space_id(), session.run_id(), &api_auth)
+            .await?;
+        stream.push(&SpaceTaskSummary::default())?;
+
+        assert_eq!(
+            client.shutdown(Duration::from_secs(5)).await,
+            vec![Unflushed::SummaryStream {
+                run_id: session.run_id().to_string()
+            }]
+        );
+        // The run was finished by the shutdown
+        assert!(matches!(
+            session.finish(0, 0).await?,
+            FinishOutcome::AlreadyFinished
+        ));
+        assert!(stream.close().await.is_err());
+
+        handle.abort();
+        Ok(())
+    }
+}
@@ -0,0 +1,110 @@
+use reqwest::{Method, StatusCode};
+use turborepo_vercel_api::SpaceStatsResponse;
+
+use super::{SpaceId, SpacesMethod};
+use crate::{retry, APIAuth, APIClient, Error};
+
+/// A time range, as millisecond timestamps, to aggregate run statistics over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatsRange {
+    pub start: i64,
+    pub end: i64,
+}
+
+/// Aggregated run statistics for a space
+#[derive(Debug, Clone)]
+pub enum SpaceStats {
+    Available(SpaceStatsResponse),
+    /// The server doesn't support aggregated statistics, so they need to be
+    /// computed from the space's runs instead
+    Unsupported,
+}
+
+impl APIClient {
+    pub async fn get_space_stats(
+        &self,
+        space_id: &SpaceId,
+        api_auth: &APIAuth,
+        range: StatsRange,
+    ) -> Result<SpaceStats, Error> {
+        self.check_space(space_id)?;
+
+        let _permit = self.acquire_spaces_slot(SpacesMethod::Stats).await;
+        let url = format!(
+            "/v0/spaces/{}/stats?start={}&end={}",
+            space_id, range.start, range.end
+        );
+        let request_builder = self
+            .create_request_builder(&url, api_auth, Method::GET, None)
+            .await?;
+
+        let response = retry::make_retryable_request(request_builder, self).await?;
+
+        // Servers without the endpoint respond with a 404, since the route
+        // doesn't exist, or explicitly with a 501
+        if matches!(
+            response.status(),
+            StatusCode::NOT_FOUND | StatusCode::NOT_IMPLEMENTED
+        ) {
+            return Ok(SpaceStats::Unsupported);
+        }
+
+        Ok(SpaceStats::Available(
+            response.error_for_status()?.json().await?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use turborepo_vercel_api_mock::{
+        start_test_server, EXPECTED_SPACE_ID, EXPECTED_SPACE_RUN_COUNT,
+    };
+
+    use super::{SpaceStats, StatsRange};
+    use crate::{
+        testing::{test_auth, Canned, CannedServer},
+        APIClient,
+    };
+
+    const RANGE: StatsRange = StatsRange {
+        start: 1_000,
+        end: 2_000,
+    };
+
+    #[tokio::test]
+    async fn test_get_space_stats() -> anyhow::Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+        let client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0", false)?;
+        let SpaceStats::Available(stats) = client
+            .get_space_stats(&EXPECTED_SPACE_ID.into(), &test_auth(), RANGE)
+            .await?
+        else {
+            panic!("expected stats to be available");
+        };
+        assert_eq!(stats.run_count, EXPECTED_SPACE_RUN_COUNT);
+        assert_eq!(stats.cache_hit_rate, 0.5);
+        handle.abort();
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_space_stats() -> anyhow::Result<()> {
+        for status in [404, 501] {
+            let server = CannedServer::always(Canned::status(status)).await;
+            let stats = server
+                .client()
+                .get_space_stats(&"space".into(), &test_auth(), RANGE)
+                .await?;
+            assert!(matches!(stats, SpaceStats::Unsupported));
+            assert_eq!(
+                server.requests()[0].path,
+                "/v0/spaces/space/stats?start=1000&end=2000"
+            );
+        }
+
+        Ok(())
+    }
+}
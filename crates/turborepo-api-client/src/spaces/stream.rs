@@ -0,0 +1,521 @@
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use futures::stream;
+use reqwest::{
+    header::{HeaderMap, HeaderValue, CONTENT_TYPE},
+    Method, RequestBuilder,
+};
+use tokio::sync::{mpsc, oneshot, watch};
+
+use super::{RunId, SpaceId, SpaceTaskSummary, Unflushed};
+use crate::{retry, APIAuth, APIClient, Error, Warning};
+
+// The stream stays open for as long as the run, so the client's timeout,
+// which bounds a whole request, can't apply. reqwest falls back to the
+// client's timeout for requests without one, so the stream gets one that
+// never fires instead.
+const NO_TIMEOUT: Duration = Duration::MAX;
+
+/// How a `SummaryStream` is reopened after its connection drops. The delay
+/// before each reconnect doubles, like the delay between retries of other
+/// requests, so a struggling server isn't hit with a new request right away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamReconnectPolicy {
+    /// The number of times the stream is reopened before giving up
+    pub reconnects: u32,
+    /// The delay before the first reconnect
+    pub delay: Duration,
+    /// The longest delay before a reconnect
+    pub max_delay: Duration,
+}
+
+impl Default for StreamReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            reconnects: 3,
+            delay: Duration::from_secs(2),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+impl StreamReconnectPolicy {
+    fn delay(&self, reconnects: u32) -> Duration {
+        self.delay
+            .saturating_mul(2_u32.saturating_pow(reconnects))
+            .min(self.max_delay)
+    }
+}
+
+/// A single long-lived upload of task summaries, written as newline
+/// delimited JSON to one chunked request. Created by `open_summary_stream`.
+///
+/// The request is opened once the first summary is pushed. It isn't limited
+/// by the client's timeout, but it's sent like any other request otherwise,
+/// e.g. it takes up a slot of `APIClient::with_max_in_flight_requests` while
+/// it's open. Its body can't be sent twice, so redirects aren't followed.
+///
+/// If the connection drops, the stream is reopened, see
+/// `APIClient::with_stream_reconnect_policy`, and every summary that the
+/// server hasn't acknowledged yet is sent again. The server only
+/// acknowledges summaries by successfully completing the request, so it must
+/// handle receiving a summary twice. If the server completes the request
+/// early, the next summary opens a new one.
+pub struct SummaryStream {
+    // `None` when spaces are disabled
+    inner: Option<StreamWorker>,
+}
+
+struct StreamWorker {
+    client: APIClient,
+    run_id: RunId,
+    tx: mpsc::UnboundedSender<Line>,
+    result: oneshot::Receiver<Result<(), Error>>,
+}
+
+/// A summary written to the stream, along with its key if `push` recorded
+/// it, so it can be forgotten if the summary is never acknowledged
+#[derive(Clone)]
+struct Line {
+    key: Option<String>,
+    bytes: Bytes,
+}
+
+impl SummaryStream {
+    /// Queues a summary to be written to the stream.
+    pub fn push(&self, summary: &SpaceTaskSummary) -> Result<(), Error> {
+        let Some(StreamWorker {
+            client, run_id, tx, ..
+        }) = &self.inner
+        else {
+            return Ok(());
+        };
+
+        let clamped = client.task_with_checked_times(run_id, summary)?;
+        let summary = clamped.as_ref().unwrap_or(summary);
+        let key = client
+            .record_task_key(run_id, &summary.key)?
+            .then(|| summary.key.clone());
+        let encoded = if client.log_upload_policy.uploads_logs(summary) {
+            client.encode_payload(summary)
+        } else {
+            client.encode_payload(&SpaceTaskSummary {
+                logs: String::new(),
+                ..summary.clone()
+            })
+        };
+        let mut bytes = match encoded {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                if let Some(key) = &key {
+                    client.forget_task_key(run_id, key);
+                }
+                return Err(err);
+            }
+        };
+        bytes.push(b'\n');
+        let line = Line {
+            key,
+            bytes: bytes.into(),
+        };
+        // The worker only stops early if the stream failed, which `close`
+        // reports
+        if let Err(mpsc::error::SendError(line)) = tx.send(line) {
+            if let Some(key) = &line.key {
+                client.forget_task_key(run_id, key);
+            }
+        }
+        Ok(())
+    }
+
+    /// Ends the stream and waits for the server to acknowledge every summary.
+    pub async fn close(self) -> Result<(), Error> {
+        let Some(StreamWorker { tx, result, .. }) = self.inner else {
+            return Ok(());
+        };
+
+        drop(tx);
+        // The worker is only dropped without a result if `shutdown` cancelled
+        // it
+        result.await.map_err(|_| Error::SummaryStreamClosed)?
+    }
+}
+
+impl APIClient {
+    /// Opens a `SummaryStream` for uploading a run's task summaries over a
+    /// single request, instead of a request per summary.
+    ///
+    /// Since the body is streamed, HMAC signatures only cover the method and
+    /// the URL.
+    pub async fn open_summary_stream(
+        &self,
+        space_id: &SpaceId,
+        run_id: &RunId,
+        api_auth: &APIAuth,
+    ) -> Result<SummaryStream, Error> {
+        if self.spaces_disabled() {
+            return Ok(SummaryStream { inner: None });
+        }
+
+        self.check_space(space_id)?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/x-ndjson"),
+        );
+        let request_builder = self
+            .create_request_builder(
+                &format!("/v0/spaces/{}/runs/{}/tasks/stream", space_id, run_id),
+                api_auth,
+                Method::POST,
+                None,
+            )
+            .await?
+            .headers(headers);
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (result_tx, result) = oneshot::channel();
+        let worker = run_stream(
+            self.clone(),
+            run_id.clone(),
+            request_builder,
+            rx,
+            self.open_work.closing(),
+        );
+        let unflushed = Unflushed::SummaryStream {
+            run_id: run_id.to_string(),
+        };
+        self.open_work.spawn(unflushed, async move {
+            let result = worker.await;
+            let flushed = result.is_ok();
+            let _ = result_tx.send(result);
+            flushed
+        });
+
+        Ok(SummaryStream {
+            inner: Some(StreamWorker {
+                client: self.clone(),
+                run_id: run_id.clone(),
+                tx,
+                result,
+            }),
+        })
+    }
+}
+
+async fn run_stream(
+    client: APIClient,
+    run_id: RunId,
+    request_builder: RequestBuilder,
+    mut summaries: mpsc::UnboundedReceiver<Line>,
+    closing: watch::Receiver<bool>,
+) -> Result<(), Error> {
+    let mut unacknowledged = Vec::new();
+    let result = stream_summaries(
+        &client,
+        &request_builder,
+        &mut summaries,
+        closing,
+        &mut unacknowledged,
+    )
+    .await;
+    if result.is_err() {
+        // The summaries that weren't acknowledged can be uploaded again, e.g.
+        // with `create_task_summary`, without being reported as duplicates
+        summaries.close();
+        while let Ok(line) = summaries.try_recv() {
+            unacknowledged.push(line);
+        }
+        for key in unacknowledged.into_iter().filter_map(|line| line.key) {
+            client.forget_task_key(&run_id, &key);
+        }
+    }
+
+    result
+}
+
+async fn stream_summaries(
+    client: &APIClient,
+    request_builder: &RequestBuilder,
+    summaries: &mut mpsc::UnboundedReceiver<Line>,
+    mut closing: watch::Receiver<bool>,
+    unacknowledged: &mut Vec<Line>,
+) -> Result<(), Error> {
+    let policy = client.stream_reconnect_policy;
+    let mut summaries_done = false;
+    let mut reconnects = 0;
+
+    loop {
+        // A request is only opened once there's something to send, so a
+        // server that completes the request early isn't sent empty ones
+        if unacknowledged.is_empty() {
+            if summaries_done {
+                return Ok(());
+            }
+            tokio::select! {
+                line = summaries.recv() => match line {
+                    Some(line) => unacknowledged.push(line),
+                    None => return Ok(()),
+                },
+                _ = closing.wait_for(|closing| *closing) => {
+                    end_summaries(summaries, unacknowledged, None);
+                    summaries_done = true;
+                    continue;
+                }
+            }
+        }
+        if client.network.is_offline() {
+            return Err(Error::Offline);
+        }
+
+        let (body_tx, body_rx) = mpsc::unbounded_channel::<Bytes>();
+        for line in unacknowledged.iter() {
+            let _ = body_tx.send(line.bytes.clone());
+        }
+        let mut body_tx = (!summaries_done).then_some(body_tx);
+
+        let body = stream::unfold(body_rx, |mut rx| async move {
+            let line = rx.recv().await?;
+            Some((Ok::<_, std::io::Error>(line), rx))
+        });
+        retry::wait_to_send(client).await;
+        let (http_client, request) = request_builder
+            .try_clone()
+            .expect("cannot clone request")
+            .body(reqwest::Body::wrap_stream(body))
+            .timeout(NO_TIMEOUT)
+            .build_split();
+        let response =
+            retry::send_attempt(client, &http_client, request?, reconnects, Instant::now());
+        tokio::pin!(response);
+
+        let result = loop {
+            tokio::select! {
+                line = summaries.recv(), if !summaries_done => match line {
+                    Some(line) => {
+                        if let Some(body_tx) = &body_tx {
+                            let _ = body_tx.send(line.bytes.clone());
+                        }
+                        unacknowledged.push(line);
+                    }
+                    None => {
+                        // Ends the request body
+                        summaries_done = true;
+                        body_tx = None;
+                    }
+                },
+                // On shutdown, the summaries pushed so far are sent and the
+                // body is ended
+                _ = closing.wait_for(|closing| *closing), if !summaries_done => {
+                    end_summaries(summaries, unacknowledged, body_tx.take());
+                    summaries_done = true;
+                }
+                result = &mut response => break result.and_then(|r| r.error_for_status()),
+            }
+        };
+
+        match result {
+            // Everything written so far has been received
+            Ok(_) => unacknowledged.clear(),
+            Err(err) if reconnects >= policy.reconnects => return Err(err.into()),
+            Err(_) => {
+                client.warn(Warning::SummaryStreamReconnected);
+                // Summaries pushed in the meantime are queued and sent with
+                // the next request. Once the client shuts down, the remaining
+                // reconnects aren't delayed, since shutdown has its own
+                // deadline.
+                tokio::select! {
+                    _ = tokio::time::sleep(policy.delay(reconnects)) => {}
+                    _ = closing.wait_for(|closing| *closing) => {}
+                }
+                reconnects += 1;
+            }
+        }
+    }
+}
+
+/// Takes the summaries that were pushed before the stream started closing,
+/// writing them to the body if there is one, which is then ended
+fn end_summaries(
+    summaries: &mut mpsc::UnboundedReceiver<Line>,
+    unacknowledged: &mut Vec<Line>,
+    body_tx: Option<mpsc::UnboundedSender<Bytes>>,
+) {
+    while let Ok(line) = summaries.try_recv() {
+        if let Some(body_tx) = &body_tx {
+            let _ = body_tx.send(line.bytes.clone());
+        }
+        unacknowledged.push(line);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::{Duration, Instant},
+    };
+
+    use anyhow::Result;
+    use serde_json::Value;
+
+    use crate::{
+        spaces::{DuplicateTaskKeyPolicy, SpaceTaskSummary, StreamReconnectPolicy, SummaryStream},
+        testing::{test_auth, Canned, CannedServer},
+        APIClient, Error, Warning, WarningSink,
+    };
+
+    /// Reconnects without the default delays, which add up to 14s
+    fn fast_reconnects(client: APIClient) -> APIClient {
+        client.with_stream_reconnect_policy(StreamReconnectPolicy {
+            delay: Duration::from_millis(1),
+            ..StreamReconnectPolicy::default()
+        })
+    }
+
+    async fn open(client: &APIClient) -> Result<SummaryStream> {
+        Ok(client
+            .open_summary_stream(&"space".into(), &"run".into(), &test_auth())
+            .await?)
+    }
+
+    fn summary(key: &str) -> SpaceTaskSummary {
+        SpaceTaskSummary {
+            key: key.to_string(),
+            ..SpaceTaskSummary::default()
+        }
+    }
+
+    /// The keys of the summaries in each request the server received
+    fn keys(server: &CannedServer) -> Vec<Vec<String>> {
+        server
+            .requests()
+            .iter()
+            .map(|request| {
+                request
+                    .text()
+                    .lines()
+                    .map(|line| {
+                        let summary: Value = serde_json::from_str(line).unwrap();
+                        summary["key"].as_str().unwrap().to_string()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_stream_reconnects() -> Result<()> {
+        // Only the first request fails
+        let requests = AtomicUsize::new(0);
+        let server = CannedServer::start(move |_| match requests.fetch_add(1, Ordering::SeqCst) {
+            0 => Canned::status(500),
+            _ => Canned::ok(),
+        })
+        .await;
+        let warnings = WarningSink::new();
+        let client = fast_reconnects(server.client()).with_warnings(warnings.clone());
+
+        let stream = open(&client).await?;
+        stream.push(&summary("a#build"))?;
+        stream.push(&summary("b#build"))?;
+        stream.close().await?;
+
+        // The summaries the failed request didn't get acknowledged are sent
+        // again
+        assert_eq!(
+            keys(&server),
+            [["a#build", "b#build"], ["a#build", "b#build"]]
+        );
+        assert_eq!(warnings.take(), [Warning::SummaryStreamReconnected]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stream_gives_up() -> Result<()> {
+        let server = CannedServer::always(Canned::status(500)).await;
+        let stream = open(&fast_reconnects(server.client())).await?;
+        stream.push(&summary("a#build"))?;
+        assert!(matches!(stream.close().await, Err(Error::ReqwestError(_))));
+        // The first request and three reconnects
+        assert_eq!(server.requests().len(), 4);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stream_reconnect_backoff() -> Result<()> {
+        let server = CannedServer::always(Canned::status(500)).await;
+        let client = server
+            .client()
+            .with_stream_reconnect_policy(StreamReconnectPolicy {
+                reconnects: 3,
+                delay: Duration::from_millis(50),
+                max_delay: Duration::from_millis(80),
+            });
+        let started = Instant::now();
+        let stream = open(&client).await?;
+        stream.push(&summary("a#build"))?;
+        assert!(stream.close().await.is_err());
+
+        // Waits 50ms, then 80ms twice, since the doubled delay is capped
+        assert!(started.elapsed() >= Duration::from_millis(210));
+        assert_eq!(server.requests().len(), 4);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failed_stream_forgets_keys() -> Result<()> {
+        let server = CannedServer::always(Canned::status(500)).await;
+        let client = fast_reconnects(server.client())
+            .with_duplicate_task_key_policy(DuplicateTaskKeyPolicy::Fail);
+        let stream = open(&client).await?;
+        stream.push(&summary("a#build"))?;
+        assert!(stream.close().await.is_err());
+
+        // The summary was never acknowledged, so it can be pushed again
+        let stream = open(&client).await?;
+        stream.push(&summary("a#build"))?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stream_outlives_timeout() -> Result<()> {
+        let server = CannedServer::always(Canned::ok()).await;
+        let client = APIClient::new(server.url(), 1, "2.0.0", false)?;
+
+        let stream = open(&client).await?;
+        stream.push(&summary("a#build"))?;
+        tokio::time::sleep(Duration::from_millis(1_200)).await;
+        stream.push(&summary("b#build"))?;
+        stream.close().await?;
+        assert_eq!(keys(&server), [["a#build", "b#build"]]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stream_shutdown() -> Result<()> {
+        let server = CannedServer::always(Canned::ok()).await;
+        let client = server.client();
+
+        // Nothing is sent for a stream without summaries
+        open(&client).await?.close().await?;
+        assert!(server.requests().is_empty());
+
+        let stream = open(&client).await?;
+        stream.push(&summary("a#build"))?;
+        // The body is ended by the shutdown, which waits for the server to
+        // acknowledge it
+        assert!(client.shutdown(Duration::from_secs(5)).await.is_empty());
+        assert_eq!(keys(&server), [["a#build"]]);
+        stream.close().await?;
+
+        Ok(())
+    }
+}
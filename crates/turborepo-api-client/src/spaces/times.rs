@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use super::{RunId, SpaceTaskSummary};
+use crate::{APIClient, Error, Warning};
+
+/// How a task summary that starts before its run is handled. This happens
+/// with clock adjustments or misordered instrumentation, and breaks the
+/// dashboard's timeline.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TaskTimePolicy {
+    /// Upload the summary as is
+    #[default]
+    Ignore,
+    /// Move the summary's start, and its end if needed, to the run's start
+    Clamp,
+    /// Upload the summary as is and report a `Warning::TaskStartedBeforeRun`
+    Warn,
+    /// Don't upload the summary and return `Error::TaskStartedBeforeRun`
+    Fail,
+}
+
+/// The start times of the open runs, so task summaries can be checked
+/// against them
+pub(crate) type RunStartTimes = HashMap<RunId, i64>;
+
+impl SpaceTaskSummary {
+    pub(super) fn clamp_to_run(&mut self, run_start: i64) {
+        self.start_time = self.start_time.max(run_start);
+        self.end_time = self.end_time.max(self.start_time);
+    }
+}
+
+impl APIClient {
+    /// Remembers a run's start time, if task times are checked
+    pub(crate) fn record_run_start(&self, run_id: &RunId, start_time: i64) {
+        if self.task_time_policy == TaskTimePolicy::Ignore {
+            return;
+        }
+        self.run_start_times
+            .lock()
+            .expect("run start times lock poisoned")
+            .insert(run_id.clone(), start_time);
+    }
+
+    /// Forgets a run's start time once it's finished
+    pub(crate) fn forget_run_start(&self, run_id: &RunId) {
+        self.run_start_times
+            .lock()
+            .expect("run start times lock poisoned")
+            .remove(run_id);
+    }
+
+    fn run_start(&self, run_id: &RunId) -> Option<i64> {
+        self.run_start_times
+            .lock()
+            .expect("run start times lock poisoned")
+            .get(run_id)
+            .copied()
+    }
+
+    /// Applies the task time policy to a summary of a run that started at
+    /// `run_start`. Returns whether the summary has to be clamped.
+    pub(crate) fn check_task_times(
+        &self,
+        run_start: i64,
+        task: &SpaceTaskSummary,
+    ) -> Result<bool, Error> {
+        if task.start_time >= run_start {
+            return Ok(false);
+        }
+
+        match self.task_time_policy {
+            TaskTimePolicy::Ignore => Ok(false),
+            TaskTimePolicy::Clamp => Ok(true),
+            TaskTimePolicy::Warn => {
+                self.warn(Warning::TaskStartedBeforeRun {
+                    key: task.key.clone(),
+                });
+                Ok(false)
+            }
+            TaskTimePolicy::Fail => Err(Error::TaskStartedBeforeRun {
+                key: task.key.clone(),
+                task_start: task.start_time,
+                run_start,
+            }),
+        }
+    }
+
+    /// Like `check_task_times`, but for a summary of an open run, which is
+    /// clamped in place
+    pub(crate) fn apply_task_time_policy(
+        &self,
+        run_id: &RunId,
+        task: &mut SpaceTaskSummary,
+    ) -> Result<(), Error> {
+        let Some(run_start) = self.run_start(run_id) else {
+            return Ok(());
+        };
+        if self.check_task_times(run_start, task)? {
+            task.clamp_to_run(run_start);
+        }
+
+        Ok(())
+    }
+
+    /// Like `apply_task_time_policy`, but only clones the summary if it's
+    /// clamped
+    pub(crate) fn task_with_checked_times(
+        &self,
+        run_id: &RunId,
+        task: &SpaceTaskSummary,
+    ) -> Result<Option<SpaceTaskSummary>, Error> {
+        let Some(run_start) = self.run_start(run_id) else {
+            return Ok(None);
+        };
+        if !self.check_task_times(run_start, task)? {
+            return Ok(None);
+        }
+
+        let mut task = task.clone();
+        task.clamp_to_run(run_start);
+        Ok(Some(task))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TaskTimePolicy;
+    use crate::{
+        spaces::{RunId, SpaceTaskSummary},
+        APIClient, Error, Warning, WarningSink,
+    };
+
+    #[test]
+    fn test_task_started_before_run() -> anyhow::Result<()> {
+        let run_id = RunId::from("run");
+        let early = || SpaceTaskSummary {
+            key: "web#build".to_string(),
+            start_time: 900,
+            end_time: 950,
+            ..SpaceTaskSummary::default()
+        };
+        let client = |policy| {
+            let warnings = WarningSink::new();
+            let client = APIClient::new("http://localhost", 0, "2.0.0", false)
+                .unwrap()
+                .with_task_time_policy(policy)
+                .with_warnings(warnings.clone());
+            client.record_run_start(&run_id, 1000);
+            (client, warnings)
+        };
+
+        let (ignoring, _) = client(TaskTimePolicy::Ignore);
+        let mut task = early();
+        ignoring.apply_task_time_policy(&run_id, &mut task)?;
+        assert_eq!((task.start_time, task.end_time), (900, 950));
+
+        let (clamping, _) = client(TaskTimePolicy::Clamp);
+        let mut task = early();
+        clamping.apply_task_time_policy(&run_id, &mut task)?;
+        assert_eq!((task.start_time, task.end_time), (1000, 1000));
+        let task = clamping
+            .task_with_checked_times(&run_id, &early())?
+            .unwrap();
+        assert_eq!(task.start_time, 1000);
+
+        let (warning, warnings) = client(TaskTimePolicy::Warn);
+        let mut task = early();
+        warning.apply_task_time_policy(&run_id, &mut task)?;
+        assert_eq!(task.start_time, 900);
+        assert_eq!(
+            warnings.take(),
+            vec![Warning::TaskStartedBeforeRun {
+                key: "web#build".to_string()
+            }]
+        );
+
+        let (failing, _) = client(TaskTimePolicy::Fail);
+        assert!(matches!(
+            failing.apply_task_time_policy(&run_id, &mut early()),
+            Err(Error::TaskStartedBeforeRun {
+                task_start: 900,
+                run_start: 1000,
+                ..
+            })
+        ));
+        // Tasks that start with the run are fine
+        let mut task = SpaceTaskSummary {
+            start_time: 1000,
+            ..early()
+        };
+        failing.apply_task_time_policy(&run_id, &mut task)?;
+
+        // Finished runs aren't checked anymore
+        failing.forget_run_start(&run_id);
+        failing.apply_task_time_policy(&run_id, &mut early())?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use tokio::time::{sleep_until, timeout_at, Instant};
+
+use super::{RunId, SpaceId};
+use crate::{APIAuth, APIClient, Error};
+
+/// How long `verify_run_visible` waits for a run by default
+pub const DEFAULT_RUN_VISIBILITY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often `verify_run_visible` reads the run while it isn't visible
+const VISIBILITY_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+impl APIClient {
+    /// Checks that a run can be read back from the dashboard, e.g. as a smoke
+    /// test after uploading it. Runs are eventually consistent, so a run that
+    /// was just created may not be visible right away: the run is read until
+    /// it is, for at most the client's run visibility timeout.
+    ///
+    /// Returns whether the run became visible in time. Failures to read it,
+    /// including a 404, count as not visible yet, except for other client
+    /// errors, e.g. a 403, which won't go away by waiting. While spaces are
+    /// disabled, runs are never visible.
+    pub async fn verify_run_visible(
+        &self,
+        space_id: &SpaceId,
+        run_id: &RunId,
+        api_auth: &APIAuth,
+    ) -> bool {
+        if self.spaces_disabled() || self.check_space(space_id).is_err() {
+            return false;
+        }
+
+        let url = format!("/v0/spaces/{}/runs/{}", space_id, run_id);
+        let deadline = Instant::now() + self.run_visibility_timeout;
+        loop {
+            match timeout_at(
+                deadline,
+                self.get_run_status(&url, api_auth, deadline.into_std()),
+            )
+            .await
+            {
+                Ok(Ok(_)) => return true,
+                Ok(Err(Error::ReqwestError(err))) if err.status().is_some_and(is_permanent) => {
+                    return false
+                }
+                _ => {}
+            }
+
+            let next_poll = Instant::now() + VISIBILITY_POLL_INTERVAL;
+            if next_poll >= deadline {
+                return false;
+            }
+            sleep_until(next_poll).await;
+        }
+    }
+}
+
+// Client errors other than the run not existing yet, or the server asking
+// us to slow down
+fn is_permanent(status: StatusCode) -> bool {
+    status.is_client_error()
+        && !matches!(
+            status,
+            StatusCode::NOT_FOUND | StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS
+        )
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        testing::{test_auth, Canned, CannedServer},
+        APIClient,
+    };
+
+    /// Starts a server that responds with a 404 to the first `missing` reads
+    /// of a run, and with the run afterwards
+    async fn start_server(missing: usize) -> CannedServer {
+        let reads = Arc::new(AtomicUsize::new(0));
+        CannedServer::start(move |_| {
+            if reads.fetch_add(1, Ordering::SeqCst) < missing {
+                Canned::status(404)
+            } else {
+                Canned::json(r#"{"status":"running"}"#)
+            }
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_verify_run_visible() -> anyhow::Result<()> {
+        let api_auth = test_auth();
+        let client = |server: CannedServer| -> APIClient {
+            server
+                .client()
+                .with_run_visibility_timeout(Duration::from_secs(2))
+        };
+
+        let server = start_server(2).await;
+        assert!(
+            client(server)
+                .verify_run_visible(&"space".into(), &"run".into(), &api_auth)
+                .await
+        );
+
+        let server = start_server(usize::MAX).await;
+        let never_visible = client(server).with_run_visibility_timeout(Duration::from_millis(600));
+        assert!(
+            !never_visible
+                .verify_run_visible(&"space".into(), &"run".into(), &api_auth)
+                .await
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_verify_run_visible_gives_up() -> anyhow::Result<()> {
+        let api_auth = test_auth();
+        let server = CannedServer::always(Canned::status(403)).await;
+        let client = server
+            .client()
+            .with_run_visibility_timeout(Duration::from_secs(10));
+
+        // A 403 won't go away, so the run isn't read again
+        let started_at = Instant::now();
+        assert!(
+            !client
+                .verify_run_visible(&"space".into(), &"run".into(), &api_auth)
+                .await
+        );
+        assert!(started_at.elapsed() < Duration::from_secs(1));
+        assert_eq!(server.requests().len(), 1);
+
+        let disabled = client.with_spaces_disabled(|| true);
+        assert!(
+            !disabled
+                .verify_run_visible(&"space".into(), &"run".into(), &api_auth)
+                .await
+        );
+        assert_eq!(server.requests().len(), 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+use reqwest::StatusCode;
+use turborepo_vercel_api::Team;
+
+use crate::{retry, APIAuth, APIClient, Client, Error};
+
+impl APIClient {
+    /// Fills in the team id of an `APIAuth` that only has a team slug, for
+    /// endpoints that need the id. Resolved ids are cached across all clones
+    /// of this client. Auth that already has a team id, or no slug, is
+    /// returned as is.
+    pub async fn resolve_team(&self, api_auth: &APIAuth) -> Result<APIAuth, Error> {
+        let Some(slug) = api_auth.team_slug.as_deref() else {
+            return Ok(api_auth.clone());
+        };
+        if !api_auth.team_id.is_empty() {
+            return Ok(api_auth.clone());
+        }
+
+        let cached = self
+            .team_ids
+            .lock()
+            .expect("team id cache lock poisoned")
+            .get(slug)
+            .cloned();
+        let team_id = match cached {
+            Some(team_id) => team_id,
+            None => {
+                let team_id = self.get_team_by_slug(&api_auth.token, slug).await?.id;
+                self.team_ids
+                    .lock()
+                    .expect("team id cache lock poisoned")
+                    .insert(slug.to_string(), team_id.clone());
+                team_id
+            }
+        };
+
+        Ok(APIAuth {
+            team_id,
+            ..api_auth.clone()
+        })
+    }
+
+    async fn get_team_by_slug(&self, token: &str, slug: &str) -> Result<Team, Error> {
+        let request_builder = self
+            .client
+            .get(self.make_url("/v2/team"))
+            .query(&[("slug", slug)])
+            .header("User-Agent", self.user_agent.clone())
+            .header("Content-Type", "application/json")
+            .header("Authorization", format!("Bearer {}", token));
+
+        let response = retry::make_retryable_request(request_builder, self).await?;
+        if matches!(
+            response.status(),
+            StatusCode::NOT_FOUND | StatusCode::FORBIDDEN
+        ) {
+            return Err(Error::TeamNotFound {
+                slug: slug.to_string(),
+            });
+        }
+
+        response
+            .error_for_status()?
+            .json::<Option<Team>>()
+            .await?
+            .ok_or_else(|| Error::TeamNotFound {
+                slug: slug.to_string(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+    use turborepo_vercel_api_mock::{start_test_server, EXPECTED_TEAM_ID, EXPECTED_TEAM_SLUG};
+
+    use crate::{testing::test_auth, APIAuth, APIClient, Error};
+
+    #[tokio::test]
+    async fn test_resolve_team() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+        let client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0", false)?;
+        let api_auth = |slug: &str| APIAuth {
+            team_id: String::new(),
+            team_slug: Some(slug.to_string()),
+            ..test_auth()
+        };
+
+        let resolved = client.resolve_team(&api_auth(EXPECTED_TEAM_SLUG)).await?;
+        assert_eq!(resolved.team_id, EXPECTED_TEAM_ID);
+        assert_eq!(resolved.team_slug.as_deref(), Some(EXPECTED_TEAM_SLUG));
+
+        assert!(matches!(
+            client.resolve_team(&api_auth("unknown")).await,
+            Err(Error::TeamNotFound { .. })
+        ));
+
+        handle.abort();
+        Ok(())
+    }
+}
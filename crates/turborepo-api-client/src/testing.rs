@@ -0,0 +1,296 @@
+//! Helpers shared by the client's tests
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use turborepo_vercel_api::CapabilitiesResponse;
+use turborepo_vercel_api_mock::{EXPECTED_TEAM_ID, EXPECTED_TOKEN};
+
+use crate::{APIAuth, APIClient, AuthMode};
+
+/// The auth the mock server expects, with the default auth mode
+pub(crate) fn test_auth() -> APIAuth {
+    APIAuth {
+        team_id: EXPECTED_TEAM_ID.to_string(),
+        token: EXPECTED_TOKEN.to_string(),
+        team_slug: None,
+        mode: AuthMode::default(),
+    }
+}
+
+/// Makes `client` behave as if the server advertised `capabilities`
+pub(crate) fn set_capabilities(client: &APIClient, capabilities: &[&str]) {
+    client
+        .capabilities
+        .set(Some(CapabilitiesResponse {
+            api_version: 1,
+            min_client_api_version: 1,
+            capabilities: capabilities.iter().map(|name| name.to_string()).collect(),
+        }))
+        .expect("capabilities were already fetched");
+}
+
+/// A request received by a `CannedServer`
+#[derive(Debug, Clone)]
+pub(crate) struct ReceivedRequest {
+    pub method: String,
+    /// The path and query
+    pub path: String,
+    /// Keyed by lowercased name
+    pub headers: HashMap<String, String>,
+    /// The body, after undoing any chunked transfer encoding
+    pub body: Vec<u8>,
+}
+
+impl ReceivedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_lowercase()).map(String::as_str)
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+/// The response a `CannedServer` sends for a request
+#[derive(Debug, Clone)]
+pub(crate) struct Canned {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    delay: Duration,
+    hang: bool,
+}
+
+impl Canned {
+    pub fn status(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+            delay: Duration::ZERO,
+            hang: false,
+        }
+    }
+
+    pub fn ok() -> Self {
+        Self::status(200)
+    }
+
+    /// A 200 with a JSON body
+    pub fn json(body: impl Into<String>) -> Self {
+        Self::ok()
+            .header("content-type", "application/json")
+            .body(body.into())
+    }
+
+    /// Never responds, but keeps the connection open
+    pub fn hang() -> Self {
+        Self {
+            hang: true,
+            ..Self::ok()
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Waits before responding
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut response = format!("HTTP/1.1 {} {}\r\n", self.status, reason(self.status));
+        for (name, value) in &self.headers {
+            response.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        response.push_str(&format!("content-length: {}\r\n\r\n", self.body.len()));
+        let mut bytes = response.into_bytes();
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    reqwest::StatusCode::from_u16(status)
+        .ok()
+        .and_then(|status| status.canonical_reason())
+        .unwrap_or("Unknown")
+}
+
+type Respond = dyn Fn(&ReceivedRequest) -> Canned + Send + Sync;
+
+/// A server on 127.0.0.1 that answers every request with the response its
+/// function picks for it, and records the requests it received
+#[derive(Clone)]
+pub(crate) struct CannedServer {
+    pub port: u16,
+    requests: Arc<Mutex<Vec<ReceivedRequest>>>,
+    connections: Arc<AtomicUsize>,
+}
+
+impl CannedServer {
+    /// Starts a server that responds to every request with `response`
+    pub async fn always(response: Canned) -> Self {
+        Self::start(move |_| response.clone()).await
+    }
+
+    pub async fn start(
+        respond: impl Fn(&ReceivedRequest) -> Canned + Send + Sync + 'static,
+    ) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = Self {
+            port: listener.local_addr().unwrap().port(),
+            requests: Arc::default(),
+            connections: Arc::default(),
+        };
+        let respond: Arc<Respond> = Arc::new(respond);
+        let (requests, connections) = (server.requests.clone(), server.connections.clone());
+        tokio::spawn(async move {
+            while let Ok((connection, _)) = listener.accept().await {
+                connections.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(serve(connection, respond.clone(), requests.clone()));
+            }
+        });
+        server
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://127.0.0.1:{}", self.port)
+    }
+
+    /// A client for the server, with the timeout most tests use
+    pub fn client(&self) -> APIClient {
+        APIClient::new(self.url(), 200, "2.0.0", false).unwrap()
+    }
+
+    pub fn requests(&self) -> Vec<ReceivedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// The number of connections that were accepted
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+}
+
+/// Starts a server that accepts connections but never responds
+pub(crate) async fn start_hanging_server() -> CannedServer {
+    CannedServer::always(Canned::hang()).await
+}
+
+async fn serve(
+    mut connection: TcpStream,
+    respond: Arc<Respond>,
+    requests: Arc<Mutex<Vec<ReceivedRequest>>>,
+) {
+    let mut buf = Vec::new();
+    while let Some(request) = read_request(&mut connection, &mut buf).await {
+        requests.lock().unwrap().push(request.clone());
+        let response = respond(&request);
+        if response.hang {
+            std::future::pending::<()>().await;
+        }
+        tokio::time::sleep(response.delay).await;
+        if connection.write_all(&response.to_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Reads the next request of the connection. `buf` holds what was read past
+/// the previous request.
+async fn read_request(connection: &mut TcpStream, buf: &mut Vec<u8>) -> Option<ReceivedRequest> {
+    let head_end = loop {
+        if let Some(end) = find(buf, b"\r\n\r\n") {
+            break end;
+        }
+        fill(connection, buf).await?;
+    };
+    let head = String::from_utf8_lossy(&buf[..head_end]).into_owned();
+    buf.drain(..head_end + 4);
+
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next()?.split(' ');
+    let method = request_line.next()?.to_string();
+    let path = request_line.next()?.to_string();
+    let headers: HashMap<_, _> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+        .collect();
+
+    let body = if headers.get("transfer-encoding").map(String::as_str) == Some("chunked") {
+        let mut body = Vec::new();
+        loop {
+            let line_end = loop {
+                if let Some(end) = find(buf, b"\r\n") {
+                    break end;
+                }
+                fill(connection, buf).await?;
+            };
+            let size = String::from_utf8_lossy(&buf[..line_end]).into_owned();
+            let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+            buf.drain(..line_end + 2);
+            while buf.len() < size + 2 {
+                fill(connection, buf).await?;
+            }
+            body.extend(buf.drain(..size));
+            buf.drain(..2);
+            if size == 0 {
+                break body;
+            }
+        }
+    } else {
+        let length = headers
+            .get("content-length")
+            .and_then(|length| length.parse().ok())
+            .unwrap_or(0);
+        while buf.len() < length {
+            fill(connection, buf).await?;
+        }
+        buf.drain(..length).collect()
+    };
+
+    Some(ReceivedRequest {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
+
+async fn fill(connection: &mut TcpStream, buf: &mut Vec<u8>) -> Option<()> {
+    let mut chunk = [0; 16 * 1024];
+    match connection.read(&mut chunk).await {
+        Ok(0) | Err(_) => None,
+        Ok(n) => {
+            buf.extend_from_slice(&chunk[..n]);
+            Some(())
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
@@ -0,0 +1,143 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{stream, StreamExt};
+use reqwest::{header::CONTENT_LENGTH, Body, Method, Request, StatusCode};
+use url::Url;
+
+/// A coarse breakdown of where the time for a single request attempt went.
+///
+/// reqwest doesn't expose DNS, connect or TLS timings, so these are measured
+/// around the call instead. The observer is notified as soon as the response
+/// headers arrive, before the caller reads the body, so there's no body read
+/// phase: the body is read through the `reqwest::Response`, which can only be
+/// instrumented by rebuilding it around a wrapped stream, losing its content
+/// length and connection info.
+#[derive(Debug, Clone)]
+pub struct RequestTiming {
+    pub method: Method,
+    pub url: Url,
+    /// Zero-indexed attempt number, incremented on every retry
+    pub attempt: u32,
+    /// The response status, or `None` if the request failed before a
+    /// response was received
+    pub status: Option<StatusCode>,
+    /// Time the attempt spent waiting inside the client before it was sent:
+    /// the backoff after a previous attempt, the client's 429 cooldown, its
+    /// rate limiter and its in-flight request cap. Waiting for a slot in the
+    /// spaces queue happens before the first attempt and isn't included.
+    pub queued: Duration,
+    /// Time from sending the request until the response headers arrived, or
+    /// the attempt failed. Includes DNS, connecting and TLS. This is `send`
+    /// plus `response_wait`.
+    pub time_to_headers: Duration,
+    /// Time from sending the request until its body was handed to the
+    /// connection, including DNS, connecting and TLS. `None` for requests
+    /// without a buffered body, e.g. GETs or uploads that report progress,
+    /// whose send time is part of `response_wait`.
+    pub send: Option<Duration>,
+    /// Time from the body being sent, or the request if it has none, until
+    /// the response headers arrived or the attempt failed. For requests with
+    /// a body, this is roughly the server's processing time plus a round trip.
+    pub response_wait: Duration,
+}
+
+/// Callback invoked with the timing of every request attempt made by the
+/// `APIClient`.
+pub type RequestObserver = Arc<dyn Fn(&RequestTiming) + Send + Sync>;
+
+/// When the body of a request attempt was last handed to the connection. It's
+/// shared by the redirects and failovers of the attempt, which resend it.
+#[derive(Clone, Default)]
+pub(crate) struct BodySent(Arc<Mutex<Option<Instant>>>);
+
+// Records when the body stream it's moved into is dropped, which the
+// connection does once it has written the last chunk
+struct SentGuard(Arc<Mutex<Option<Instant>>>);
+
+impl Drop for SentGuard {
+    fn drop(&mut self) {
+        *self.0.lock().expect("body sent lock poisoned") = Some(Instant::now());
+    }
+}
+
+impl BodySent {
+    /// Replaces the buffered body of `request`, which has to be sent right
+    /// after, with a stream that records when it's been sent. The request
+    /// can't be cloned afterwards. Copying the body is the only overhead, a
+    /// memcpy that's negligible next to sending it.
+    pub(crate) fn track(&self, request: &mut Request) {
+        let Some(body) = request.body().and_then(|body| body.as_bytes()) else {
+            return;
+        };
+        if body.is_empty() {
+            return;
+        }
+        let body = Bytes::copy_from_slice(body);
+
+        // A streamed body has no length, so it's set explicitly to avoid
+        // falling back to a chunked upload
+        request
+            .headers_mut()
+            .entry(CONTENT_LENGTH)
+            .or_insert_with(|| body.len().into());
+        let guard = SentGuard(self.0.clone());
+        let chunks = stream::iter([Ok::<_, std::io::Error>(body)]).map(move |chunk| {
+            let _ = &guard;
+            chunk
+        });
+        *request.body_mut() = Some(Body::wrap_stream(chunks));
+    }
+
+    pub(crate) fn get(&self) -> Option<Instant> {
+        *self.0.lock().expect("body sent lock poisoned")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use anyhow::Result;
+
+    use crate::{
+        retry,
+        testing::{Canned, CannedServer},
+    };
+
+    #[tokio::test]
+    async fn test_timing_phases() -> Result<()> {
+        let server = CannedServer::always(Canned::ok().delay(Duration::from_millis(100))).await;
+        let timings = Arc::new(Mutex::new(Vec::new()));
+        let recorded = timings.clone();
+        let client = server
+            .client()
+            .with_request_observer(move |timing| recorded.lock().unwrap().push(timing.clone()));
+
+        let upload = client.client.post(server.url()).body("payload");
+        retry::make_retryable_request(upload, &client).await?;
+        let download = client.client.get(server.url());
+        retry::make_retryable_request(download, &client).await?;
+
+        let timings = timings.lock().unwrap();
+        let send = timings[0].send.expect("the body was sent");
+        assert!(timings[0].response_wait >= Duration::from_millis(100));
+        assert_eq!(timings[0].time_to_headers, send + timings[0].response_wait);
+        // Without a body, all of the time is spent waiting for the response
+        assert_eq!(timings[1].send, None);
+        assert_eq!(timings[1].response_wait, timings[1].time_to_headers);
+
+        // The tracked body is sent as is, with its length
+        let requests = server.requests();
+        assert_eq!(requests[0].body, b"payload");
+        assert_eq!(requests[0].header("content-length"), Some("7"));
+
+        Ok(())
+    }
+}
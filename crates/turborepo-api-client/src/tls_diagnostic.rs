@@ -0,0 +1,116 @@
+use std::{io, sync::Arc};
+
+use tokio::net::TcpStream;
+use tokio_rustls::{
+    rustls::{self, ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName},
+    TlsConnector,
+};
+use url::Url;
+
+use crate::{APIClient, Error};
+
+/// The result of checking who signed the API host's certificate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TlsDiagnosis {
+    /// The certificate chains to a well-known public root
+    Trusted,
+    /// The certificate doesn't chain to a well-known public root. This usually
+    /// means a TLS-intercepting proxy re-signed the connection.
+    UnexpectedIssuer { reason: String },
+    /// The API is served over plain HTTP, so there is no certificate to check
+    NotTls,
+}
+
+impl APIClient {
+    /// Connects to the API host and checks whether its certificate chains to
+    /// one of the public roots the API's certificates are issued from. This is
+    /// purely informational: it helps explain odd connection behavior behind
+    /// corporate proxies, and doesn't change how requests are made.
+    ///
+    /// The check connects directly, so proxies configured through environment
+    /// variables aren't used, but transparent intercepting proxies are seen.
+    pub async fn diagnose_tls(&self) -> Result<TlsDiagnosis, Error> {
+        let url = Url::parse(&self.base_url)?;
+        if url.scheme() != "https" {
+            return Ok(TlsDiagnosis::NotTls);
+        }
+        let host = url.host_str().unwrap_or_default();
+        let port = url.port_or_known_default().unwrap_or(443);
+
+        let mut roots = RootCertStore::empty();
+        roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|anchor| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                anchor.subject,
+                anchor.spki,
+                anchor.name_constraints,
+            )
+        }));
+        let config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        let server_name = ServerName::try_from(host).map_err(|err| {
+            Error::ConnectionError(io::Error::new(io::ErrorKind::InvalidInput, err))
+        })?;
+        let stream = TcpStream::connect((host, port))
+            .await
+            .map_err(Error::ConnectionError)?;
+
+        match TlsConnector::from(Arc::new(config))
+            .connect(server_name, stream)
+            .await
+        {
+            Ok(_) => Ok(TlsDiagnosis::Trusted),
+            Err(err) => match err
+                .get_ref()
+                .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+            {
+                Some(rustls::Error::InvalidCertificateData(reason)) => {
+                    Ok(TlsDiagnosis::UnexpectedIssuer {
+                        reason: reason.clone(),
+                    })
+                }
+                _ => Err(Error::ConnectionError(err)),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::net::TcpListener;
+
+    use super::TlsDiagnosis;
+    use crate::{APIClient, Error};
+
+    #[tokio::test]
+    async fn test_diagnose_tls() -> anyhow::Result<()> {
+        let client = APIClient::new("http://localhost:1", 200, "2.0.0", false)?;
+        assert_eq!(client.diagnose_tls().await?, TlsDiagnosis::NotTls);
+
+        // Nothing is listening on the port
+        let port = TcpListener::bind("127.0.0.1:0").await?.local_addr()?.port();
+        let client = APIClient::new(format!("https://127.0.0.1:{}", port), 200, "2.0.0", false)?;
+        assert!(matches!(
+            client.diagnose_tls().await,
+            Err(Error::ConnectionError(_))
+        ));
+
+        // The server closes the connection instead of completing the handshake
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        tokio::spawn(async move {
+            while let Ok((connection, _)) = listener.accept().await {
+                drop(connection);
+            }
+        });
+        let client = APIClient::new(format!("https://127.0.0.1:{}", port), 200, "2.0.0", false)?;
+        assert!(matches!(
+            client.diagnose_tls().await,
+            Err(Error::ConnectionError(_))
+        ));
+
+        Ok(())
+    }
+}
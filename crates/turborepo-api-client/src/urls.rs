@@ -0,0 +1,99 @@
+/// Whether URLs built from the base URL end in a slash. Self-hosted caches
+/// disagree on this, and strict ones respond with a 404 to the other form.
+/// The case of the URL is never changed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TrailingSlashPolicy {
+    /// Keep the endpoint's path as is
+    #[default]
+    Preserve,
+    /// Remove trailing slashes, e.g. `/v8/artifacts/` becomes `/v8/artifacts`
+    Strip,
+    /// Add a trailing slash, e.g. `/v8/artifacts` becomes `/v8/artifacts/`
+    Require,
+}
+
+/// Joins a base URL, which may have a path and a trailing slash, and an
+/// endpoint, which may have a query. Duplicate slashes in the path are
+/// collapsed and `policy` is applied to its end.
+pub(crate) fn join_url(base_url: &str, endpoint: &str, policy: TrailingSlashPolicy) -> String {
+    let (origin, base_path) = match base_url.find("://") {
+        Some(scheme_end) => {
+            let path_start = base_url[scheme_end + 3..]
+                .find('/')
+                .map_or(base_url.len(), |i| scheme_end + 3 + i);
+            base_url.split_at(path_start)
+        }
+        None => ("", base_url),
+    };
+    let (endpoint_path, query) = match endpoint.find('?') {
+        Some(i) => endpoint.split_at(i),
+        None => (endpoint, ""),
+    };
+
+    let mut path = String::with_capacity(base_path.len() + endpoint_path.len() + 1);
+    for segment in base_path
+        .split('/')
+        .chain(endpoint_path.split('/'))
+        .filter(|segment| !segment.is_empty())
+    {
+        path.push('/');
+        path.push_str(segment);
+    }
+    match policy {
+        TrailingSlashPolicy::Preserve if endpoint_path.ends_with('/') => path.push('/'),
+        TrailingSlashPolicy::Preserve if endpoint_path.is_empty() && base_path.ends_with('/') => {
+            path.push('/')
+        }
+        TrailingSlashPolicy::Require => path.push('/'),
+        _ => {}
+    }
+    if path.is_empty() {
+        path.push('/');
+    }
+
+    format!("{}{}{}", origin, path, query)
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::{join_url, TrailingSlashPolicy};
+
+    #[test_case("https://cache.example.com", "/v8/artifacts/abc", "https://cache.example.com/v8/artifacts/abc" ; "plain")]
+    #[test_case("https://cache.example.com/", "/v8/artifacts/abc", "https://cache.example.com/v8/artifacts/abc" ; "base with trailing slash")]
+    #[test_case("https://cache.example.com", "v8/artifacts/abc", "https://cache.example.com/v8/artifacts/abc" ; "endpoint without leading slash")]
+    #[test_case("https://cache.example.com/turbo", "/v8/artifacts/abc", "https://cache.example.com/turbo/v8/artifacts/abc" ; "base with path")]
+    #[test_case("https://cache.example.com/turbo//", "//v8//artifacts/abc", "https://cache.example.com/turbo/v8/artifacts/abc" ; "duplicate slashes")]
+    #[test_case("https://cache.example.com", "/v2/teams?limit=100", "https://cache.example.com/v2/teams?limit=100" ; "query")]
+    #[test_case("https://cache.example.com/", "/v0/spaces/", "https://cache.example.com/v0/spaces/" ; "endpoint with trailing slash")]
+    #[test_case("https://Cache.example.com/Turbo", "/v8/artifacts/ABC", "https://Cache.example.com/Turbo/v8/artifacts/ABC" ; "case is kept")]
+    #[test_case("https://cache.example.com/", "", "https://cache.example.com/" ; "no endpoint")]
+    #[test_case("http://localhost:3000", "/v8/artifacts/abc", "http://localhost:3000/v8/artifacts/abc" ; "port")]
+    fn test_join_url_preserve(base_url: &str, endpoint: &str, expected: &str) {
+        assert_eq!(
+            join_url(base_url, endpoint, TrailingSlashPolicy::Preserve),
+            expected
+        );
+    }
+
+    #[test_case("https://cache.example.com/", "/v8/artifacts/", "https://cache.example.com/v8/artifacts" ; "endpoint with trailing slash")]
+    #[test_case("https://cache.example.com", "/v2/teams/?limit=100", "https://cache.example.com/v2/teams?limit=100" ; "query")]
+    #[test_case("https://cache.example.com/", "", "https://cache.example.com/" ; "root")]
+    fn test_join_url_strip(base_url: &str, endpoint: &str, expected: &str) {
+        assert_eq!(
+            join_url(base_url, endpoint, TrailingSlashPolicy::Strip),
+            expected
+        );
+    }
+
+    #[test_case("https://cache.example.com", "/v8/artifacts", "https://cache.example.com/v8/artifacts/" ; "endpoint without trailing slash")]
+    #[test_case("https://cache.example.com/turbo/", "/v8/artifacts/", "https://cache.example.com/turbo/v8/artifacts/" ; "endpoint with trailing slash")]
+    #[test_case("https://cache.example.com", "/v2/teams?limit=100", "https://cache.example.com/v2/teams/?limit=100" ; "query")]
+    fn test_join_url_require(base_url: &str, endpoint: &str, expected: &str) {
+        assert_eq!(
+            join_url(base_url, endpoint, TrailingSlashPolicy::Require),
+            expected
+        );
+    }
+}
@@ -0,0 +1,74 @@
+use reqwest::{Method, StatusCode};
+use turborepo_vercel_api::UsageResponse;
+
+use crate::{retry, APIAuth, APIClient, Error};
+
+/// Remote cache and spaces usage for a team over the current billing period
+#[derive(Debug, Clone)]
+pub enum TeamUsage {
+    Available(UsageResponse),
+    /// The team's plan doesn't expose usage information
+    Unavailable,
+}
+
+impl APIClient {
+    pub async fn get_team_usage(&self, api_auth: &APIAuth) -> Result<TeamUsage, Error> {
+        let request_builder = self
+            .create_request_builder("/v8/artifacts/usage", api_auth, Method::GET, None)
+            .await?;
+
+        let response = retry::make_retryable_request(request_builder, self).await?;
+
+        // Plans without metered usage don't have the endpoint. A 403 is left
+        // to fail, since it's more likely a token without access to the team
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(TeamUsage::Unavailable);
+        }
+
+        Ok(TeamUsage::Available(
+            response.error_for_status()?.json().await?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use reqwest::StatusCode;
+    use turborepo_vercel_api_mock::{
+        start_test_server, EXPECTED_ARTIFACT_BYTES_USED, EXPECTED_RUNS_THIS_PERIOD,
+    };
+
+    use super::TeamUsage;
+    use crate::{
+        testing::{test_auth, Canned, CannedServer},
+        APIClient, Error,
+    };
+
+    #[tokio::test]
+    async fn test_get_team_usage() -> anyhow::Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+        let client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0", false)?;
+        let TeamUsage::Available(usage) = client.get_team_usage(&test_auth()).await? else {
+            panic!("expected usage to be available");
+        };
+        assert_eq!(usage.artifact_bytes_used, EXPECTED_ARTIFACT_BYTES_USED);
+        assert_eq!(usage.runs_this_period, EXPECTED_RUNS_THIS_PERIOD);
+        assert_eq!(usage.runs_limit, Some(100));
+        handle.abort();
+
+        let server = CannedServer::always(Canned::status(404)).await;
+        assert!(matches!(
+            server.client().get_team_usage(&test_auth()).await?,
+            TeamUsage::Unavailable
+        ));
+
+        let server = CannedServer::always(Canned::status(403)).await;
+        assert!(matches!(
+            server.client().get_team_usage(&test_auth()).await,
+            Err(Error::ReqwestError(err)) if err.status() == Some(StatusCode::FORBIDDEN)
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+/// A soft failure that didn't stop a request from succeeding, but that users
+/// may want to know about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Warning {
+    /// The finish pre-check couldn't read the run's state, so the run was
+    /// finished without knowing whether the server already finished it
+    FinishPrecheckFailed { run_id: String },
+    /// A run was finished with a failing exit code because its session was
+    /// dropped before the run was finished
+    AbandonedRunFinished { run_id: String },
+    /// The task summary stream's connection dropped and was reopened
+    SummaryStreamReconnected,
+    /// A task summary was uploaded to a run that already had a summary with
+    /// the same key, see `DuplicateTaskKeyPolicy`
+    DuplicateTaskKey { key: String },
+    /// A run couldn't be finished and was stored to be finished later, see
+    /// `APIClient::with_deferred_finishes`
+    FinishDeferred { run_id: String },
+    /// A task summary started before its run, see `TaskTimePolicy`
+    TaskStartedBeforeRun { key: String },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::FinishPrecheckFailed { run_id } => write!(
+                f,
+                "could not check whether run {} was already finished",
+                run_id
+            ),
+            Warning::AbandonedRunFinished { run_id } => {
+                write!(f, "run {} was finished after being abandoned", run_id)
+            }
+            Warning::SummaryStreamReconnected => {
+                write!(f, "the task summary stream was reconnected")
+            }
+            Warning::DuplicateTaskKey { key } => write!(
+                f,
+                "task {} was uploaded more than once, only one summary will be kept",
+                key
+            ),
+            Warning::FinishDeferred { run_id } => write!(
+                f,
+                "run {} could not be finished, it will be finished on the next run",
+                run_id
+            ),
+            Warning::TaskStartedBeforeRun { key } => write!(
+                f,
+                "task {} started before its run, the dashboard's timeline may be wrong",
+                key
+            ),
+        }
+    }
+}
+
+/// Collects warnings from an `APIClient` and its clones, so they can be shown
+/// together, e.g. at the end of a run.
+#[derive(Debug, Clone, Default)]
+pub struct WarningSink {
+    warnings: Arc<Mutex<Vec<Warning>>>,
+}
+
+impl WarningSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes and returns the warnings collected so far
+    pub fn take(&self) -> Vec<Warning> {
+        std::mem::take(&mut *self.warnings.lock().expect("warnings lock poisoned"))
+    }
+
+    pub(crate) fn push(&self, warning: Warning) {
+        self.warnings
+            .lock()
+            .expect("warnings lock poisoned")
+            .push(warning);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use turbopath::AbsoluteSystemPathBuf;
+
+    use super::{Warning, WarningSink};
+    use crate::{
+        spaces::{FinishOutcome, FinishRetryPolicy},
+        testing::{test_auth, Canned, CannedServer},
+    };
+
+    #[tokio::test]
+    async fn test_warnings_reach_sink() -> Result<()> {
+        // The run's state can't be read, and it can't be finished
+        let server = CannedServer::start(|request| match request.method.as_str() {
+            "GET" => Canned::status(500),
+            _ => Canned::status(502),
+        })
+        .await;
+        let dir = tempfile::tempdir()?;
+        let warnings = WarningSink::new();
+        let client = server
+            .client()
+            .with_warnings(warnings.clone())
+            .with_finish_precheck(true)
+            .with_finish_retry_policy(FinishRetryPolicy {
+                retries: 0,
+                delay: Duration::ZERO,
+            })
+            .with_deferred_finishes(AbsoluteSystemPathBuf::try_from(dir.path())?);
+
+        // Warnings from clones go to the same sink
+        let outcome = client
+            .clone()
+            .finish_space_run(&"space".into(), &"run".into(), &test_auth(), 0, 0)
+            .await?;
+        assert_eq!(outcome, FinishOutcome::Deferred);
+        assert_eq!(
+            warnings.take(),
+            [
+                Warning::FinishPrecheckFailed {
+                    run_id: "run".to_string()
+                },
+                Warning::FinishDeferred {
+                    run_id: "run".to_string()
+                },
+            ]
+        );
+        assert!(warnings.take().is_empty());
+
+        Ok(())
+    }
+}
@@ -223,6 +223,7 @@ mod tests {
             _artifact_body: &[u8],
             _duration: u64,
             _tag: Option<&str>,
+            _content_type: Option<&str>,
             _token: &str,
         ) -> turborepo_api_client::Result<()> {
             unimplemented!("put_artifact")
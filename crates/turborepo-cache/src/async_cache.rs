@@ -150,7 +150,7 @@ mod tests {
     use futures::future::try_join_all;
     use tempfile::tempdir;
     use turbopath::AbsoluteSystemPathBuf;
-    use turborepo_api_client::{APIAuth, APIClient};
+    use turborepo_api_client::{APIAuth, APIClient, AuthMode};
     use turborepo_vercel_api_mock::start_test_server;
 
     use crate::{
@@ -197,6 +197,7 @@ mod tests {
             team_id: "my-team-id".to_string(),
             token: "my-token".to_string(),
             team_slug: None,
+            mode: AuthMode::default(),
         });
         let mut async_cache = AsyncCache::new(&opts, &repo_root_path, api_client, api_auth)?;
 
@@ -272,6 +273,7 @@ mod tests {
             team_id: "my-team-id".to_string(),
             token: "my-token".to_string(),
             team_slug: None,
+            mode: AuthMode::default(),
         });
         let mut async_cache = AsyncCache::new(&opts, &repo_root_path, api_client, api_auth)?;
 
@@ -353,6 +355,7 @@ mod tests {
             team_id: "my-team-id".to_string(),
             token: "my-token".to_string(),
             team_slug: None,
+            mode: AuthMode::default(),
         });
         let mut async_cache = AsyncCache::new(&opts, &repo_root_path, api_client, api_auth)?;
 
@@ -1,7 +1,7 @@
 use std::{backtrace::Backtrace, io::Write};
 
 use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf, AnchoredSystemPathBuf};
-use turborepo_api_client::{APIAuth, APIClient, Client, Response};
+use turborepo_api_client::{read_verified_artifact, APIAuth, APIClient, Client, Response};
 
 use crate::{
     cache_archive::{CacheReader, CacheWriter},
@@ -9,6 +9,9 @@ use crate::{
     CacheError, CacheOpts, CacheResponse, CacheSource,
 };
 
+// Artifacts are always written as zstd compressed tarballs
+const ARTIFACT_CONTENT_TYPE: &str = "application/x-tar+zstd";
+
 pub struct HTTPCache {
     client: APIClient,
     signer_verifier: Option<ArtifactSignatureAuthenticator>,
@@ -61,12 +64,13 @@ impl HTTPCache {
             .transpose()?;
 
         self.client
-            .put_artifact(
+            .put_artifact_resumable(
                 hash,
                 &artifact_body,
                 duration,
                 tag.as_deref(),
-                &self.api_auth.token,
+                Some(ARTIFACT_CONTENT_TYPE),
+                &self.api_auth,
             )
             .await?;
 
@@ -147,12 +151,7 @@ impl HTTPCache {
                 .map_err(|_| CacheError::InvalidTag(Backtrace::capture()))?
                 .to_string();
 
-            let body = response.bytes().await.map_err(|e| {
-                CacheError::ApiClientError(
-                    Box::new(turborepo_api_client::Error::ReqwestError(e)),
-                    Backtrace::capture(),
-                )
-            })?;
+            let body = read_verified_artifact(response).await?;
             let is_valid = signer_verifier.validate(hash.as_bytes(), &body, &expected_tag)?;
 
             if !is_valid {
@@ -161,12 +160,7 @@ impl HTTPCache {
 
             body
         } else {
-            response.bytes().await.map_err(|e| {
-                CacheError::ApiClientError(
-                    Box::new(turborepo_api_client::Error::ReqwestError(e)),
-                    Backtrace::capture(),
-                )
-            })?
+            read_verified_artifact(response).await?
         };
 
         let files = Self::restore_tar(&self.repo_root, &body)?;
@@ -195,7 +189,7 @@ mod test {
     use futures::future::try_join_all;
     use tempfile::tempdir;
     use turbopath::AbsoluteSystemPathBuf;
-    use turborepo_api_client::APIClient;
+    use turborepo_api_client::{APIClient, AuthMode};
     use turborepo_vercel_api_mock::start_test_server;
 
     use crate::{
@@ -237,6 +231,7 @@ mod test {
             team_id: "my-team".to_string(),
             token: "my-token".to_string(),
             team_slug: None,
+            mode: AuthMode::default(),
         };
 
         let cache = HTTPCache::new(api_client, &opts, repo_root_path.to_owned(), api_auth);
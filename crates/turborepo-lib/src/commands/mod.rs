@@ -4,7 +4,7 @@ use anyhow::anyhow;
 use dirs_next::config_dir;
 use sha2::{Digest, Sha256};
 use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf};
-use turborepo_api_client::{APIAuth, APIClient};
+use turborepo_api_client::{APIAuth, APIClient, AuthMode};
 use turborepo_ui::UI;
 
 use crate::{
@@ -105,6 +105,7 @@ impl CommandBase {
             team_id: team_id.to_string(),
             token: token.to_string(),
             team_slug: team_slug.map(|s| s.to_string()),
+            mode: AuthMode::default(),
         }))
     }
 
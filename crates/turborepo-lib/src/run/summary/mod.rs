@@ -22,7 +22,10 @@ use tabwriter::TabWriter;
 use thiserror::Error;
 use tracing::log::warn;
 use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf, AnchoredSystemPath};
-use turborepo_api_client::{spaces::CreateSpaceRunPayload, APIAuth, APIClient};
+use turborepo_api_client::{
+    spaces::{CreateSpaceRunPayload, SpacesFailurePolicy},
+    APIAuth, APIClient,
+};
 use turborepo_env::EnvironmentVariableMap;
 use turborepo_ui::{color, cprintln, cwriteln, BOLD, BOLD_CYAN, GREY, UI};
 
@@ -62,6 +65,10 @@ pub enum Error {
     SpacesClientClose(#[from] tokio::task::JoinError),
     #[error("failed to contact spaces client")]
     SpacesClientSend(#[from] tokio::sync::mpsc::error::SendError<SpaceRequest>),
+    #[error("failed to record the run in its space")]
+    SpacesRunNotRecorded,
+    #[error("failed to flush {0} before exiting")]
+    SpacesUnflushed(turborepo_api_client::spaces::Unflushed),
     #[error("failed to parse environment variables")]
     EnvironmentVars(regex::Error),
     #[error("failed to construct task summary: {0}")]
@@ -168,7 +175,7 @@ impl RunTracker {
         if let Some(spaces_client) =
             SpacesClient::new(spaces_id.clone(), spaces_api_client, api_auth)
         {
-            let payload = CreateSpaceRunPayload::new(
+            let mut payload = CreateSpaceRunPayload::new(
                 started_at,
                 synthesized_command,
                 package_inference_root,
@@ -177,6 +184,7 @@ impl RunTracker {
                 version.to_string(),
                 user,
             );
+            payload.client.platform = Some(std::env::consts::OS.to_string());
             run_tracker.spaces_client_handle = spaces_client.start(payload).ok();
         }
 
@@ -394,16 +402,23 @@ impl<'a> RunSummary<'a> {
 
         spaces_client_handle.finish_run(exit_code, ended_at).await?;
 
+        let failure_policy = spaces_client_handle.failure_policy();
         let result = spaces_client_handle.close().await;
 
         spinner.finish_and_clear();
 
-        Self::print_errors(&result.errors);
+        if failure_policy != SpacesFailurePolicy::Ignore {
+            Self::print_errors(&result.errors);
+        }
 
         if let Some(run) = result.run {
             println!("Run: {}\n", run.url);
         }
 
+        if result.run_failed && failure_policy == SpacesFailurePolicy::Fail {
+            return Err(Error::SpacesRunNotRecorded);
+        }
+
         Ok(())
     }
 
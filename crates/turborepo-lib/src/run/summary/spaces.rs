@@ -9,7 +9,10 @@ use serde::Serialize;
 use tokio::{sync::mpsc::Sender, task::JoinHandle};
 use tracing::debug;
 use turborepo_api_client::{
-    spaces::{CreateSpaceRunPayload, SpaceTaskSummary},
+    spaces::{
+        CreateSpaceRunPayload, SpaceId, SpaceSession, SpaceTaskSummary, SpacesFailurePolicy,
+        Unflushed,
+    },
     APIAuth, APIClient,
 };
 use turborepo_vercel_api::SpaceRun;
@@ -17,7 +20,7 @@ use turborepo_vercel_api::SpaceRun;
 use crate::run::summary::Error;
 
 pub struct SpacesClient {
-    space_id: String,
+    space_id: SpaceId,
     api_client: APIClient,
     api_auth: APIAuth,
     request_timeout: Duration,
@@ -29,6 +32,9 @@ pub struct SpacesClient {
 #[derive(Debug)]
 pub struct SpacesClientResult {
     pub errors: Vec<Error>,
+    // Whether creating or finishing the run failed, i.e. the run wasn't
+    // recorded in the space
+    pub run_failed: bool,
     // Can be None because SpacesClient could error on join
     pub run: Option<SpaceRun>,
 }
@@ -38,6 +44,9 @@ pub struct SpacesClientResult {
 pub struct SpacesClientHandle {
     handle: JoinHandle<Result<SpacesClientResult, Error>>,
     tx: Sender<SpaceRequest>,
+    failure_policy: SpacesFailurePolicy,
+    api_client: APIClient,
+    shutdown_deadline: Duration,
 }
 
 impl Debug for SpacesClientHandle {
@@ -67,23 +76,39 @@ impl SpacesClientHandle {
             })
             .await?)
     }
+    /// What the run should do if it couldn't be recorded in the space
+    pub fn failure_policy(&self) -> SpacesFailurePolicy {
+        self.failure_policy
+    }
+
     pub async fn close(self) -> SpacesClientResult {
         // Drop the transmitter to signal to the worker thread that
         // we're done sending requests
         drop(self.tx);
 
         // Wait for all of the requests to finish being processed
-        match self.handle.await {
+        let mut result = match self.handle.await {
             Ok(Ok(spaces_client_result)) => spaces_client_result,
             Ok(Err(err)) => SpacesClientResult {
                 errors: vec![err],
+                run_failed: true,
                 run: None,
             },
             Err(e) => SpacesClientResult {
                 errors: vec![e.into()],
+                run_failed: true,
                 run: None,
             },
+        };
+
+        // Flush whatever the worker left behind, e.g. the finish sent by a
+        // session the worker dropped
+        for unflushed in self.api_client.shutdown(self.shutdown_deadline).await {
+            result.run_failed |= matches!(unflushed, Unflushed::Run { .. });
+            result.errors.push(Error::SpacesUnflushed(unflushed));
         }
+
+        result
     }
 }
 
@@ -101,7 +126,7 @@ impl SpacesClient {
         api_auth: Option<APIAuth>,
     ) -> Option<Self> {
         // If space_id is empty, we don't build a client
-        let space_id = space_id?;
+        let space_id = SpaceId::from(space_id?);
         let Some(api_auth) = api_auth else {
             eprintln!(
                 "Error: experimentalSpaceId is enabled, but repo is not linked to API. Run `turbo \
@@ -124,6 +149,9 @@ impl SpacesClient {
         create_run_payload: CreateSpaceRunPayload,
     ) -> Result<SpacesClientHandle, Error> {
         let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let failure_policy = self.api_client.spaces_failure_policy();
+        let api_client = self.api_client.clone();
+        let shutdown_deadline = self.request_timeout;
         let handle = tokio::spawn(async move {
             let run = match self.create_run(create_run_payload).await {
                 Ok(run) => run,
@@ -132,22 +160,37 @@ impl SpacesClient {
                     self.errors.push(e);
                     return Ok(SpacesClientResult {
                         errors: self.errors,
+                        run_failed: true,
                         run: None,
                     });
                 }
             };
 
-            debug!("created run: {:?}", run);
+            debug!("created run: {:?}", run.run());
 
+            // If the worker exits without receiving a FinishedRun request,
+            // dropping the session finishes the run
+            let space_run = run.run().clone();
+            let mut run = Some(run);
+            let mut run_failed = false;
             while let Some(req) = rx.recv().await {
                 let resp = match req {
                     SpaceRequest::FinishedRun {
                         end_time,
                         exit_code,
-                    } => self.finish_run_handler(&run, end_time, exit_code).await,
-                    SpaceRequest::FinishedTask { summary } => {
-                        self.finish_task_handler(*summary, &run).await
-                    }
+                    } => match run.take() {
+                        Some(run) => {
+                            let resp = self.finish_run_handler(run, end_time, exit_code).await;
+                            run_failed |= resp.is_err();
+                            resp
+                        }
+                        None => Ok(()),
+                    },
+                    // Tasks can't be added once the run is finished
+                    SpaceRequest::FinishedTask { summary } => match &run {
+                        Some(run) => self.finish_task_handler(*summary, run).await,
+                        None => Ok(()),
+                    },
                 };
 
                 if let Err(e) = resp {
@@ -157,14 +200,21 @@ impl SpacesClient {
 
             Ok(SpacesClientResult {
                 errors: self.errors,
-                run: Some(run),
+                run_failed,
+                run: Some(space_run),
             })
         });
 
-        Ok(SpacesClientHandle { handle, tx })
+        Ok(SpacesClientHandle {
+            handle,
+            tx,
+            failure_policy,
+            api_client,
+            shutdown_deadline,
+        })
     }
 
-    async fn create_run(&self, payload: CreateSpaceRunPayload) -> Result<SpaceRun, Error> {
+    async fn create_run(&self, payload: CreateSpaceRunPayload) -> Result<SpaceSession, Error> {
         Ok(tokio::time::timeout(
             self.request_timeout,
             self.api_client
@@ -176,38 +226,20 @@ impl SpacesClient {
     async fn finish_task_handler(
         &self,
         task_summary: SpaceTaskSummary,
-        run: &SpaceRun,
+        run: &SpaceSession,
     ) -> Result<(), Error> {
-        Ok(tokio::time::timeout(
-            self.request_timeout,
-            self.api_client.create_task_summary(
-                &self.space_id,
-                &run.id,
-                &self.api_auth,
-                task_summary,
-            ),
-        )
-        .await??)
+        Ok(tokio::time::timeout(self.request_timeout, run.upload_task(task_summary)).await??)
     }
 
     // Called by the worker thread upon receiving a SpaceRequest::FinishedRun
     async fn finish_run_handler(
         &self,
-        run: &SpaceRun,
+        run: SpaceSession,
         end_time: i64,
         exit_code: i32,
     ) -> Result<(), Error> {
-        Ok(tokio::time::timeout(
-            self.request_timeout,
-            self.api_client.finish_space_run(
-                &self.space_id,
-                &run.id,
-                &self.api_auth,
-                end_time,
-                exit_code,
-            ),
-        )
-        .await??)
+        tokio::time::timeout(self.request_timeout, run.finish(end_time, exit_code)).await??;
+        Ok(())
     }
 }
 
@@ -218,7 +250,7 @@ mod tests {
     use test_case::test_case;
     use turborepo_api_client::{
         spaces::{CreateSpaceRunPayload, SpaceTaskSummary},
-        APIAuth, APIClient,
+        APIAuth, APIClient, AuthMode,
     };
     use turborepo_vercel_api_mock::{
         start_test_server, EXPECTED_SPACE_ID, EXPECTED_SPACE_RUN_ID, EXPECTED_TEAM_ID,
@@ -241,6 +273,7 @@ mod tests {
             token: EXPECTED_TOKEN.to_string(),
             team_id: EXPECTED_TEAM_ID.to_string(),
             team_slug: Some(EXPECTED_TEAM_SLUG.to_string()),
+            mode: AuthMode::default(),
         });
 
         let spaces_client =
@@ -4,7 +4,7 @@ use std::{collections::HashMap, fs::OpenOptions, io::Write, net::SocketAddr, syn
 
 use anyhow::Result;
 use axum::{
-    extract::{BodyStream, Path},
+    extract::{BodyStream, Path, Query},
     http::{HeaderMap, HeaderValue, StatusCode},
     routing::{get, head, options, patch, post, put},
     Json, Router,
@@ -12,8 +12,8 @@ use axum::{
 use futures_util::StreamExt;
 use tokio::sync::Mutex;
 use turborepo_vercel_api::{
-    CachingStatus, CachingStatusResponse, Membership, Role, Space, SpaceRun, SpacesResponse, Team,
-    TeamsResponse, User, UserResponse, VerificationResponse,
+    CachingStatus, CachingStatusResponse, Membership, Role, Space, SpaceRun, SpaceStatsResponse,
+    SpacesResponse, Team, TeamsResponse, UsageResponse, User, UserResponse, VerificationResponse,
 };
 
 pub const EXPECTED_TOKEN: &str = "expected_token";
@@ -32,6 +32,11 @@ pub const EXPECTED_SPACE_NAME: &str = "expected_space_name";
 pub const EXPECTED_SPACE_RUN_ID: &str = "expected_space_run_id";
 pub const EXPECTED_SPACE_RUN_URL: &str = "https://example.com";
 
+pub const EXPECTED_SPACE_RUN_COUNT: u64 = 7;
+
+pub const EXPECTED_ARTIFACT_BYTES_USED: u64 = 1024;
+pub const EXPECTED_RUNS_THIS_PERIOD: u64 = 12;
+
 pub const EXPECTED_SSO_TEAM_ID: &str = "expected_sso_team_id";
 pub const EXPECTED_SSO_TEAM_SLUG: &str = "expected_sso_team_slug";
 
@@ -72,6 +77,26 @@ pub async fn start_test_server(port: u16) -> Result<()> {
                 })
             }),
         )
+        .route(
+            "/v2/team",
+            get(|Query(query): Query<HashMap<String, String>>| async move {
+                if query.get("slug").map(String::as_str) != Some(EXPECTED_TEAM_SLUG) {
+                    return (StatusCode::NOT_FOUND, Json(None));
+                }
+
+                (
+                    StatusCode::OK,
+                    Json(Some(Team {
+                        id: EXPECTED_TEAM_ID.to_string(),
+                        slug: EXPECTED_TEAM_SLUG.to_string(),
+                        name: EXPECTED_TEAM_NAME.to_string(),
+                        created_at: EXPECTED_TEAM_CREATED_AT,
+                        created: Default::default(),
+                        membership: Membership::new(Role::Owner),
+                    })),
+                )
+            }),
+        )
         .route(
             "/v0/spaces",
             get(|| async move {
@@ -99,6 +124,24 @@ pub async fn start_test_server(port: u16) -> Result<()> {
                 )
             }),
         )
+        .route(
+            "/v0/spaces/:space_id/stats",
+            get(|Path(space_id): Path<String>| async move {
+                if space_id != EXPECTED_SPACE_ID {
+                    return (StatusCode::NOT_FOUND, Json(None));
+                }
+
+                (
+                    StatusCode::OK,
+                    Json(Some(SpaceStatsResponse {
+                        run_count: EXPECTED_SPACE_RUN_COUNT,
+                        average_duration: 1_000,
+                        cache_hit_rate: 0.5,
+                        time_saved: 2_000,
+                    })),
+                )
+            }),
+        )
         .route(
             "/v0/spaces/:space_id/runs/:run_id",
             patch(
@@ -140,6 +183,17 @@ pub async fn start_test_server(port: u16) -> Result<()> {
                 })
             }),
         )
+        .route(
+            "/v8/artifacts/usage",
+            get(|| async move {
+                Json(UsageResponse {
+                    artifact_bytes_used: EXPECTED_ARTIFACT_BYTES_USED,
+                    artifact_bytes_limit: None,
+                    runs_this_period: EXPECTED_RUNS_THIS_PERIOD,
+                    runs_limit: Some(100),
+                })
+            }),
+        )
         .route(
             "/v8/artifacts/:hash",
             put(
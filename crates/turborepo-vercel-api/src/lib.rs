@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use serde::{Deserialize, Serialize};
 use url::Url;
 
@@ -98,6 +100,26 @@ pub struct SpaceRun {
     pub url: String,
 }
 
+/// A run as listed by the spaces API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpaceRunListing {
+    pub id: String,
+    pub url: String,
+    /// Millisecond timestamp
+    pub created_at: i64,
+    /// Millisecond timestamp
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpaceRunsResponse {
+    pub runs: Vec<SpaceRunListing>,
+    /// The cursor of the next page of runs, if there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: String,
@@ -113,10 +135,41 @@ pub struct UserResponse {
     pub user: User,
 }
 
-#[derive(Debug)]
+/// The response to a CORS preflight request
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PreflightResponse {
+    /// Where the actual request should be sent, from the `Location` header.
+    /// The request URL if the server didn't rewrite it.
     pub location: Url,
-    pub allow_authorization_header: bool,
+    /// The lowercased headers from `Access-Control-Allow-Headers`, which may
+    /// include the `*` wildcard
+    pub allowed_headers: Vec<String>,
+    /// The uppercased methods from `Access-Control-Allow-Methods`
+    pub allowed_methods: Vec<String>,
+    /// How long the response may be cached, from `Access-Control-Max-Age`
+    pub max_age: Option<Duration>,
+}
+
+impl PreflightResponse {
+    /// Whether the actual request may send `header`. The wildcard doesn't
+    /// cover `Authorization`, which has to be listed explicitly.
+    pub fn allows_header(&self, header: &str) -> bool {
+        let header = header.to_ascii_lowercase();
+        self.allowed_headers
+            .iter()
+            .any(|allowed| *allowed == header || (allowed == "*" && header != "authorization"))
+    }
+
+    pub fn allows_method(&self, method: &str) -> bool {
+        self.allowed_methods
+            .iter()
+            .any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(method))
+    }
+
+    /// Whether the bearer token may be sent with the actual request
+    pub fn allow_authorization_header(&self) -> bool {
+        self.allows_header("authorization")
+    }
 }
 
 #[derive(Deserialize)]
@@ -124,3 +177,42 @@ pub struct APIError {
     pub code: String,
     pub message: String,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageResponse {
+    pub artifact_bytes_used: u64,
+    pub artifact_bytes_limit: Option<u64>,
+    pub runs_this_period: u64,
+    pub runs_limit: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpaceStatsResponse {
+    pub run_count: u64,
+    /// Average run duration in milliseconds
+    pub average_duration: u64,
+    /// Fraction of tasks that were cache hits, from 0 to 1
+    pub cache_hit_rate: f64,
+    /// Total time saved by cache hits in milliseconds
+    pub time_saved: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CapabilitiesResponse {
+    /// The newest API version the server implements
+    pub api_version: u32,
+    /// The oldest client API version the server still supports
+    pub min_client_api_version: u32,
+    /// Optional features the server supports
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerTimeResponse {
+    /// Millisecond timestamp
+    pub time: i64,
+}